@@ -0,0 +1,218 @@
+use super::{
+    recover, rules::is_rule_ahead, ASTNode, CSSToken, Declaration, Entry, Interpolation,
+    ParseError, Rule, ToStringSettings,
+};
+use source_map::{Span, ToString};
+use tokenizer_lib::{Token, TokenReader};
+
+/// A [at-rule](https://developer.mozilla.org/en-US/docs/Web/CSS/At-rule) e.g. `@font-face`, `@import`
+#[derive(Debug)]
+pub struct AtRule {
+    /// Name without the `@`
+    pub name: String,
+    /// Everything between the name and the block or `;`. Whitespace is collapsed to single spaces
+    pub prelude: String,
+    /// Prelude containing SCSS interpolation e.g. `(#{$property}: sticky)`. `prelude` holds it as
+    /// written until [`compile`](crate::compile) replaces it with the evaluated prelude
+    pub interpolated_prelude: Option<Interpolation>,
+    /// `None` for statement at-rules e.g. `@import "reset.css";`
+    pub block: Option<AtRuleBlock>,
+    pub position: Option<Span>,
+}
+
+/// Contents of a at-rule block. Descriptors (`@font-face`) are declarations, rule lists (`@supports`)
+/// are entries
+#[derive(Debug, Default)]
+pub struct AtRuleBlock {
+    pub declarations: Vec<Declaration>,
+    pub entries: Vec<Entry>,
+}
+
+impl ASTNode for AtRule {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        Self::from_reader_with_recovery(reader, None)
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push('@');
+        buf.push_str(&self.name);
+        if !self.prelude.is_empty() {
+            buf.push(' ');
+            buf.push_str(&self.prelude);
+        }
+        if let Some(block) = &self.block {
+            if !settings.minify {
+                buf.push(' ');
+            }
+            block.to_string_from_buffer(buf, settings, depth);
+        } else {
+            buf.push(';');
+        }
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl AtRule {
+    /// If `errors` is `Some`, invalid items in the block are skipped and their errors collected
+    pub(crate) fn from_reader_with_recovery(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self, ParseError> {
+        let (name, start_span) = match reader.next().unwrap() {
+            Token(CSSToken::AtKeyword(name), position) => (name, position),
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected at-rule found '{:?}'", token),
+                    position,
+                })
+            }
+        };
+
+        let interpolation = Interpolation::from_reader_until(reader, |token| {
+            matches!(
+                token,
+                CSSToken::OpenCurly | CSSToken::SemiColon | CSSToken::CloseCurly
+            )
+        })?;
+        let prelude = interpolation.to_source_string();
+        let last_span = if interpolation.parts.is_empty() {
+            start_span.clone()
+        } else {
+            interpolation.position.clone()
+        };
+        let interpolated_prelude = Some(interpolation).filter(Interpolation::has_expressions);
+        match reader.peek().unwrap() {
+            Token(CSSToken::OpenCurly, _) => {}
+            Token(CSSToken::SemiColon, _) => {
+                let end_span = reader.next().unwrap().1;
+                return Ok(Self {
+                    name,
+                    prelude,
+                    interpolated_prelude,
+                    block: None,
+                    position: Some(start_span.union(&end_span)),
+                });
+            }
+            _ => {
+                return Ok(Self {
+                    name,
+                    prelude,
+                    interpolated_prelude,
+                    block: None,
+                    position: Some(start_span.union(&last_span)),
+                });
+            }
+        }
+
+        let (block, end_span) = AtRuleBlock::from_reader(reader, errors)?;
+        Ok(Self {
+            name,
+            prelude,
+            interpolated_prelude,
+            block: Some(block),
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+}
+
+impl AtRuleBlock {
+    /// Parses from `{` to `}`. Returns the position of the closing `}`. If `errors` is `Some`,
+    /// invalid items are skipped and their errors collected rather than returned
+    pub(crate) fn from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        mut errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<(Self, Span), ParseError> {
+        reader.expect_next(CSSToken::OpenCurly)?;
+        let mut block = AtRuleBlock::default();
+        loop {
+            match reader.peek().unwrap() {
+                Token(CSSToken::CloseCurly, _) | Token(CSSToken::EOS, _) => break,
+                Token(CSSToken::SemiColon, _) => {
+                    reader.next();
+                    continue;
+                }
+                Token(CSSToken::Comment(_), _)
+                | Token(CSSToken::AtKeyword(_), _)
+                | Token(CSSToken::Variable(_), _) => {
+                    let entry = match errors.as_deref_mut() {
+                        Some(errors) => recover(reader, errors, |reader, errors| {
+                            Entry::from_reader(reader, Some(errors))
+                        }),
+                        None => Some(Entry::from_reader(reader, None)?),
+                    };
+                    block.entries.extend(entry);
+                    continue;
+                }
+                _ => {}
+            }
+            if is_rule_ahead(reader) {
+                let rule = match errors.as_deref_mut() {
+                    Some(errors) => recover(reader, errors, |reader, errors| {
+                        Rule::from_reader_with_recovery(reader, Some(errors), false)
+                    }),
+                    None => Some(Rule::from_reader_with_recovery(reader, None, false)?),
+                };
+                block.entries.extend(rule.map(Entry::from));
+            } else {
+                let declaration = match errors.as_deref_mut() {
+                    Some(errors) => {
+                        recover(reader, errors, |reader, _| Declaration::from_reader(reader))
+                    }
+                    None => Some(Declaration::from_reader(reader)?),
+                };
+                block.declarations.extend(declaration);
+            }
+        }
+        let end_span = reader.expect_next(CSSToken::CloseCurly)?;
+        Ok((block, end_span))
+    }
+
+    /// Prints from `{` to `}`
+    pub(crate) fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        buf.push('{');
+        for declaration in self.declarations.iter() {
+            if !settings.minify {
+                buf.push_new_line();
+                buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
+            }
+            declaration.to_string_from_buffer(buf, settings, depth);
+            buf.push(';');
+        }
+        for (idx, entry) in self.entries.iter().enumerate() {
+            if settings.minify && matches!(entry, Entry::Comment(_)) {
+                continue;
+            }
+            if !settings.minify {
+                // Blank line between rules, like the top level of a stylesheet
+                if idx > 0 || !self.declarations.is_empty() {
+                    buf.push_new_line();
+                }
+                buf.push_new_line();
+                buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
+            }
+            entry.to_string_from_buffer(buf, settings, depth + 1);
+        }
+        let is_empty = self.declarations.is_empty() && self.entries.is_empty();
+        if !settings.minify && !is_empty {
+            buf.push_new_line();
+            buf.push_str(&settings.indent_with.repeat(depth as usize));
+        }
+        buf.push('}');
+    }
+}
@@ -0,0 +1,467 @@
+use super::{BinaryOperator, CSSValue};
+
+/// A colour in the sRGB space. Used by the SCSS colour functions e.g. `darken`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Color {
+    /// 0 to 255
+    pub red: f64,
+    /// 0 to 255
+    pub green: f64,
+    /// 0 to 255
+    pub blue: f64,
+    /// 0 to 1
+    pub alpha: f64,
+}
+
+/// [Named colours](https://developer.mozilla.org/en-US/docs/Web/CSS/named-color) and their hex
+/// values
+const NAMED_COLORS: [(&str, &str); 148] = [
+    ("aliceblue", "f0f8ff"),
+    ("antiquewhite", "faebd7"),
+    ("aqua", "00ffff"),
+    ("aquamarine", "7fffd4"),
+    ("azure", "f0ffff"),
+    ("beige", "f5f5dc"),
+    ("bisque", "ffe4c4"),
+    ("black", "000000"),
+    ("blanchedalmond", "ffebcd"),
+    ("blue", "0000ff"),
+    ("blueviolet", "8a2be2"),
+    ("brown", "a52a2a"),
+    ("burlywood", "deb887"),
+    ("cadetblue", "5f9ea0"),
+    ("chartreuse", "7fff00"),
+    ("chocolate", "d2691e"),
+    ("coral", "ff7f50"),
+    ("cornflowerblue", "6495ed"),
+    ("cornsilk", "fff8dc"),
+    ("crimson", "dc143c"),
+    ("cyan", "00ffff"),
+    ("darkblue", "00008b"),
+    ("darkcyan", "008b8b"),
+    ("darkgoldenrod", "b8860b"),
+    ("darkgray", "a9a9a9"),
+    ("darkgreen", "006400"),
+    ("darkgrey", "a9a9a9"),
+    ("darkkhaki", "bdb76b"),
+    ("darkmagenta", "8b008b"),
+    ("darkolivegreen", "556b2f"),
+    ("darkorange", "ff8c00"),
+    ("darkorchid", "9932cc"),
+    ("darkred", "8b0000"),
+    ("darksalmon", "e9967a"),
+    ("darkseagreen", "8fbc8f"),
+    ("darkslateblue", "483d8b"),
+    ("darkslategray", "2f4f4f"),
+    ("darkslategrey", "2f4f4f"),
+    ("darkturquoise", "00ced1"),
+    ("darkviolet", "9400d3"),
+    ("deeppink", "ff1493"),
+    ("deepskyblue", "00bfff"),
+    ("dimgray", "696969"),
+    ("dimgrey", "696969"),
+    ("dodgerblue", "1e90ff"),
+    ("firebrick", "b22222"),
+    ("floralwhite", "fffaf0"),
+    ("forestgreen", "228b22"),
+    ("fuchsia", "ff00ff"),
+    ("gainsboro", "dcdcdc"),
+    ("ghostwhite", "f8f8ff"),
+    ("gold", "ffd700"),
+    ("goldenrod", "daa520"),
+    ("gray", "808080"),
+    ("green", "008000"),
+    ("greenyellow", "adff2f"),
+    ("grey", "808080"),
+    ("honeydew", "f0fff0"),
+    ("hotpink", "ff69b4"),
+    ("indianred", "cd5c5c"),
+    ("indigo", "4b0082"),
+    ("ivory", "fffff0"),
+    ("khaki", "f0e68c"),
+    ("lavender", "e6e6fa"),
+    ("lavenderblush", "fff0f5"),
+    ("lawngreen", "7cfc00"),
+    ("lemonchiffon", "fffacd"),
+    ("lightblue", "add8e6"),
+    ("lightcoral", "f08080"),
+    ("lightcyan", "e0ffff"),
+    ("lightgoldenrodyellow", "fafad2"),
+    ("lightgray", "d3d3d3"),
+    ("lightgreen", "90ee90"),
+    ("lightgrey", "d3d3d3"),
+    ("lightpink", "ffb6c1"),
+    ("lightsalmon", "ffa07a"),
+    ("lightseagreen", "20b2aa"),
+    ("lightskyblue", "87cefa"),
+    ("lightslategray", "778899"),
+    ("lightslategrey", "778899"),
+    ("lightsteelblue", "b0c4de"),
+    ("lightyellow", "ffffe0"),
+    ("lime", "00ff00"),
+    ("limegreen", "32cd32"),
+    ("linen", "faf0e6"),
+    ("magenta", "ff00ff"),
+    ("maroon", "800000"),
+    ("mediumaquamarine", "66cdaa"),
+    ("mediumblue", "0000cd"),
+    ("mediumorchid", "ba55d3"),
+    ("mediumpurple", "9370db"),
+    ("mediumseagreen", "3cb371"),
+    ("mediumslateblue", "7b68ee"),
+    ("mediumspringgreen", "00fa9a"),
+    ("mediumturquoise", "48d1cc"),
+    ("mediumvioletred", "c71585"),
+    ("midnightblue", "191970"),
+    ("mintcream", "f5fffa"),
+    ("mistyrose", "ffe4e1"),
+    ("moccasin", "ffe4b5"),
+    ("navajowhite", "ffdead"),
+    ("navy", "000080"),
+    ("oldlace", "fdf5e6"),
+    ("olive", "808000"),
+    ("olivedrab", "6b8e23"),
+    ("orange", "ffa500"),
+    ("orangered", "ff4500"),
+    ("orchid", "da70d6"),
+    ("palegoldenrod", "eee8aa"),
+    ("palegreen", "98fb98"),
+    ("paleturquoise", "afeeee"),
+    ("palevioletred", "db7093"),
+    ("papayawhip", "ffefd5"),
+    ("peachpuff", "ffdab9"),
+    ("peru", "cd853f"),
+    ("pink", "ffc0cb"),
+    ("plum", "dda0dd"),
+    ("powderblue", "b0e0e6"),
+    ("purple", "800080"),
+    ("rebeccapurple", "663399"),
+    ("red", "ff0000"),
+    ("rosybrown", "bc8f8f"),
+    ("royalblue", "4169e1"),
+    ("saddlebrown", "8b4513"),
+    ("salmon", "fa8072"),
+    ("sandybrown", "f4a460"),
+    ("seagreen", "2e8b57"),
+    ("seashell", "fff5ee"),
+    ("sienna", "a0522d"),
+    ("silver", "c0c0c0"),
+    ("skyblue", "87ceeb"),
+    ("slateblue", "6a5acd"),
+    ("slategray", "708090"),
+    ("slategrey", "708090"),
+    ("snow", "fffafa"),
+    ("springgreen", "00ff7f"),
+    ("steelblue", "4682b4"),
+    ("tan", "d2b48c"),
+    ("teal", "008080"),
+    ("thistle", "d8bfd8"),
+    ("tomato", "ff6347"),
+    ("turquoise", "40e0d0"),
+    ("violet", "ee82ee"),
+    ("wheat", "f5deb3"),
+    ("white", "ffffff"),
+    ("whitesmoke", "f5f5f5"),
+    ("yellow", "ffff00"),
+    ("yellowgreen", "9acd32"),
+];
+
+impl Color {
+    pub fn new(red: f64, green: f64, blue: f64, alpha: f64) -> Self {
+        Self {
+            red: red.clamp(0.0, 255.0),
+            green: green.clamp(0.0, 255.0),
+            blue: blue.clamp(0.0, 255.0),
+            alpha: alpha.clamp(0.0, 1.0),
+        }
+    }
+
+    /// From 3, 4, 6 or 8 hex digits without the `#`
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|chr| chr.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits: Vec<u8> = match hex.len() {
+            // Short form digits are repeated e.g. `#f00` is `#ff0000`
+            3 | 4 => hex.bytes().flat_map(|digit| [digit, digit]).collect(),
+            6 | 8 => hex.bytes().collect(),
+            _ => return None,
+        };
+        let channel = |index: usize| {
+            let pair = std::str::from_utf8(&digits[index * 2..index * 2 + 2]).unwrap();
+            u8::from_str_radix(pair, 16).unwrap() as f64
+        };
+        let alpha = if digits.len() == 8 {
+            channel(3) / 255.0
+        } else {
+            1.0
+        };
+        Some(Self::new(channel(0), channel(1), channel(2), alpha))
+    }
+
+    /// From a named colour e.g. `rebeccapurple` or `transparent`
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("transparent") {
+            return Some(Self::new(0.0, 0.0, 0.0, 0.0));
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(named, _)| named.eq_ignore_ascii_case(name))
+            .and_then(|(_, hex)| Self::from_hex(hex))
+    }
+
+    /// From a hue in degrees and saturation and lightness from 0 to 100
+    pub fn from_hsl(hue: f64, saturation: f64, lightness: f64, alpha: f64) -> Self {
+        let hue = hue.rem_euclid(360.0) / 360.0;
+        let saturation = saturation.clamp(0.0, 100.0) / 100.0;
+        let lightness = lightness.clamp(0.0, 100.0) / 100.0;
+        let m2 = if lightness <= 0.5 {
+            lightness * (saturation + 1.0)
+        } else {
+            lightness + saturation - lightness * saturation
+        };
+        let m1 = lightness * 2.0 - m2;
+        let channel = |hue: f64| {
+            let hue = hue.rem_euclid(1.0);
+            let value = if hue * 6.0 < 1.0 {
+                m1 + (m2 - m1) * hue * 6.0
+            } else if hue * 2.0 < 1.0 {
+                m2
+            } else if hue * 3.0 < 2.0 {
+                m1 + (m2 - m1) * (2.0 / 3.0 - hue) * 6.0
+            } else {
+                m1
+            };
+            value * 255.0
+        };
+        Self::new(
+            channel(hue + 1.0 / 3.0),
+            channel(hue),
+            channel(hue - 1.0 / 3.0),
+            alpha,
+        )
+    }
+
+    /// Hue in degrees and saturation and lightness from 0 to 100
+    pub fn to_hsl(self) -> (f64, f64, f64) {
+        let (red, green, blue) = (self.red / 255.0, self.green / 255.0, self.blue / 255.0);
+        let max = red.max(green).max(blue);
+        let min = red.min(green).min(blue);
+        let delta = max - min;
+        let lightness = (max + min) / 2.0;
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == red {
+            60.0 * ((green - blue) / delta)
+        } else if max == green {
+            60.0 * ((blue - red) / delta) + 120.0
+        } else {
+            60.0 * ((red - green) / delta) + 240.0
+        };
+        let saturation = if delta == 0.0 {
+            0.0
+        } else if lightness < 0.5 {
+            delta / (max + min)
+        } else {
+            delta / (2.0 - max - min)
+        };
+        (hue.rem_euclid(360.0), saturation * 100.0, lightness * 100.0)
+    }
+
+    /// From a hex colour, named colour or a `rgb()`, `rgba()`, `hsl()` or `hsla()` call with
+    /// literal arguments
+    pub fn from_value(value: &CSSValue) -> Option<Self> {
+        match value {
+            CSSValue::Color(hex) => Self::from_hex(hex),
+            CSSValue::Keyword(name) => Self::from_name(name),
+            CSSValue::Function(name, arguments) => {
+                let name = name.to_ascii_lowercase();
+                let channels = color_function_channels(arguments)?;
+                let (first, second, third) = (channels[0], channels[1], channels[2]);
+                let alpha = match channels.get(3) {
+                    Some(&(alpha, "%")) => alpha / 100.0,
+                    Some(&(alpha, "")) => alpha,
+                    Some(_) => return None,
+                    None => 1.0,
+                };
+                match name.as_str() {
+                    "rgb" | "rgba" => {
+                        let channel = |(value, unit): (f64, &str)| match unit {
+                            "" => Some(value),
+                            "%" => Some(value * 255.0 / 100.0),
+                            _ => None,
+                        };
+                        Some(Self::new(
+                            channel(first)?,
+                            channel(second)?,
+                            channel(third)?,
+                            alpha,
+                        ))
+                    }
+                    "hsl" | "hsla" => {
+                        let hue = match first.1 {
+                            "" | "deg" => first.0,
+                            "turn" => first.0 * 360.0,
+                            "rad" => first.0.to_degrees(),
+                            _ => return None,
+                        };
+                        Some(Self::from_hsl(hue, second.0, third.0, alpha))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The shortest representation e.g. `red`, `#fff`, `#1a2b3c`, `#0003` or `rgba(0,0,0,.5)`.
+    /// Opacity is written in hex when it is a whole number out of 255, so that it is kept exactly
+    pub fn to_value(self) -> CSSValue {
+        // Rounds off floating point error first so that e.g. `127.49999999999997` becomes `128`
+        let [red, green, blue, alpha] = [self.red, self.green, self.blue, self.alpha * 255.0]
+            .map(|channel| (channel * 1e10).round() / 1e10);
+        let [red, green, blue] = [red, green, blue].map(f64::round);
+        if alpha < 255.0 {
+            if alpha == 0.0 && red == 0.0 && green == 0.0 && blue == 0.0 {
+                return CSSValue::Keyword("transparent".to_owned());
+            }
+            if alpha.fract() != 0.0 {
+                let mut arguments: Vec<_> = [red, green, blue, self.alpha]
+                    .iter()
+                    .map(|&value| CSSValue::from_number(value, ""))
+                    .collect();
+                // `.5` rather than `0.5`
+                if let Some(CSSValue::Number(alpha)) = arguments.last_mut() {
+                    if let Some(without_zero) = alpha.0.strip_prefix('0') {
+                        alpha.0 = without_zero.to_owned();
+                    }
+                }
+                return CSSValue::Function("rgba".to_owned(), arguments);
+            }
+        }
+        let mut hex = format!("{:02x}{:02x}{:02x}", red as u8, green as u8, blue as u8);
+        if alpha < 255.0 {
+            hex.push_str(&format!("{:02x}", alpha as u8));
+        }
+        let bytes = hex.as_bytes();
+        if bytes.chunks(2).all(|pair| pair[0] == pair[1]) {
+            hex = bytes.iter().step_by(2).map(|&byte| byte as char).collect();
+        }
+        if alpha < 255.0 {
+            return CSSValue::Color(hex);
+        }
+        let name = NAMED_COLORS
+            .iter()
+            .filter(|(_, named_hex)| Self::from_hex(named_hex) == Self::from_hex(&hex))
+            .map(|(name, _)| *name)
+            .min_by_key(|name| name.len());
+        match name {
+            // The `#` makes hex one longer
+            Some(name) if name.len() <= hex.len() => CSSValue::Keyword(name.to_owned()),
+            _ => CSSValue::Color(hex),
+        }
+    }
+
+    /// Mixes with `other`. `weight` from 0 to 1 is the proportion of this colour. Opacity is
+    /// taken into account as in Sass
+    pub fn mix(self, other: Self, weight: f64) -> Self {
+        let normalized = weight * 2.0 - 1.0;
+        let alpha_difference = self.alpha - other.alpha;
+        let combined = if normalized * alpha_difference == -1.0 {
+            normalized
+        } else {
+            (normalized + alpha_difference) / (1.0 + normalized * alpha_difference)
+        };
+        let weight1 = (combined + 1.0) / 2.0;
+        let weight2 = 1.0 - weight1;
+        Self::new(
+            self.red * weight1 + other.red * weight2,
+            self.green * weight1 + other.green * weight2,
+            self.blue * weight1 + other.blue * weight2,
+            self.alpha * weight + other.alpha * (1.0 - weight),
+        )
+    }
+}
+
+/// Numbers and units of the arguments of a colour function. Supports comma separated, space
+/// separated and `/` alpha forms e.g. `rgb(0 0 0 / 50%)`
+fn color_function_channels(arguments: &[CSSValue]) -> Option<Vec<(f64, &str)>> {
+    let mut values: Vec<&CSSValue> = Vec::new();
+    for argument in arguments {
+        match argument {
+            CSSValue::List(items) => values.extend(items),
+            value => values.push(value),
+        }
+    }
+    // Split the last channel and the alpha of `a b c / alpha`
+    if let [.., CSSValue::BinaryOperation {
+        lhs,
+        operator: BinaryOperator::Divide,
+        rhs,
+        ..
+    }] = values.as_slice()
+    {
+        let (lhs, rhs) = (&**lhs, &**rhs);
+        values.pop();
+        values.push(lhs);
+        values.push(rhs);
+    }
+    if values.len() != 3 && values.len() != 4 {
+        return None;
+    }
+    values.into_iter().map(CSSValue::as_number).collect()
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::*;
+    use crate::{ASTNode, ToStringSettings};
+    use source_map::SourceId;
+
+    fn parse(source: &str) -> Color {
+        let value = CSSValue::from_string(source.to_owned(), SourceId::null(), None).unwrap();
+        Color::from_value(&value).unwrap()
+    }
+
+    fn shortest(color: Color) -> String {
+        color.to_value().to_string(&ToStringSettings::minified())
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse("#ff0000"), Color::new(255.0, 0.0, 0.0, 1.0));
+        assert_eq!(parse("#f008"), Color::new(255.0, 0.0, 0.0, 136.0 / 255.0));
+        assert_eq!(parse("RebeccaPurple"), parse("#663399"));
+        assert_eq!(parse("rgb(255, 0, 0)"), parse("red"));
+        assert_eq!(
+            parse("rgb(100% 0% 0% / 50%)"),
+            parse("rgba(255, 0, 0, 0.5)")
+        );
+        assert_eq!(parse("hsl(120, 100%, 50%)"), parse("lime"));
+        assert_eq!(shortest(parse("hsl(0.5turn 100% 25%)")), "teal");
+        assert!(Color::from_value(&CSSValue::Keyword("auto".to_owned())).is_none());
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        let color = parse("#3c78b4");
+        let (hue, saturation, lightness) = color.to_hsl();
+        assert_eq!(
+            shortest(Color::from_hsl(hue, saturation, lightness, 1.0)),
+            "#3c78b4"
+        );
+    }
+
+    #[test]
+    fn shortest_representation() {
+        assert_eq!(shortest(parse("#ff0000")), "red");
+        assert_eq!(shortest(parse("#ffffff")), "#fff");
+        assert_eq!(shortest(parse("#c0c0c0")), "silver");
+        assert_eq!(shortest(parse("#1a2b3c")), "#1a2b3c");
+        assert_eq!(shortest(parse("rgba(0, 0, 0, 0.5)")), "rgba(0,0,0,.5)");
+        assert_eq!(shortest(parse("rgba(51, 102, 204, 0.2)")), "#36c3");
+        assert_eq!(shortest(parse("#3366cc80")), "#3366cc80");
+        assert_eq!(shortest(parse("rgba(1, 2, 3, 0.6)")), "#01020399");
+        assert_eq!(shortest(parse("rgba(0, 0, 0, 0)")), "transparent");
+    }
+}
@@ -0,0 +1,2690 @@
+use super::{
+    lexer, modules,
+    selectors::{relative_selector_list_from_reader, selector_list_from_reader},
+    ASTNode, Argument, AtRuleBlock, BinaryOperator, BlockItem, CSSToken, CSSValue, Declaration,
+    EachStatement, Entry, Extend, FileLoader, FileSystemLoader, ForStatement, ForwardFilter,
+    ForwardStatement, FunctionDefinition, FunctionRegistry, IfStatement, ImportStatement, Include,
+    Interpolation, InterpolationPart, MediaCondition, MediaFeature, MediaFeatureValue, MediaQuery,
+    MediaQueryList, MediaRule, MixinDefinition, Parameter, ParseError, ReturnStatement, Rule,
+    ScssStatement, Selector, StyleSheet, Syntax, ToStringSettings, UnaryOperator, UseStatement,
+    VariableDeclaration, WhileStatement,
+};
+use source_map::{SourceId, Span};
+use std::{
+    collections::HashMap,
+    mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use tokenizer_lib::{BufferedTokenQueue, TokenReader};
+
+/// Options for [`compile_with_options`]
+pub struct CompileOptions {
+    /// The most iterations a `@for` or `@while` loop can run before compilation errors. Stops a
+    /// `@while` whose condition never becomes false from hanging
+    pub max_iterations: usize,
+    /// Directories searched for `@use`, `@forward` and `@import` urls which are not found
+    /// relative to the stylesheet containing them
+    pub load_paths: Vec<PathBuf>,
+    /// Reads the files of `@use`, `@forward` and `@import`
+    pub file_loader: Box<dyn FileLoader>,
+    /// Functions implemented in Rust. Register on the default to add functions alongside the
+    /// built-in ones
+    pub functions: FunctionRegistry,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            max_iterations: 100_000,
+            load_paths: Vec::new(),
+            file_loader: Box::new(FileSystemLoader),
+            functions: FunctionRegistry::default(),
+        }
+    }
+}
+
+/// Compiles SCSS in the stylesheet down to CSS, loading modules, evaluating variables, functions
+/// and control flow, expanding mixins, applying `@extend`s and removing SCSS statements and
+/// placeholder selectors. Should be run before [`raise_nested_rules`](crate::raise_nested_rules).
+/// Stylesheets with [`Syntax::Css`] are left as written
+pub fn compile(stylesheet: &mut StyleSheet) -> Result<(), ParseError> {
+    compile_with_options(stylesheet, &CompileOptions::default())
+}
+
+/// [`compile`] with options
+pub fn compile_with_options(
+    stylesheet: &mut StyleSheet,
+    options: &CompileOptions,
+) -> Result<(), ParseError> {
+    if stylesheet.syntax == Syntax::Css {
+        return Ok(());
+    }
+    let mut compiler = Compiler {
+        options,
+        syntax: stylesheet.syntax,
+        scopes: vec![Scope::default()],
+        content_blocks: Vec::new(),
+        rule_selectors: Vec::new(),
+        extensions: Vec::new(),
+        item_position: None,
+        call_depth: 0,
+        media_queries: Vec::new(),
+        modules: Vec::new(),
+        module_paths: HashMap::new(),
+        loading: Vec::new(),
+    };
+    // The stylesheet's own file, so that a module loading it is reported as a loop
+    let file = stylesheet.entries.iter().find_map(|entry| {
+        let position = match entry {
+            Entry::Rule(rule) => rule.get_position(),
+            Entry::AtRule(at_rule) => at_rule.get_position(),
+            Entry::MediaRule(media_rule) => media_rule.get_position(),
+            Entry::KeyframesRule(keyframes_rule) => keyframes_rule.get_position(),
+            Entry::ScssStatement(statement) => statement.get_position(),
+            Entry::Comment(_) => None,
+        };
+        position?.source_id.get_file()
+    });
+    if let Some((path, _)) = file {
+        compiler.loading.push(modules::normalize(&path));
+    }
+    stylesheet.entries = compiler.compile_entries(mem::take(&mut stylesheet.entries))?;
+    compiler.extend_entries(&mut stylesheet.entries, &mut Vec::new())?;
+    if let Some(extension) = compiler
+        .extensions
+        .iter()
+        .find(|extension| !extension.matched && !extension.optional)
+    {
+        let target = extension.target.to_string(&ToStringSettings::minified());
+        return Err(ParseError {
+            reason: format!(
+                "The target selector {} was not found, use `@extend {} !optional` to avoid this error",
+                target, target
+            ),
+            position: extension.position.clone(),
+        });
+    }
+    Ok(())
+}
+
+struct Compiler<'a> {
+    options: &'a CompileOptions,
+    /// Syntax of the stylesheet, for naming variables in errors
+    syntax: Syntax,
+    /// The first is the global scope, the last is the innermost block
+    scopes: Vec<Scope>,
+    /// Content blocks of the `@include`s being expanded, innermost last
+    content_blocks: Vec<ContentBlock>,
+    /// Selectors of the rules being compiled, with parent selectors nested in
+    rule_selectors: Vec<Vec<Selector>>,
+    extensions: Vec<Extension>,
+    /// Position of the declaration or statement being compiled. For errors in values, which do
+    /// not hold positions
+    item_position: Option<Span>,
+    /// Number of `@function` calls and `@include`s being evaluated
+    call_depth: usize,
+    /// Queries of the `@media` blocks being compiled, outermost first
+    media_queries: Vec<String>,
+    /// Global scopes of the modules loaded by `@use` and `@forward`
+    modules: Vec<Scope>,
+    /// Indexes into `modules` by path
+    module_paths: HashMap<PathBuf, usize>,
+    /// Paths of the files being compiled, to catch files which load themselves
+    loading: Vec<PathBuf>,
+}
+
+/// Stops recursive `@function`s and mixins from overflowing the stack
+const MAX_CALL_DEPTH: usize = 50;
+
+/// Modules loaded with `@use "sass:<name>"`. Their functions are in the
+/// [`FunctionRegistry`](crate::FunctionRegistry) e.g. `math.div`
+const BUILT_IN_MODULES: [&str; 7] = ["color", "list", "map", "math", "meta", "selector", "string"];
+
+#[derive(Default)]
+struct Scope {
+    /// Scope of a `@if`, `@each`, `@for` or `@while` body. Assignments in these can update globals
+    flow_control: bool,
+    variables: HashMap<String, CSSValue>,
+    /// Mixins with the number of scopes visible where they were defined. Mixin bodies only see
+    /// those scopes rather than the scopes of the `@include`
+    mixins: HashMap<String, (Rc<MixinDefinition>, usize)>,
+    /// Functions with the number of scopes visible where they were defined, as with `mixins`
+    functions: HashMap<String, (Rc<FunctionDefinition>, usize)>,
+    /// Indexes of modules from `@use` by namespace, `None` for `as *`. Only in global scopes
+    uses: Vec<(Option<String>, usize)>,
+    /// Names of built-in modules from `@use "sass:..."` by namespace, `None` for `as *`. Only in
+    /// global scopes
+    built_in_uses: Vec<(Option<String>, &'static str)>,
+    /// Indexes of modules from `@forward`. Only in global scopes
+    forwards: Vec<(usize, Option<ForwardFilter>)>,
+}
+
+/// Where a mixin or function was defined
+#[derive(Clone, Copy)]
+enum Definition {
+    /// In the current stylesheet, seeing this many of the current scopes
+    Local(usize),
+    /// In the global scope of the module at this index
+    Module(usize),
+}
+
+/// The block passed to an `@include`, evaluated in the scopes of the `@include`
+struct ContentBlock {
+    items: Option<Rc<Vec<BlockItem>>>,
+    /// Scopes of the `@include` which are above the scopes shared with the mixin
+    caller_scopes: Vec<Scope>,
+    shared_scopes: usize,
+}
+
+/// A `@extend` target with the selectors of the rule the `@extend` is in
+struct Extension {
+    target: Selector,
+    extenders: Vec<Selector>,
+    optional: bool,
+    position: Span,
+    /// Queries of the `@media` blocks the `@extend` is in. It may only extend rules in the same
+    /// blocks
+    media_queries: Vec<String>,
+    /// Whether any selector matched the target
+    matched: bool,
+}
+
+/// Where compiled block items are added
+enum Output<'a> {
+    Rule(&'a mut Rule),
+    /// The stylesheet or an at-rule block, where declarations are not allowed
+    Entries(&'a mut Vec<Entry>),
+    /// The body of a `@function`, which only outputs the value of its `@return`
+    Function(&'a mut Option<CSSValue>),
+}
+
+impl Output<'_> {
+    /// Whether a `@return` has been reached, after which the rest of the function body is skipped
+    fn has_returned(&self) -> bool {
+        matches!(self, Output::Function(Some(_)))
+    }
+}
+
+impl Compiler<'_> {
+    fn compile_entries(&mut self, entries: Vec<Entry>) -> Result<Vec<Entry>, ParseError> {
+        let mut compiled_entries = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match entry {
+                Entry::Rule(rule) => compiled_entries.push(self.compile_rule(rule)?.into()),
+                Entry::AtRule(mut at_rule) => {
+                    if let Some(interpolation) = at_rule.interpolated_prelude.take() {
+                        let position = interpolation.position.clone();
+                        at_rule.prelude = self.interpolate(interpolation)?;
+                        // `@media #{$query}` is parsed once its queries are known
+                        if at_rule.name.eq_ignore_ascii_case("media") {
+                            if let Some(block) = at_rule.block {
+                                let media_rule = MediaRule {
+                                    queries: reparse(
+                                        at_rule.prelude,
+                                        position,
+                                        MediaQueryList::from_reader,
+                                    )?,
+                                    block,
+                                    position: at_rule.position,
+                                };
+                                compiled_entries
+                                    .extend(self.compile_entries(vec![media_rule.into()])?);
+                                continue;
+                            }
+                        }
+                    }
+                    if let Some(block) = at_rule.block.take() {
+                        at_rule.block = Some(self.compile_at_rule_block(block)?);
+                    }
+                    compiled_entries.push(at_rule.into());
+                }
+                Entry::MediaRule(mut media_rule) => {
+                    self.item_position = media_rule.position.clone();
+                    self.evaluate_media_query_list(&mut media_rule.queries)?;
+                    self.media_queries
+                        .push(media_rule.queries.to_string(&ToStringSettings::minified()));
+                    let block = self.compile_at_rule_block(mem::take(&mut media_rule.block));
+                    self.media_queries.pop();
+                    media_rule.block = block?;
+                    compiled_entries.push(media_rule.into());
+                }
+                Entry::KeyframesRule(mut keyframes_rule) => {
+                    for keyframe in keyframes_rule.keyframes.iter_mut() {
+                        keyframe.declarations = mem::take(&mut keyframe.declarations)
+                            .into_iter()
+                            .filter_map(|declaration| {
+                                self.compile_declaration(declaration).transpose()
+                            })
+                            .collect::<Result<_, _>>()?;
+                    }
+                    compiled_entries.push(keyframes_rule.into());
+                }
+                Entry::ScssStatement(statement) => {
+                    self.execute_statement(statement, &mut Output::Entries(&mut compiled_entries))?
+                }
+                entry @ Entry::Comment(_) => compiled_entries.push(entry),
+            }
+        }
+        Ok(compiled_entries)
+    }
+
+    /// Compiles the block in a new scope
+    fn compile_at_rule_block(&mut self, block: AtRuleBlock) -> Result<AtRuleBlock, ParseError> {
+        let AtRuleBlock {
+            declarations,
+            entries,
+        } = block;
+        self.scopes.push(Scope::default());
+        let block = declarations
+            .into_iter()
+            .filter_map(|declaration| self.compile_declaration(declaration).transpose())
+            .collect::<Result<_, _>>()
+            .and_then(|declarations| {
+                Ok(AtRuleBlock {
+                    declarations,
+                    entries: self.compile_entries(entries)?,
+                })
+            });
+        self.scopes.pop();
+        block
+    }
+
+    /// Compiles the rule in a new scope
+    fn compile_rule(&mut self, mut rule: Rule) -> Result<Rule, ParseError> {
+        if let Some(interpolation) = rule.interpolated_selectors.take() {
+            let position = interpolation.position.clone();
+            let selectors = self.interpolate(interpolation)?;
+            rule.selectors = if self.rule_selectors.is_empty() {
+                reparse(selectors, position, selector_list_from_reader)?
+            } else {
+                reparse(selectors, position, relative_selector_list_from_reader)?
+            };
+        }
+        let items = match rule.scss_body.take() {
+            Some(items) => items,
+            None => mem::take(&mut rule.declarations)
+                .into_iter()
+                .map(BlockItem::Declaration)
+                .chain(
+                    rule.nested_rules
+                        .take()
+                        .into_iter()
+                        .flatten()
+                        .map(BlockItem::Rule),
+                )
+                .collect(),
+        };
+        let selectors = match self.rule_selectors.last() {
+            Some(parent_selectors) => parent_selectors
+                .iter()
+                .flat_map(|parent| {
+                    rule.selectors
+                        .iter()
+                        .map(move |selector| parent.nest_selector(selector.clone()))
+                })
+                .collect::<Result<_, _>>()?,
+            None => rule.selectors.clone(),
+        };
+        self.rule_selectors.push(selectors);
+        self.scopes.push(Scope::default());
+        let result = self.compile_block_items(items, &mut Output::Rule(&mut rule));
+        self.scopes.pop();
+        self.rule_selectors.pop();
+        result.map(|_| rule)
+    }
+
+    /// Evaluates items into `output`
+    fn compile_block_items(
+        &mut self,
+        items: Vec<BlockItem>,
+        output: &mut Output,
+    ) -> Result<(), ParseError> {
+        for item in items {
+            if output.has_returned() {
+                break;
+            }
+            match item {
+                BlockItem::Declaration(declaration) => match output {
+                    Output::Rule(rule) => {
+                        let declaration = self.compile_declaration(declaration)?;
+                        rule.declarations.extend(declaration);
+                    }
+                    Output::Entries(_) => {
+                        return Err(ParseError {
+                            reason: "Declarations may only be used within style rules".to_owned(),
+                            position: declaration.position.unwrap(),
+                        })
+                    }
+                    Output::Function(_) => {
+                        return Err(function_body_error(declaration.position.unwrap()))
+                    }
+                },
+                BlockItem::Rule(nested_rule) => {
+                    if let Output::Function(_) = output {
+                        return Err(function_body_error(nested_rule.position.unwrap()));
+                    }
+                    let nested_rule = self.compile_rule(nested_rule)?;
+                    match output {
+                        Output::Rule(rule) => rule
+                            .nested_rules
+                            .get_or_insert_with(Vec::new)
+                            .push(nested_rule),
+                        Output::Entries(entries) => entries.push(nested_rule.into()),
+                        Output::Function(_) => unreachable!(),
+                    }
+                }
+                BlockItem::Statement(statement) => self.execute_statement(statement, output)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// `None` if the value is `null`, which removes the declaration e.g. `w: map.get($m, missing)`
+    fn compile_declaration(
+        &mut self,
+        mut declaration: Declaration,
+    ) -> Result<Option<Declaration>, ParseError> {
+        self.item_position = declaration.position.clone();
+        if let Some(interpolation) = declaration.interpolated_name.take() {
+            declaration.name = self.interpolate(interpolation)?;
+        }
+        let value = self.evaluate(declaration.value)?;
+        if let Some(map) = find_map(&value) {
+            return Err(ParseError {
+                reason: format!(
+                    "{} isn't a valid CSS value",
+                    map.to_string(&ToStringSettings::minified())
+                ),
+                position: self.value_position(),
+            });
+        }
+        let value = match without_nulls(value) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        Ok(Some(Declaration {
+            value,
+            ..declaration
+        }))
+    }
+
+    fn execute_statement(
+        &mut self,
+        statement: ScssStatement,
+        output: &mut Output,
+    ) -> Result<(), ParseError> {
+        let previous_position =
+            mem::replace(&mut self.item_position, statement.get_position().cloned());
+        let result = self.run_statement(statement, output);
+        self.item_position = previous_position;
+        result
+    }
+
+    fn run_statement(
+        &mut self,
+        statement: ScssStatement,
+        output: &mut Output,
+    ) -> Result<(), ParseError> {
+        match statement {
+            ScssStatement::Variable(VariableDeclaration {
+                name,
+                value,
+                default,
+                global,
+                position: _,
+            }) => {
+                let value = self.evaluate(value)?;
+                self.assign(name, value, default, global);
+                Ok(())
+            }
+            ScssStatement::Mixin(mixin) => {
+                let visible_scopes = self.scopes.len();
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .mixins
+                    .insert(mixin.name.clone(), (Rc::new(mixin), visible_scopes));
+                Ok(())
+            }
+            ScssStatement::Include(include) => self.include(include, output),
+            ScssStatement::Function(function) => {
+                let visible_scopes = self.scopes.len();
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .functions
+                    .insert(function.name.clone(), (Rc::new(function), visible_scopes));
+                Ok(())
+            }
+            ScssStatement::Use(statement) => {
+                let namespace = statement.namespace();
+                let UseStatement { url, position, .. } = statement;
+                let position = position.unwrap();
+                let entries = self.module_output(output, "@use", &position)?;
+                if let Some(namespace) = &namespace {
+                    if self.namespace_module(namespace).is_some()
+                        || self.built_in_module(namespace).is_some()
+                    {
+                        return Err(ParseError {
+                            reason: format!(
+                                "There is already a module with the namespace '{}'",
+                                namespace
+                            ),
+                            position,
+                        });
+                    }
+                }
+                if let Some(name) = url.strip_prefix("sass:") {
+                    let module = BUILT_IN_MODULES
+                        .iter()
+                        .copied()
+                        .find(|module| *module == name)
+                        .ok_or_else(|| ParseError {
+                            reason: format!("Unknown built-in module '{}'", url),
+                            position,
+                        })?;
+                    self.scopes[0].built_in_uses.push((namespace, module));
+                    return Ok(());
+                }
+                let (module, css) = self.load_module(&url, &position)?;
+                entries.extend(css);
+                self.scopes[0].uses.push((namespace, module));
+                Ok(())
+            }
+            ScssStatement::Forward(ForwardStatement {
+                url,
+                filter,
+                position,
+            }) => {
+                let position = position.unwrap();
+                let entries = self.module_output(output, "@forward", &position)?;
+                let (module, css) = self.load_module(&url, &position)?;
+                entries.extend(css);
+                self.scopes[0].forwards.push((module, filter));
+                Ok(())
+            }
+            ScssStatement::Import(ImportStatement { urls, position }) => {
+                let position = position.unwrap();
+                let entries = match output {
+                    Output::Entries(entries) => entries,
+                    _ => {
+                        return Err(ParseError {
+                            reason: "@import may only be used at the top level of a stylesheet"
+                                .to_owned(),
+                            position,
+                        })
+                    }
+                };
+                for url in urls {
+                    let path = self.resolve_url(&url, &position)?;
+                    let style_sheet = self.parse_file(&path, &position)?;
+                    // Imported stylesheets share the scopes of the importing stylesheet
+                    self.loading.push(path);
+                    let result = self.compile_module_entries(style_sheet);
+                    self.loading.pop();
+                    entries.extend(result?);
+                }
+                Ok(())
+            }
+            ScssStatement::Return(ReturnStatement { value, position }) => match output {
+                Output::Function(returned) => {
+                    **returned = Some(self.evaluate(value)?);
+                    Ok(())
+                }
+                _ => Err(ParseError {
+                    reason: "@return may only be used within a function".to_owned(),
+                    position: position.unwrap(),
+                }),
+            },
+            ScssStatement::If(IfStatement {
+                branches,
+                else_branch,
+                position: _,
+            }) => {
+                for (condition, body) in branches {
+                    if self.evaluate(condition)?.is_truthy() {
+                        return self.compile_flow_control_block(body, Vec::new(), output);
+                    }
+                }
+                match else_branch {
+                    Some(body) => self.compile_flow_control_block(body, Vec::new(), output),
+                    None => Ok(()),
+                }
+            }
+            ScssStatement::Each(EachStatement {
+                variables,
+                list,
+                body,
+                position: _,
+            }) => {
+                for item in list_items(self.evaluate(list)?) {
+                    let bindings = if let [variable] = variables.as_slice() {
+                        vec![(variable.clone(), item)]
+                    } else {
+                        // Destructure the item, variables without a value are `null`
+                        let mut values = list_items(item).into_iter();
+                        variables
+                            .iter()
+                            .map(|variable| {
+                                let value = values.next();
+                                (variable.clone(), value.unwrap_or_else(null))
+                            })
+                            .collect()
+                    };
+                    self.compile_flow_control_block(body.clone(), bindings, output)?;
+                    if output.has_returned() {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            ScssStatement::For(ForStatement {
+                variable,
+                from,
+                to,
+                inclusive,
+                body,
+                position,
+            }) => {
+                let position = position.unwrap();
+                let (from, unit) = self.evaluate_integer(from, &position)?;
+                let (to, _) = self.evaluate_integer(to, &position)?;
+                let step = if from <= to { 1 } else { -1 };
+                let end = if inclusive { to + step } else { to };
+                if (end - from).unsigned_abs() > self.options.max_iterations as u64 {
+                    return Err(self.iteration_limit_error("@for", position));
+                }
+                let mut index = from;
+                while index != end {
+                    let value = CSSValue::from_number(index as f64, &unit);
+                    let bindings = vec![(variable.clone(), value)];
+                    self.compile_flow_control_block(body.clone(), bindings, output)?;
+                    if output.has_returned() {
+                        break;
+                    }
+                    index += step;
+                }
+                Ok(())
+            }
+            ScssStatement::While(WhileStatement {
+                condition,
+                body,
+                position,
+            }) => {
+                let mut iterations = 0;
+                while self.evaluate(condition.clone())?.is_truthy() {
+                    iterations += 1;
+                    if iterations > self.options.max_iterations {
+                        return Err(self.iteration_limit_error("@while", position.unwrap()));
+                    }
+                    self.compile_flow_control_block(body.clone(), Vec::new(), output)?;
+                    if output.has_returned() {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            ScssStatement::Extend(Extend {
+                selectors,
+                optional,
+                position,
+            }) => {
+                let position = position.unwrap();
+                let extenders = match output {
+                    Output::Function(_) => None,
+                    _ => self.rule_selectors.last(),
+                };
+                let extenders = extenders.ok_or_else(|| ParseError {
+                    reason: "@extend may only be used within style rules".to_owned(),
+                    position: position.clone(),
+                })?;
+                for target in selectors {
+                    if !target.is_compound() {
+                        return Err(ParseError {
+                            reason: "Complex selectors may not be extended".to_owned(),
+                            position,
+                        });
+                    }
+                    self.extensions.push(Extension {
+                        target,
+                        extenders: extenders.clone(),
+                        optional,
+                        position: position.clone(),
+                        media_queries: self.media_queries.clone(),
+                        matched: false,
+                    });
+                }
+                Ok(())
+            }
+            ScssStatement::Content(position) => {
+                let content = match output {
+                    Output::Function(_) => None,
+                    _ => self.content_blocks.pop(),
+                };
+                let mut content = content.ok_or_else(|| ParseError {
+                    reason: "@content is only allowed within mixin declarations".to_owned(),
+                    position,
+                })?;
+                let result = match content.items.clone() {
+                    Some(items) => {
+                        // Evaluate the block in the scopes of the `@include`
+                        let mixin_scopes = self.scopes.split_off(content.shared_scopes);
+                        self.scopes.append(&mut content.caller_scopes);
+                        self.scopes.push(Scope::default());
+                        let result = self.compile_block_items((*items).clone(), output);
+                        self.scopes.pop();
+                        content.caller_scopes = self.scopes.split_off(content.shared_scopes);
+                        self.scopes.extend(mixin_scopes);
+                        result
+                    }
+                    None => Ok(()),
+                };
+                self.content_blocks.push(content);
+                result
+            }
+        }
+    }
+
+    /// Compiles the body of a `@if`, `@each`, `@for` or `@while` in a new scope with `bindings`
+    fn compile_flow_control_block(
+        &mut self,
+        body: Vec<BlockItem>,
+        bindings: Vec<(String, CSSValue)>,
+        output: &mut Output,
+    ) -> Result<(), ParseError> {
+        self.scopes.push(Scope {
+            flow_control: true,
+            variables: bindings.into_iter().collect(),
+            ..Default::default()
+        });
+        let result = self.compile_block_items(body, output);
+        self.scopes.pop();
+        result
+    }
+
+    /// For `@for` bounds. Returns the integer and its unit
+    fn evaluate_integer(
+        &mut self,
+        value: CSSValue,
+        position: &Span,
+    ) -> Result<(i64, String), ParseError> {
+        let value = self.evaluate(value)?;
+        match value.as_number() {
+            Some((number, unit)) if number.fract() == 0.0 => Ok((number as i64, unit.to_owned())),
+            _ => Err(ParseError {
+                reason: format!(
+                    "Expected integer, found '{}'",
+                    value.to_string(&ToStringSettings::minified())
+                ),
+                position: position.clone(),
+            }),
+        }
+    }
+
+    fn iteration_limit_error(&self, directive: &str, position: Span) -> ParseError {
+        ParseError {
+            reason: format!(
+                "{} exceeded the limit of {} iterations",
+                directive, self.options.max_iterations
+            ),
+            position,
+        }
+    }
+
+    /// Applies the `@extend`s to the selectors of rules then removes selectors with placeholders.
+    /// Rules left with no selectors are removed. `media_queries` are the queries of the `@media`
+    /// blocks `entries` are in
+    fn extend_entries(
+        &mut self,
+        entries: &mut Vec<Entry>,
+        media_queries: &mut Vec<String>,
+    ) -> Result<(), ParseError> {
+        let mut extended_entries = Vec::with_capacity(entries.len());
+        for mut entry in mem::take(entries) {
+            let keep = match entry {
+                Entry::Rule(ref mut rule) => self.extend_rule(rule, media_queries)?,
+                Entry::AtRule(ref mut at_rule) => {
+                    if let Some(ref mut block) = at_rule.block {
+                        self.extend_entries(&mut block.entries, media_queries)?;
+                    }
+                    true
+                }
+                Entry::MediaRule(ref mut media_rule) => {
+                    media_queries.push(media_rule.queries.to_string(&ToStringSettings::minified()));
+                    let result = self.extend_entries(&mut media_rule.block.entries, media_queries);
+                    media_queries.pop();
+                    result?;
+                    true
+                }
+                _ => true,
+            };
+            if keep {
+                extended_entries.push(entry);
+            }
+        }
+        *entries = extended_entries;
+        Ok(())
+    }
+
+    /// Returns whether the rule has selectors left
+    fn extend_rule(
+        &mut self,
+        rule: &mut Rule,
+        media_queries: &[String],
+    ) -> Result<bool, ParseError> {
+        self.extend_selectors(&mut rule.selectors, media_queries)?;
+        rule.selectors
+            .retain(|selector| !selector.contains_placeholder());
+        if rule.selectors.is_empty() {
+            return Ok(false);
+        }
+        if let Some(ref mut nested_rules) = rule.nested_rules {
+            for mut nested_rule in mem::take(nested_rules) {
+                if self.extend_rule(&mut nested_rule, media_queries)? {
+                    nested_rules.push(nested_rule);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Adds the selectors of extending rules after each selector they match. Selectors added by an
+    /// extension are not extended by it again, so that extensions which match their own output end.
+    /// Errors if an `@extend` in a `@media` block matches a selector outside of it
+    fn extend_selectors(
+        &mut self,
+        selectors: &mut Vec<Selector>,
+        media_queries: &[String],
+    ) -> Result<(), ParseError> {
+        let settings = ToStringSettings::minified();
+        let mut extended: Vec<(Selector, Vec<usize>)> = Vec::new();
+        for selector in mem::take(selectors) {
+            let mut idx = extended.len();
+            extended.push((selector, Vec::new()));
+            while idx < extended.len() {
+                for (extension_idx, extension) in self.extensions.iter_mut().enumerate() {
+                    if extended[idx].1.contains(&extension_idx)
+                        || !extended[idx].0.has_compound(&extension.target)
+                    {
+                        continue;
+                    }
+                    if !extension.media_queries.is_empty()
+                        && extension.media_queries != media_queries
+                    {
+                        return Err(ParseError {
+                            reason: "You may not @extend selectors across media queries".to_owned(),
+                            position: extension.position.clone(),
+                        });
+                    }
+                    extension.matched = true;
+                    for extender in extension.extenders.iter() {
+                        if let Some(selector) = extended[idx].0.extend(&extension.target, extender)
+                        {
+                            let selector_string = selector.to_string(&settings);
+                            if extended.iter().all(|(existing, _)| {
+                                existing.to_string(&settings) != selector_string
+                            }) {
+                                let mut applied = extended[idx].1.clone();
+                                applied.push(extension_idx);
+                                extended.push((selector, applied));
+                            }
+                        }
+                    }
+                }
+                idx += 1;
+            }
+        }
+        *selectors = extended.into_iter().map(|(selector, _)| selector).collect();
+        Ok(())
+    }
+
+    /// Expands the mixin body into `output`
+    fn include(&mut self, include: Include, output: &mut Output) -> Result<(), ParseError> {
+        let Include {
+            name,
+            arguments,
+            content,
+            position,
+        } = include;
+        let position = position.unwrap();
+        let (namespace, member) = match name.split_once('.') {
+            Some((namespace, member)) => (Some(namespace), member),
+            None => (None, name.as_str()),
+        };
+        let (mixin, definition) = self
+            .find_member(
+                namespace,
+                member,
+                false,
+                |scope| scope.mixins.get(member).cloned(),
+                &position,
+            )?
+            .ok_or_else(|| ParseError {
+                reason: format!("Undefined mixin '{}'", name),
+                position: position.clone(),
+            })?;
+        let callee = format!("mixin '{}'", mixin.name);
+        self.check_call_depth(&callee, &position)?;
+        let arguments = self.match_arguments(&callee, &mixin.parameters, arguments, &position)?;
+
+        let caller_scopes = self.enter_definition(definition);
+        let shared_scopes = match definition {
+            Definition::Local(shared_scopes) => shared_scopes,
+            Definition::Module(_) => 0,
+        };
+        self.scopes.push(Scope::default());
+        self.content_blocks.push(ContentBlock {
+            items: content.map(Rc::new),
+            caller_scopes,
+            shared_scopes,
+        });
+        self.call_depth += 1;
+        let result = self
+            .bind_parameters(&mixin.parameters, arguments)
+            .and_then(|_| self.compile_block_items(mixin.body.clone(), output));
+        self.call_depth -= 1;
+        let ContentBlock { caller_scopes, .. } = self.content_blocks.pop().unwrap();
+        self.leave_definition(definition, caller_scopes);
+        result
+    }
+
+    /// Errors if another call would exceed [`MAX_CALL_DEPTH`]
+    fn check_call_depth(&self, callee: &str, position: &Span) -> Result<(), ParseError> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            Err(ParseError {
+                reason: format!(
+                    "{} exceeded the maximum call depth of {}",
+                    callee, MAX_CALL_DEPTH
+                ),
+                position: position.clone(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The entries of `output` for `@use` and `@forward`, which may only be used at the top level
+    fn module_output<'a>(
+        &self,
+        output: &'a mut Output,
+        statement: &str,
+        position: &Span,
+    ) -> Result<&'a mut Vec<Entry>, ParseError> {
+        match output {
+            Output::Entries(entries) if self.scopes.len() == 1 => Ok(entries),
+            _ => Err(ParseError {
+                reason: format!(
+                    "{} may only be used at the top level of a stylesheet",
+                    statement
+                ),
+                position: position.clone(),
+            }),
+        }
+    }
+
+    /// Loads the module of a `@use` or `@forward`. Modules are compiled once, in their own global
+    /// scope. Returns the index of the module and its CSS, which is empty after the first load
+    fn load_module(
+        &mut self,
+        url: &str,
+        position: &Span,
+    ) -> Result<(usize, Vec<Entry>), ParseError> {
+        let path = self.resolve_url(url, position)?;
+        if let Some(&module) = self.module_paths.get(&path) {
+            return Ok((module, Vec::new()));
+        }
+        let style_sheet = self.parse_file(&path, position)?;
+        self.loading.push(path.clone());
+        let caller_scopes = mem::replace(&mut self.scopes, vec![Scope::default()]);
+        let caller_rule_selectors = mem::take(&mut self.rule_selectors);
+        let result = self.compile_module_entries(style_sheet);
+        self.rule_selectors = caller_rule_selectors;
+        let module_scope = mem::replace(&mut self.scopes, caller_scopes)
+            .into_iter()
+            .next()
+            .unwrap();
+        self.loading.pop();
+        let css = result?;
+        self.modules.push(module_scope);
+        self.module_paths.insert(path, self.modules.len() - 1);
+        Ok((self.modules.len() - 1, css))
+    }
+
+    /// Finds the file of a url, relative to the stylesheet containing `position` or in a load
+    /// path
+    fn resolve_url(&self, url: &str, position: &Span) -> Result<PathBuf, ParseError> {
+        let base_directory = position
+            .source_id
+            .get_file()
+            .and_then(|(path, _)| path.parent().map(Path::to_path_buf));
+        modules::resolve(
+            url,
+            base_directory.as_deref(),
+            &self.options.load_paths,
+            self.options.file_loader.as_ref(),
+        )
+        .ok_or_else(|| ParseError {
+            reason: format!("Could not find stylesheet '{}'", url),
+            position: position.clone(),
+        })
+    }
+
+    /// Plain CSS files are output as written
+    fn compile_module_entries(
+        &mut self,
+        style_sheet: StyleSheet,
+    ) -> Result<Vec<Entry>, ParseError> {
+        if style_sheet.syntax == Syntax::Css {
+            Ok(style_sheet.entries)
+        } else {
+            self.compile_entries(style_sheet.entries)
+        }
+    }
+
+    /// Reads and parses the file. Positions in the stylesheet have a new [`SourceId`] for the file
+    fn parse_file(&self, path: &Path, position: &Span) -> Result<StyleSheet, ParseError> {
+        if self.loading.iter().any(|loading| loading == path) {
+            return Err(ParseError {
+                reason: format!("'{}' is loaded by itself", path.display()),
+                position: position.clone(),
+            });
+        }
+        let source = self
+            .options
+            .file_loader
+            .read(path)
+            .map_err(|error| ParseError {
+                reason: format!("Could not read '{}': {}", path.display(), error),
+                position: position.clone(),
+            })?;
+        let source_id = SourceId::new(path.to_path_buf(), source.clone());
+        StyleSheet::from_string_with_syntax(source, source_id, Syntax::from_path(path))
+    }
+
+    /// Switches to the scopes visible where a mixin or function was defined. Returns the scopes
+    /// of the caller, to be restored with [`Compiler::leave_definition`]
+    fn enter_definition(&mut self, definition: Definition) -> Vec<Scope> {
+        match definition {
+            Definition::Local(shared_scopes) => self.scopes.split_off(shared_scopes),
+            Definition::Module(module) => {
+                let module_scope = mem::take(&mut self.modules[module]);
+                mem::replace(&mut self.scopes, vec![module_scope])
+            }
+        }
+    }
+
+    fn leave_definition(&mut self, definition: Definition, caller_scopes: Vec<Scope>) {
+        match definition {
+            Definition::Local(shared_scopes) => {
+                self.scopes.truncate(shared_scopes);
+                self.scopes.extend(caller_scopes);
+            }
+            Definition::Module(module) => {
+                let scopes = mem::replace(&mut self.scopes, caller_scopes);
+                self.modules[module] = scopes.into_iter().next().unwrap();
+            }
+        }
+    }
+
+    /// Finds a variable, mixin or function with `get`, which returns the member of a scope with
+    /// the number of scopes visible where it was defined. Members with a namespace are found in
+    /// the module `@use`d with that namespace, others in the current scopes and then in modules
+    /// `@use`d with `as *`
+    fn find_member<T>(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        is_variable: bool,
+        get: impl Fn(&Scope) -> Option<(T, usize)>,
+        position: &Span,
+    ) -> Result<Option<(T, Definition)>, ParseError> {
+        if let Some(namespace) = namespace {
+            let module = self.namespace_module(namespace).ok_or_else(|| ParseError {
+                reason: format!("There is no module with the namespace '{}'", namespace),
+                position: position.clone(),
+            })?;
+            return Ok(self.module_member(module, name, is_variable, &get));
+        }
+        if let Some((member, shared_scopes)) = self.scopes.iter().rev().find_map(&get) {
+            return Ok(Some((member, Definition::Local(shared_scopes))));
+        }
+        Ok(self.scopes[0]
+            .uses
+            .iter()
+            .filter(|(namespace, _)| namespace.is_none())
+            .find_map(|(_, module)| self.module_member(*module, name, is_variable, &get)))
+    }
+
+    /// The index of the module `@use`d with the namespace
+    fn namespace_module(&self, namespace: &str) -> Option<usize> {
+        self.scopes[0]
+            .uses
+            .iter()
+            .find(|(used, _)| used.as_deref() == Some(namespace))
+            .map(|(_, module)| *module)
+    }
+
+    /// The name of the built-in module `@use`d with the namespace e.g. `math` for
+    /// `@use "sass:math" as m`
+    fn built_in_module(&self, namespace: &str) -> Option<&'static str> {
+        self.scopes[0]
+            .built_in_uses
+            .iter()
+            .find(|(used, _)| used.as_deref() == Some(namespace))
+            .map(|(_, module)| *module)
+    }
+
+    /// Finds a member of the module or of the modules it forwards. Members starting with `-` or
+    /// `_` are private to their module
+    fn module_member<T>(
+        &self,
+        module: usize,
+        name: &str,
+        is_variable: bool,
+        get: &impl Fn(&Scope) -> Option<(T, usize)>,
+    ) -> Option<(T, Definition)> {
+        if name.starts_with(['-', '_']) {
+            return None;
+        }
+        let scope = &self.modules[module];
+        if let Some((member, _)) = get(scope) {
+            return Some((member, Definition::Module(module)));
+        }
+        let filter_name = if is_variable {
+            format!("${}", name)
+        } else {
+            name.to_owned()
+        };
+        scope
+            .forwards
+            .iter()
+            .filter(|(_, filter)| {
+                filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.allows(&filter_name))
+            })
+            .find_map(|(forwarded, _)| self.module_member(*forwarded, name, is_variable, get))
+    }
+
+    /// Evaluates arguments and matches them to parameters. `None` for parameters which take their
+    /// default
+    fn match_arguments(
+        &mut self,
+        callee: &str,
+        parameters: &[Parameter],
+        arguments: Vec<Argument>,
+        position: &Span,
+    ) -> Result<Vec<Option<CSSValue>>, ParseError> {
+        let error = |reason: String| ParseError {
+            reason,
+            position: position.clone(),
+        };
+        let mut values: Vec<Option<CSSValue>> = vec![None; parameters.len()];
+        let mut positional = 0;
+        for Argument { name, value } in arguments {
+            let index = match name {
+                Some(name) => {
+                    let index = parameters
+                        .iter()
+                        .position(|parameter| parameter.name == name)
+                        .ok_or_else(|| {
+                            error(format!(
+                                "No argument named {} for {}",
+                                self.variable_name(&name),
+                                callee
+                            ))
+                        })?;
+                    if values[index].is_some() {
+                        return Err(error(format!(
+                            "Argument {} passed more than once",
+                            self.variable_name(&name)
+                        )));
+                    }
+                    index
+                }
+                None => {
+                    positional += 1;
+                    positional - 1
+                }
+            };
+            if index >= parameters.len() {
+                return Err(error(format!(
+                    "{} takes {} argument{} but {} were passed",
+                    callee,
+                    parameters.len(),
+                    if parameters.len() == 1 { "" } else { "s" },
+                    positional
+                )));
+            }
+            values[index] = Some(self.evaluate(value)?);
+        }
+        if let Some(parameter) =
+            parameters
+                .iter()
+                .zip(values.iter())
+                .find_map(|(parameter, value)| {
+                    (value.is_none() && parameter.default.is_none()).then_some(parameter)
+                })
+        {
+            return Err(error(format!(
+                "Missing argument {} for {}",
+                self.variable_name(&parameter.name),
+                callee
+            )));
+        }
+        Ok(values)
+    }
+
+    /// Defines the parameters in the innermost scope. Defaults are evaluated in order so they can
+    /// reference earlier parameters
+    fn bind_parameters(
+        &mut self,
+        parameters: &[Parameter],
+        values: Vec<Option<CSSValue>>,
+    ) -> Result<(), ParseError> {
+        for (parameter, value) in parameters.iter().zip(values) {
+            let value = match value {
+                Some(value) => value,
+                None => self.evaluate(parameter.default.clone().unwrap())?,
+            };
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .variables
+                .insert(parameter.name.clone(), value);
+        }
+        Ok(())
+    }
+
+    /// Assigning to a variable which exists in a enclosing local scope updates it. Otherwise the
+    /// variable is defined in the innermost scope, shadowing any global of the same name. Globals
+    /// are only updated without `!global` in flow control blocks outside of rules and mixins
+    fn assign(&mut self, name: String, value: CSSValue, default: bool, global: bool) {
+        let scope_index = if global {
+            0
+        } else {
+            let outermost = if self.scopes[1..].iter().all(|scope| scope.flow_control) {
+                0
+            } else {
+                1
+            };
+            (outermost..self.scopes.len())
+                .rev()
+                .find(|&index| self.scopes[index].variables.contains_key(&name))
+                .unwrap_or(self.scopes.len() - 1)
+        };
+        let scope = &mut self.scopes[scope_index].variables;
+        if default && scope.get(&name).is_some_and(|value| !is_null(value)) {
+            return;
+        }
+        scope.insert(name, value);
+    }
+
+    fn lookup(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        position: &Span,
+    ) -> Result<CSSValue, ParseError> {
+        let get = |scope: &Scope| scope.variables.get(name).map(|value| (value.clone(), 0));
+        match self.find_member(namespace, name, true, get, position)? {
+            Some((value, _)) => Ok(value),
+            None => Err(ParseError {
+                reason: match namespace {
+                    Some(namespace) => format!("Undefined variable {}.${}", namespace, name),
+                    None => format!("Undefined variable {}", self.variable_name(name)),
+                },
+                position: position.clone(),
+            }),
+        }
+    }
+
+    /// The name as written in the source e.g. `$name`, or `@name` in Less
+    fn variable_name(&self, name: &str) -> String {
+        match self.syntax {
+            Syntax::Less => format!("@{}", name),
+            _ => format!("${}", name),
+        }
+    }
+
+    /// Evaluates the value down to a CSS value
+    fn evaluate(&mut self, value: CSSValue) -> Result<CSSValue, ParseError> {
+        match value {
+            CSSValue::Variable {
+                namespace,
+                name,
+                position,
+            } => self.lookup(namespace.as_deref(), &name, &position),
+            CSSValue::Keyword(keyword) if keyword == "null" => Ok(null()),
+            CSSValue::Function(name, arguments) if is_calculation(&name) => {
+                let arguments = arguments
+                    .into_iter()
+                    .map(|argument| self.evaluate_calculation(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match arguments.as_slice() {
+                    [argument] if name == "calc" && argument.as_number().is_some() => {
+                        Ok(arguments.into_iter().next().unwrap())
+                    }
+                    _ => Ok(CSSValue::Function(name, arguments)),
+                }
+            }
+            CSSValue::Function(name, arguments) => {
+                let position = self.value_position();
+                let function = match name.split_once('.') {
+                    Some((namespace, member)) if self.namespace_module(namespace).is_some() => {
+                        let get = |scope: &Scope| scope.functions.get(member).cloned();
+                        let function =
+                            self.find_member(Some(namespace), member, false, get, &position)?;
+                        Some(function.ok_or_else(|| ParseError {
+                            reason: format!("Undefined function {}", name),
+                            position,
+                        })?)
+                    }
+                    // Built-in module functions e.g. `math.div`
+                    Some(_) => None,
+                    None => {
+                        let get = |scope: &Scope| scope.functions.get(&name).cloned();
+                        self.find_member(None, &name, false, get, &position)?
+                    }
+                };
+                match function {
+                    Some((function, definition)) => {
+                        self.call_user_function(&function, definition, arguments)
+                    }
+                    None => {
+                        let arguments = self.evaluate_values(arguments)?;
+                        let name = self.built_in_function_name(name);
+                        self.call_function(name, arguments)
+                    }
+                }
+            }
+            CSSValue::List(values) => Ok(CSSValue::List(self.evaluate_values(values)?)),
+            CSSValue::CommaSeparatedList(values) => {
+                Ok(CSSValue::CommaSeparatedList(self.evaluate_values(values)?))
+            }
+            CSSValue::Bracketed(value) => Ok(CSSValue::Bracketed(Box::new(self.evaluate(*value)?))),
+            CSSValue::Interpolation(interpolation) => {
+                Ok(CSSValue::Keyword(self.interpolate(interpolation)?))
+            }
+            CSSValue::InterpolatedString(interpolation) => {
+                Ok(CSSValue::StringLiteral(self.interpolate(interpolation)?))
+            }
+            CSSValue::NamedArgument(name, value) => Ok(CSSValue::NamedArgument(
+                name,
+                Box::new(self.evaluate(*value)?),
+            )),
+            CSSValue::Map(entries) => {
+                let mut evaluated: Vec<(CSSValue, CSSValue)> = Vec::new();
+                for (key, value) in entries {
+                    let key = self.evaluate(key)?;
+                    if evaluated.iter().any(|(other, _)| other.scss_equals(&key)) {
+                        return Err(ParseError {
+                            reason: format!(
+                                "Duplicate key {} in map",
+                                key.to_string(&ToStringSettings::minified())
+                            ),
+                            position: self.value_position(),
+                        });
+                    }
+                    evaluated.push((key, self.evaluate(value)?));
+                }
+                Ok(CSSValue::Map(evaluated))
+            }
+            CSSValue::BinaryOperation {
+                lhs,
+                operator,
+                rhs,
+                position,
+            } => {
+                match operator {
+                    BinaryOperator::Divide => self.divide(*lhs, *rhs, position, false),
+                    BinaryOperator::Add
+                    | BinaryOperator::Subtract
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Modulo => {
+                        let lhs = self.evaluate_operand(*lhs)?;
+                        let rhs = self.evaluate_operand(*rhs)?;
+                        arithmetic(&lhs, operator, &rhs, position)
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        let lhs = self.evaluate(*lhs)?;
+                        // Short circuit, returning the operand which decided the result
+                        if lhs.is_truthy() == (operator == BinaryOperator::Or) {
+                            Ok(lhs)
+                        } else {
+                            self.evaluate(*rhs)
+                        }
+                    }
+                    operator => {
+                        let lhs = self.evaluate(*lhs)?;
+                        let rhs = self.evaluate(*rhs)?;
+                        compare(&lhs, operator, &rhs, position).map(CSSValue::from_bool)
+                    }
+                }
+            }
+            CSSValue::Parenthesized(value) => self.evaluate_operand(*value),
+            CSSValue::UnaryOperation {
+                operator: UnaryOperator::Not,
+                operand,
+                position: _,
+            } => Ok(CSSValue::from_bool(!self.evaluate(*operand)?.is_truthy())),
+            CSSValue::UnaryOperation {
+                operator: UnaryOperator::Negate,
+                operand,
+                position,
+            } => {
+                let operand = self.evaluate(*operand)?;
+                match operand.as_number() {
+                    Some((number, unit)) => Ok(CSSValue::from_number(-number, unit)),
+                    // e.g. `-$name` where `$name` is a keyword
+                    None => Ok(CSSValue::UnaryOperation {
+                        operator: UnaryOperator::Negate,
+                        operand: Box::new(operand),
+                        position,
+                    }),
+                }
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Operands of arithmetic and values in brackets, in which `/` between numbers divides e.g.
+    /// `6px / 2 * 2` is `6px`
+    fn evaluate_operand(&mut self, value: CSSValue) -> Result<CSSValue, ParseError> {
+        match value {
+            CSSValue::BinaryOperation {
+                lhs,
+                operator: BinaryOperator::Divide,
+                rhs,
+                position,
+            } => self.divide(*lhs, *rhs, position, true),
+            value => self.evaluate(value),
+        }
+    }
+
+    /// Between literal numbers `/` separates values e.g. `font: 12px/1.5`. Numbers are divided
+    /// if `calculated` or an operand is computed e.g. `$gap / 2`
+    fn divide(
+        &mut self,
+        lhs: CSSValue,
+        rhs: CSSValue,
+        position: Span,
+        calculated: bool,
+    ) -> Result<CSSValue, ParseError> {
+        let calculated = calculated || is_calculated(&lhs) || is_calculated(&rhs);
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        if calculated && lhs.as_number().is_some() && rhs.as_number().is_some() {
+            arithmetic(&lhs, BinaryOperator::Divide, &rhs, position)
+        } else {
+            Ok(CSSValue::BinaryOperation {
+                lhs: Box::new(lhs),
+                operator: BinaryOperator::Divide,
+                rhs: Box::new(rhs),
+                position,
+            })
+        }
+    }
+
+    /// Arguments of CSS math functions e.g. `calc(100% - $gap)`. Operations which cannot be done
+    /// at compile time, such as between incompatible units, are left for the browser
+    fn evaluate_calculation(&mut self, value: CSSValue) -> Result<CSSValue, ParseError> {
+        match value {
+            CSSValue::BinaryOperation {
+                lhs,
+                operator:
+                    operator @ (BinaryOperator::Add
+                    | BinaryOperator::Subtract
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide),
+                rhs,
+                position,
+            } => {
+                let lhs = self.evaluate_calculation(*lhs)?;
+                let rhs = self.evaluate_calculation(*rhs)?;
+                if lhs.as_number().is_some() && rhs.as_number().is_some() {
+                    if let Ok(value) = arithmetic(&lhs, operator, &rhs, position.clone()) {
+                        return Ok(value);
+                    }
+                }
+                Ok(CSSValue::BinaryOperation {
+                    lhs: Box::new(lhs),
+                    operator,
+                    rhs: Box::new(rhs),
+                    position,
+                })
+            }
+            CSSValue::Parenthesized(value) => match self.evaluate_calculation(*value)? {
+                value if value.as_number().is_some() => Ok(value),
+                value => Ok(CSSValue::Parenthesized(Box::new(value))),
+            },
+            value => self.evaluate(value),
+        }
+    }
+
+    /// Evaluates the expressions of the interpolation into its text. Strings are inserted without
+    /// their quotes and `null` as nothing
+    fn interpolate(&mut self, interpolation: Interpolation) -> Result<String, ParseError> {
+        let previous_position = self.item_position.replace(interpolation.position);
+        let result = self.interpolate_parts(interpolation.parts);
+        self.item_position = previous_position;
+        result
+    }
+
+    fn interpolate_parts(&mut self, parts: Vec<InterpolationPart>) -> Result<String, ParseError> {
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                InterpolationPart::Text(part) => text.push_str(&part),
+                InterpolationPart::Expression(value) => match self.evaluate(value)? {
+                    CSSValue::StringLiteral(content) => text.push_str(&content),
+                    value => {
+                        if let Some(value) = without_nulls(value) {
+                            text.push_str(&value.to_string(&ToStringSettings::default()))
+                        }
+                    }
+                },
+            }
+        }
+        Ok(text)
+    }
+
+    /// Runs the function body in the scopes visible where it was defined, returning the value of
+    /// its `@return`
+    fn call_user_function(
+        &mut self,
+        function: &FunctionDefinition,
+        definition: Definition,
+        arguments: Vec<CSSValue>,
+    ) -> Result<CSSValue, ParseError> {
+        let position = self.value_position();
+        let callee = format!("function '{}'", function.name);
+        self.check_call_depth(&callee, &position)?;
+        let arguments = arguments
+            .into_iter()
+            .map(|value| match value {
+                CSSValue::NamedArgument(name, value) => Argument {
+                    name: Some(name),
+                    value: *value,
+                },
+                value => Argument { name: None, value },
+            })
+            .collect();
+        let arguments =
+            self.match_arguments(&callee, &function.parameters, arguments, &position)?;
+
+        let caller_scopes = self.enter_definition(definition);
+        self.scopes.push(Scope::default());
+        self.call_depth += 1;
+        let mut returned = None;
+        let result = self
+            .bind_parameters(&function.parameters, arguments)
+            .and_then(|_| {
+                self.compile_block_items(
+                    function.body.clone(),
+                    &mut Output::Function(&mut returned),
+                )
+            });
+        self.call_depth -= 1;
+        self.leave_definition(definition, caller_scopes);
+        result?;
+        returned.ok_or_else(|| ParseError {
+            reason: format!("{} finished without @return", callee),
+            position: function.position.clone().unwrap_or(position),
+        })
+    }
+
+    /// Calls native functions from the registry, which has the built-in module functions. Other
+    /// functions are left as CSS functions
+    fn call_function(
+        &mut self,
+        name: String,
+        arguments: Vec<CSSValue>,
+    ) -> Result<CSSValue, ParseError> {
+        match self.options.functions.get(&name) {
+            Some(function) => function(arguments).map_err(|reason| ParseError {
+                reason,
+                position: self.value_position(),
+            }),
+            // CSS function names do not contain `.`
+            None if name.contains('.') => Err(ParseError {
+                reason: format!("Undefined function {}", name),
+                position: self.value_position(),
+            }),
+            None => Ok(CSSValue::Function(name, arguments)),
+        }
+    }
+
+    /// The registry name of a function from a built-in module `@use`d with a namespace or
+    /// `as *` e.g. `m.div` is `math.div` after `@use "sass:math" as m`
+    fn built_in_function_name(&self, name: String) -> String {
+        match name.split_once('.') {
+            Some((namespace, member)) => match self.built_in_module(namespace) {
+                Some(module) => format!("{}.{}", module, member),
+                None => name,
+            },
+            None => self.scopes[0]
+                .built_in_uses
+                .iter()
+                .filter(|(namespace, _)| namespace.is_none())
+                .map(|(_, module)| format!("{}.{}", module, name))
+                .find(|qualified| self.options.functions.get(qualified).is_some())
+                .unwrap_or(name),
+        }
+    }
+
+    /// For errors in values without positions, the position of the enclosing item
+    fn value_position(&self) -> Span {
+        self.item_position.clone().unwrap_or(Span {
+            start: 0,
+            end: 0,
+            source_id: SourceId::null(),
+        })
+    }
+
+    fn evaluate_values(&mut self, values: Vec<CSSValue>) -> Result<Vec<CSSValue>, ParseError> {
+        values
+            .into_iter()
+            .map(|value| self.evaluate(value))
+            .collect()
+    }
+
+    fn evaluate_media_query_list(
+        &mut self,
+        queries: &mut MediaQueryList,
+    ) -> Result<(), ParseError> {
+        for query in queries.queries.iter_mut() {
+            match query {
+                MediaQuery::Type {
+                    condition: Some(condition),
+                    ..
+                }
+                | MediaQuery::Condition(condition) => self.evaluate_media_condition(condition)?,
+                MediaQuery::Type {
+                    condition: None, ..
+                } => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate_media_condition(
+        &mut self,
+        condition: &mut MediaCondition,
+    ) -> Result<(), ParseError> {
+        match condition {
+            MediaCondition::Feature(MediaFeature::Boolean(_)) => {}
+            MediaCondition::Feature(MediaFeature::Plain(_, value)) => {
+                self.evaluate_media_feature_value(value)?
+            }
+            MediaCondition::Feature(MediaFeature::Range { start, end, .. }) => {
+                if let Some((value, _)) = start {
+                    self.evaluate_media_feature_value(value)?;
+                }
+                if let Some((_, value)) = end {
+                    self.evaluate_media_feature_value(value)?;
+                }
+            }
+            MediaCondition::Not(condition) => self.evaluate_media_condition(condition)?,
+            MediaCondition::And(conditions) | MediaCondition::Or(conditions) => {
+                for condition in conditions.iter_mut() {
+                    self.evaluate_media_condition(condition)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate_media_feature_value(
+        &mut self,
+        value: &mut MediaFeatureValue,
+    ) -> Result<(), ParseError> {
+        if let MediaFeatureValue::Value(value) = value {
+            *value = self.evaluate(mem::replace(value, CSSValue::Keyword(String::new())))?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates `==`, `!=`, `<`, `<=`, `>` and `>=`. Numbers with different units cannot be
+/// compared
+fn compare(
+    lhs: &CSSValue,
+    operator: BinaryOperator,
+    rhs: &CSSValue,
+    position: Span,
+) -> Result<bool, ParseError> {
+    match operator {
+        BinaryOperator::Equal => return Ok(lhs.scss_equals(rhs)),
+        BinaryOperator::NotEqual => return Ok(!lhs.scss_equals(rhs)),
+        _ => {}
+    }
+    let ((lhs, lhs_unit), (rhs, rhs_unit)) = match (lhs.as_number(), rhs.as_number()) {
+        (Some(lhs), Some(rhs)) => (lhs, rhs),
+        _ => {
+            let settings = ToStringSettings::minified();
+            return Err(ParseError {
+                reason: format!(
+                    "Expected numbers either side of '{}', found '{}' and '{}'",
+                    operator.as_str(),
+                    lhs.to_string(&settings),
+                    rhs.to_string(&settings)
+                ),
+                position,
+            });
+        }
+    };
+    let rhs = if lhs_unit.is_empty() || rhs_unit.is_empty() {
+        rhs
+    } else {
+        convert_unit(rhs, rhs_unit, lhs_unit)
+            .ok_or_else(|| incompatible_units(lhs_unit, rhs_unit, position))?
+    };
+    Ok(match operator {
+        BinaryOperator::LessThan => lhs < rhs,
+        BinaryOperator::LessThanEqual => lhs <= rhs,
+        BinaryOperator::GreaterThan => lhs > rhs,
+        BinaryOperator::GreaterThanEqual => lhs >= rhs,
+        _ => unreachable!(),
+    })
+}
+
+fn function_body_error(position: Span) -> ParseError {
+    ParseError {
+        reason: "Functions can only contain variable declarations and control directives"
+            .to_owned(),
+        position,
+    }
+}
+
+/// Evaluates `+`, `-`, `*`, `/` and `%`. Compatible units are converted to the unit of the lhs
+/// e.g. `1in + 1px` is `97px`. `+` concatenates strings
+pub(crate) fn arithmetic(
+    lhs: &CSSValue,
+    operator: BinaryOperator,
+    rhs: &CSSValue,
+    position: Span,
+) -> Result<CSSValue, ParseError> {
+    let settings = ToStringSettings::minified();
+    let ((lhs_number, lhs_unit), (rhs_number, rhs_unit)) = match (lhs.as_number(), rhs.as_number())
+    {
+        (Some(lhs), Some(rhs)) => (lhs, rhs),
+        _ => {
+            return match (operator, lhs, rhs) {
+                (BinaryOperator::Add, CSSValue::StringLiteral(_) | CSSValue::Keyword(_), _)
+                | (BinaryOperator::Add, _, CSSValue::StringLiteral(_) | CSSValue::Keyword(_)) => {
+                    let text = |value: &CSSValue| match value {
+                        CSSValue::StringLiteral(text) | CSSValue::Keyword(text) => text.clone(),
+                        value => value.to_string(&settings),
+                    };
+                    // The result is quoted if the lhs is quoted
+                    let content = text(lhs) + &text(rhs);
+                    if let CSSValue::StringLiteral(_) = lhs {
+                        Ok(CSSValue::StringLiteral(content))
+                    } else {
+                        Ok(CSSValue::Keyword(content))
+                    }
+                }
+                _ => Err(ParseError {
+                    reason: format!(
+                        "Undefined operation '{} {} {}'",
+                        lhs.to_string(&settings),
+                        operator.as_str(),
+                        rhs.to_string(&settings)
+                    ),
+                    position,
+                }),
+            };
+        }
+    };
+    let error = |reason: &str| ParseError {
+        reason: reason.to_owned(),
+        position: position.clone(),
+    };
+    match operator {
+        BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Modulo => {
+            let (rhs_number, unit) = if rhs_unit.is_empty() {
+                (rhs_number, lhs_unit)
+            } else if lhs_unit.is_empty() {
+                (rhs_number, rhs_unit)
+            } else {
+                let rhs_number = convert_unit(rhs_number, rhs_unit, lhs_unit)
+                    .ok_or_else(|| incompatible_units(lhs_unit, rhs_unit, position.clone()))?;
+                (rhs_number, lhs_unit)
+            };
+            let value = match operator {
+                BinaryOperator::Add => lhs_number + rhs_number,
+                BinaryOperator::Subtract => lhs_number - rhs_number,
+                _ if rhs_number == 0.0 => return Err(error("Modulo by zero")),
+                // Takes the sign of the rhs
+                _ => lhs_number - rhs_number * (lhs_number / rhs_number).floor(),
+            };
+            Ok(CSSValue::from_number(value, unit))
+        }
+        BinaryOperator::Multiply => {
+            if !lhs_unit.is_empty() && !rhs_unit.is_empty() {
+                return Err(ParseError {
+                    reason: format!(
+                        "Cannot multiply {} by {}, the result would have {}*{} units",
+                        lhs.to_string(&settings),
+                        rhs.to_string(&settings),
+                        lhs_unit,
+                        rhs_unit
+                    ),
+                    position,
+                });
+            }
+            let unit = if lhs_unit.is_empty() {
+                rhs_unit
+            } else {
+                lhs_unit
+            };
+            Ok(CSSValue::from_number(lhs_number * rhs_number, unit))
+        }
+        BinaryOperator::Divide => {
+            if rhs_number == 0.0 {
+                return Err(error("Division by zero"));
+            }
+            if rhs_unit.is_empty() {
+                Ok(CSSValue::from_number(lhs_number / rhs_number, lhs_unit))
+            } else if lhs_unit.is_empty() {
+                Err(ParseError {
+                    reason: format!(
+                        "Cannot divide {} by {}, the result would have 1/{} units",
+                        lhs.to_string(&settings),
+                        rhs.to_string(&settings),
+                        rhs_unit
+                    ),
+                    position,
+                })
+            } else {
+                let rhs_number = convert_unit(rhs_number, rhs_unit, lhs_unit)
+                    .ok_or_else(|| incompatible_units(lhs_unit, rhs_unit, position))?;
+                Ok(CSSValue::from_number(lhs_number / rhs_number, ""))
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn incompatible_units(lhs_unit: &str, rhs_unit: &str, position: Span) -> ParseError {
+    ParseError {
+        reason: format!("Incompatible units {} and {}", lhs_unit, rhs_unit),
+        position,
+    }
+}
+
+/// Converts a number between units of the same dimension e.g. `in` to `px`. `None` if the units
+/// are not compatible
+fn convert_unit(value: f64, from: &str, to: &str) -> Option<f64> {
+    if from.eq_ignore_ascii_case(to) {
+        return Some(value);
+    }
+    let (from_dimension, from_factor) = canonical_unit(from)?;
+    let (to_dimension, to_factor) = canonical_unit(to)?;
+    (from_dimension == to_dimension).then(|| value * from_factor / to_factor)
+}
+
+/// The dimension of absolute units and how many of the dimension's canonical unit they are
+fn canonical_unit(unit: &str) -> Option<(&'static str, f64)> {
+    Some(match unit.to_ascii_lowercase().as_str() {
+        "px" => ("length", 1.0),
+        "in" => ("length", 96.0),
+        "cm" => ("length", 96.0 / 2.54),
+        "mm" => ("length", 96.0 / 25.4),
+        "q" => ("length", 96.0 / 101.6),
+        "pt" => ("length", 96.0 / 72.0),
+        "pc" => ("length", 16.0),
+        "deg" => ("angle", 1.0),
+        "grad" => ("angle", 0.9),
+        "rad" => ("angle", 180.0 / std::f64::consts::PI),
+        "turn" => ("angle", 360.0),
+        "ms" => ("time", 1.0),
+        "s" => ("time", 1000.0),
+        "hz" => ("frequency", 1.0),
+        "khz" => ("frequency", 1000.0),
+        "dpi" => ("resolution", 1.0),
+        "dpcm" => ("resolution", 2.54),
+        "dppx" | "x" => ("resolution", 96.0),
+        _ => return None,
+    })
+}
+
+/// Values whose operands are computed rather than written, so `/` between them divides
+fn is_calculated(value: &CSSValue) -> bool {
+    matches!(
+        value,
+        CSSValue::Variable { .. }
+            | CSSValue::Function(..)
+            | CSSValue::BinaryOperation { .. }
+            | CSSValue::UnaryOperation { .. }
+            | CSSValue::Parenthesized(_)
+    )
+}
+
+/// CSS math functions, whose arguments the browser evaluates
+fn is_calculation(name: &str) -> bool {
+    ["calc", "min", "max", "clamp"]
+        .iter()
+        .any(|calculation| name.eq_ignore_ascii_case(calculation))
+}
+
+/// Items of a list. Items of a map are its key value pairs. Other values are a list with one item
+pub(crate) fn list_items(value: CSSValue) -> Vec<CSSValue> {
+    match value {
+        CSSValue::List(items) | CSSValue::CommaSeparatedList(items) => items,
+        CSSValue::Bracketed(list) => list_items(*list),
+        CSSValue::Map(entries) => entries
+            .into_iter()
+            .map(|(key, value)| CSSValue::List(vec![key, value]))
+            .collect(),
+        value => vec![value],
+    }
+}
+
+/// Maps cannot be output as CSS
+fn find_map(value: &CSSValue) -> Option<&CSSValue> {
+    match value {
+        CSSValue::Map(_) => Some(value),
+        CSSValue::List(items) | CSSValue::CommaSeparatedList(items) => {
+            items.iter().find_map(find_map)
+        }
+        CSSValue::Bracketed(list) => find_map(list),
+        CSSValue::Function(_, arguments) => arguments.iter().find_map(find_map),
+        _ => None,
+    }
+}
+
+/// Parses the evaluated text of an interpolation. Errors are at the position of the interpolation
+fn reparse<T>(
+    text: String,
+    position: Span,
+    parser: impl FnOnce(&mut BufferedTokenQueue<CSSToken, Span>) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    let mut reader = BufferedTokenQueue::new();
+    lexer::lex_source(&text, &mut reader, position.source_id, Some(position.start))
+        .and_then(|_| {
+            let parsed = parser(&mut reader)?;
+            reader.expect_next(CSSToken::EOS)?;
+            Ok(parsed)
+        })
+        .map_err(|error| ParseError {
+            reason: format!("{} in '{}'", error.reason, text),
+            position,
+        })
+}
+
+pub(crate) fn null() -> CSSValue {
+    CSSValue::Null
+}
+
+fn is_null(value: &CSSValue) -> bool {
+    matches!(value, CSSValue::Null)
+}
+
+/// Leaves out the nulls in lists, which are not output. `None` if the value is null or only
+/// holds nulls
+fn without_nulls(value: CSSValue) -> Option<CSSValue> {
+    match value {
+        CSSValue::Null => None,
+        CSSValue::List(items) => {
+            let items: Vec<_> = items.into_iter().filter_map(without_nulls).collect();
+            (!items.is_empty()).then_some(CSSValue::List(items))
+        }
+        CSSValue::CommaSeparatedList(items) => {
+            let items: Vec<_> = items.into_iter().filter_map(without_nulls).collect();
+            (!items.is_empty()).then_some(CSSValue::CommaSeparatedList(items))
+        }
+        value => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod compiler_tests {
+    use super::*;
+    use crate::{raise_nested_rules, MemoryFileLoader, ToStringSettings};
+    use source_map::SourceId;
+
+    fn compile_source(source: &str) -> Result<String, ParseError> {
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Scss)?;
+        compile(&mut style_sheet)?;
+        raise_nested_rules(&mut style_sheet).unwrap();
+        Ok(style_sheet.to_string(Some(ToStringSettings::minified())))
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            compile_source("$color: red; $border: 1px solid $color; a { border: $border; }")
+                .unwrap(),
+            "a{border:1px solid red;}"
+        );
+    }
+
+    #[test]
+    fn block_scoping() {
+        assert_eq!(
+            compile_source("a { $w: 1px; b { width: $w; } }").unwrap(),
+            "a{}a b{width:1px;}"
+        );
+        let source = "a { $w: 1px; } b { width: $w; }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Undefined variable $w");
+        assert_eq!(&source[error.position.start..error.position.end], "$w");
+        assert_eq!(error.position.start, 26);
+    }
+
+    #[test]
+    fn shadowing_and_global() {
+        assert_eq!(
+            compile_source(
+                "$x: 1; a { $x: 2; w: $x; } b { w: $x; } c { $x: 3 !global; } d { w: $x; }"
+            )
+            .unwrap(),
+            "a{w:2;}b{w:1;}c{}d{w:3;}"
+        );
+        // Assigning in a nested block updates the enclosing local
+        assert_eq!(
+            compile_source("a { $x: 1; b { $x: 2; } w: $x; }").unwrap(),
+            "a{w:2;}a b{}"
+        );
+    }
+
+    #[test]
+    fn default_flag() {
+        assert_eq!(
+            compile_source("$x: 1; $x: 2 !default; $y: 3 !default; a { w: $x $y; }").unwrap(),
+            "a{w:1 3;}"
+        );
+    }
+
+    #[test]
+    fn order_in_rule() {
+        assert_eq!(
+            compile_source("a { $x: 1; w: $x; $x: 2; h: $x; }").unwrap(),
+            "a{w:1;h:2;}"
+        );
+    }
+
+    #[test]
+    fn at_rules() {
+        assert_eq!(
+            compile_source(
+                "$breakpoint: 600px; @media (min-width: $breakpoint) { $c: blue; a { color: $c; } }"
+            )
+            .unwrap(),
+            "@media (min-width:600px){a{color:blue;}}"
+        );
+    }
+
+    #[test]
+    fn mixins() {
+        assert_eq!(
+            compile_source(
+                "@mixin box($w, $h: $w) { width: $w; height: $h; b { w: $w; } }
+                a { color: red; @include box(1px); } c { @include box($h: 3px, $w: 2px) }"
+            )
+            .unwrap(),
+            "a{color:red;width:1px;height:1px;}a b{w:1px;}c{width:2px;height:3px;}c b{w:2px;}"
+        );
+        // Mixin bodies do not see the locals of the `@include`
+        assert!(compile_source("@mixin m { w: $x; } a { $x: 1; @include m; }").is_err());
+        // Top level includes add rules
+        assert_eq!(
+            compile_source("@mixin reset { * { margin: 0; } } @include reset;").unwrap(),
+            "*{margin:0;}"
+        );
+    }
+
+    #[test]
+    fn mixin_content() {
+        assert_eq!(
+            compile_source(
+                "@mixin child { b { @content; } } @mixin empty { @content; }
+                a { @include child { color: red; @include empty; } }"
+            )
+            .unwrap(),
+            "a{}a b{color:red;}"
+        );
+        assert_eq!(
+            compile_source(
+                "@mixin wrap($x: 1) { w: $x; @content; } $x: 2;
+                a { $c: blue; @include wrap { color: $c; h: $x; } }"
+            )
+            .unwrap(),
+            "a{w:1;color:blue;h:2;}"
+        );
+    }
+
+    #[test]
+    fn mixin_errors() {
+        let source = "a { @include missing; }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Undefined mixin 'missing'");
+        assert_eq!(
+            &source[error.position.start..error.position.end],
+            "@include missing;"
+        );
+        for (source, reason) in [
+            (
+                "@mixin m($a) {} a { @include m(1, 2); }",
+                "mixin 'm' takes 1 argument but 2 were passed",
+            ),
+            (
+                "@mixin m($a, $b) {} a { @include m(1); }",
+                "Missing argument $b for mixin 'm'",
+            ),
+            (
+                "@mixin m($a) {} a { @include m($c: 1); }",
+                "No argument named $c for mixin 'm'",
+            ),
+            (
+                "a { @content; }",
+                "@content is only allowed within mixin declarations",
+            ),
+            (
+                "@mixin m { @include m; } .x { @include m; }",
+                "mixin 'm' exceeded the maximum call depth of 50",
+            ),
+            (
+                "@mixin a { b: c; @include b; } @mixin b { @include a; } .x { @include a; }",
+                "mixin 'a' exceeded the maximum call depth of 50",
+            ),
+        ] {
+            assert_eq!(compile_source(source).unwrap_err().reason, reason);
+        }
+    }
+
+    #[test]
+    fn extend() {
+        assert_eq!(
+            compile_source(
+                "%button { padding: 0; } .b:hover, p { c: d; }
+                .primary { @extend %button; @extend .b; color: blue; }"
+            )
+            .unwrap(),
+            ".primary{padding:0;}.b:hover,.primary:hover,p{c:d;}.primary{color:blue;}"
+        );
+        // Nested extenders and chained extends
+        assert_eq!(
+            compile_source(
+                "%a { x: 1; } .b { @extend %a; } nav { .c { @extend .b; } } %d { y: 2; }"
+            )
+            .unwrap(),
+            ".b,nav .c{x:1;}.b,nav .c{}nav{}nav .c{}"
+        );
+        // Conflicting tag names do not unify
+        assert_eq!(
+            compile_source("a.x { w: 1; } span { @extend .x; }").unwrap(),
+            "a.x{w:1;}span{}"
+        );
+        // Extends in `@media` blocks apply to rules in the same blocks, extends outside of them
+        // apply everywhere
+        assert_eq!(
+            compile_source(
+                "%p { a: b; } @media print { %q { c: d; } .y { @extend %q; } }
+                .x { @extend %p; } @media print { %p { e: f; } }"
+            )
+            .unwrap(),
+            ".x{a:b;}@media print{.y{c:d;}.y{}}.x{}@media print{.x{e:f;}}"
+        );
+    }
+
+    #[test]
+    fn extend_errors() {
+        let source = "a { @extend .missing; }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(
+            error.reason,
+            "The target selector .missing was not found, use `@extend .missing !optional` to avoid this error"
+        );
+        assert_eq!(
+            &source[error.position.start..error.position.end],
+            "@extend .missing;"
+        );
+        assert!(compile_source("a { @extend .missing !optional; }").is_ok());
+        assert_eq!(
+            compile_source(".a b {} c { @extend .a b; }")
+                .unwrap_err()
+                .reason,
+            "Complex selectors may not be extended"
+        );
+        let source = "%p { a: b; } @media print { .y { @extend %p; } }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(
+            error.reason,
+            "You may not @extend selectors across media queries"
+        );
+        assert_eq!(
+            &source[error.position.start..error.position.end],
+            "@extend %p;"
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        let source = "@mixin theme($mode) {
+            @if $mode == dark and not ($mode == light) { color: white; }
+            @else if $mode == dim or false { color: grey; }
+            @else { color: black; }
+        }
+        a { @include theme(dark); } b { @include theme(dim); } c { @include theme(light); }";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            "a{color:white;}b{color:grey;}c{color:black;}"
+        );
+        assert_eq!(
+            compile_source("$w: 2px; a { @if $w >= 2px { x: 1 < 2; } @if null { y: 1; } }")
+                .unwrap(),
+            "a{x:true;}"
+        );
+        let source = "a { @if 1px < 1em { x: 1; } }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Incompatible units px and em");
+        assert_eq!(&source[error.position.start..error.position.end], "<");
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            compile_source("@each $size in small, large { .a { font: $size; } }").unwrap(),
+            ".a{font:small;}.a{font:large;}"
+        );
+        assert_eq!(
+            compile_source("a { @each $name, $glyph in (x 1, y) { content: $name $glyph; } }")
+                .unwrap(),
+            "a{content:x 1;content:y;}"
+        );
+        assert_eq!(
+            compile_source(
+                "a { @for $i from 1px to 3px { w: $i; } @for $i from 2 through 1 { h: $i; } }"
+            )
+            .unwrap(),
+            "a{w:1px;w:2px;h:2;h:1;}"
+        );
+        // Flow control blocks at the top level assign to globals
+        assert_eq!(
+            compile_source("$go: true; @while $go { a { b: c; } $go: false; }").unwrap(),
+            "a{b:c;}"
+        );
+    }
+
+    #[test]
+    fn iteration_limit() {
+        let source = "$x: true; @while $x { a { b: c; } }";
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Scss)
+                .unwrap();
+        let options = CompileOptions {
+            max_iterations: 10,
+            ..Default::default()
+        };
+        let error = compile_with_options(&mut style_sheet, &options).unwrap_err();
+        assert_eq!(error.reason, "@while exceeded the limit of 10 iterations");
+        assert_eq!(error.position.start, 10);
+
+        let source = "$x: true; @if true { $x: false; } @while $x { a { b: c; } }";
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Scss)
+                .unwrap();
+        assert!(compile_with_options(&mut style_sheet, &options).is_ok());
+        assert!(style_sheet.entries.is_empty());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let source = "$gap: 10px; $sidebar: 25%;
+        a {
+            margin: $gap * 2 (-$gap) -$gap;
+            width: 100% - $sidebar;
+            padding: math.div(10px, 2) $gap / 4 math.div($gap, 2px);
+            font: 12px/1.5 serif;
+            height: 4px + 1in;
+            order: 1 + 2 * 3 - (1 + 2) * 3 10 % 3 -7 % 3;
+            content: \"a\" + b + 1;
+        }";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            "a{margin:20px -10px -10px;width:75%;padding:5px 2.5px 5;font:12px/1.5 serif;\
+            height:100px;order:-2 1 2;content:\"ab1\";}"
+        );
+        assert_eq!(
+            compile_source("$gap: 1em; a { width: calc(100% - $gap * 2); b: calc(1px + 2px); }")
+                .unwrap(),
+            "a{width:calc(100% - 2em);b:3px;}"
+        );
+    }
+
+    #[test]
+    fn division() {
+        let source = "$gap: 10px;
+        a {
+            a: (10px / 2) (a / b);
+            b: 6px / 2 * 2;
+            c: 10px / 2 + 1px;
+            d: 12px/1.5 6px/2/3 $gap / 2;
+            e: calc((100% - $gap) / 2) calc((1px + 2px) * 3);
+        }";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            "a{a:5px a/b;b:6px;c:6px;d:12px/1.5 6px/2/3 5px;e:calc((100% - 10px)/2) 9px;}"
+        );
+        // Plain CSS is not evaluated
+        let source = ":root { --x: 2 + 3; } a { u: 1 == 1; v: (10px / 2) 1px + 2px; }";
+        let mut style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        compile(&mut style_sheet).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            ":root{--x:2 + 3;}a{u:1 == 1;v:(10px/2) 1px + 2px;}"
+        );
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        let source = "$a: 1px; a { b: $a + 1em; }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Incompatible units px and em");
+        assert_eq!(&source[error.position.start..error.position.end], "+");
+
+        let source = "a { b: 2px * 3px; }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(
+            error.reason,
+            "Cannot multiply 2px by 3px, the result would have px*px units"
+        );
+        assert_eq!(&source[error.position.start..error.position.end], "*");
+
+        let source = "a { b: math.div(1px, 0); }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Division by zero");
+        assert_eq!(
+            &source[error.position.start..error.position.end],
+            "b: math.div(1px, 0)"
+        );
+        assert!(compile_source("a { b: red - 1; }").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let source = "@function rem($px, $base: 16px) { @return math.div($px, $base) * 1rem; }
+        @function first-even($list) {
+            @each $n in $list { @if $n % 2 == 0 { @return $n; } }
+            @return null;
+        }
+        @function fib($n) { @if $n < 2 { @return $n; } @return fib($n - 1) + fib($n - 2); }
+        a {
+            font-size: rem(24px);
+            margin: rem(32px, 32px);
+            order: first-even(1 3 4 6) fib(10);
+            transform: translate(rem(16px), -50%);
+        }";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            "a{font-size:1.5rem;margin:1rem;order:4 55;transform:translate(1rem,-50%);}"
+        );
+        // Functions see the scopes where they were defined
+        assert_eq!(
+            compile_source("$x: 1; @function f() { @return $x; } a { $x: 2; b: f(); }").unwrap(),
+            "a{b:1;}"
+        );
+    }
+
+    #[test]
+    fn function_errors() {
+        let source = "@function f($a) { @return $a; } a { b: f(); }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Missing argument $a for function 'f'");
+        assert_eq!(&source[error.position.start..error.position.end], "b: f()");
+
+        let error =
+            compile_source("@function f() { @if false { @return 1; } } a { b: f(); }").unwrap_err();
+        assert_eq!(error.reason, "function 'f' finished without @return");
+        let error =
+            compile_source("@function f() { color: red; @return 1; } a { b: f(); }").unwrap_err();
+        assert_eq!(
+            error.reason,
+            "Functions can only contain variable declarations and control directives"
+        );
+        let error = compile_source("a { @return 1; }").unwrap_err();
+        assert_eq!(error.reason, "@return may only be used within a function");
+        let error =
+            compile_source("@function f($n) { @return f($n); } a { b: f(1); }").unwrap_err();
+        assert_eq!(
+            error.reason,
+            "function 'f' exceeded the maximum call depth of 50"
+        );
+    }
+
+    fn compile_files(files: &[(&str, &str)]) -> Result<String, ParseError> {
+        let mut loader = MemoryFileLoader::new();
+        for (path, content) in files {
+            loader.add_file(path, *content);
+        }
+        let (path, source) = files[0];
+        let source_id = SourceId::new(PathBuf::from(path), source.to_owned());
+        let syntax = Syntax::from_path(Path::new(path));
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), source_id, syntax)?;
+        let options = CompileOptions {
+            file_loader: Box::new(loader),
+            ..Default::default()
+        };
+        compile_with_options(&mut style_sheet, &options)?;
+        raise_nested_rules(&mut style_sheet).unwrap();
+        Ok(style_sheet.to_string(Some(ToStringSettings::minified())))
+    }
+
+    #[test]
+    fn modules() {
+        let config = (
+            "styles/_config.scss",
+            "$primary: red; $-secret: 1; @function double($n) { @return $n * 2; } \
+            @mixin box { padding: 1px; } .config { color: $primary; }",
+        );
+        assert_eq!(
+            compile_files(&[
+                (
+                    "styles/main.scss",
+                    "@use \"config\" as cfg; a { color: cfg.$primary; width: cfg.double(2px); \
+                    @include cfg.box; }"
+                ),
+                config
+            ])
+            .unwrap(),
+            ".config{color:red;}a{color:red;width:4px;padding:1px;}"
+        );
+        assert_eq!(
+            compile_files(&[
+                (
+                    "styles/main.scss",
+                    "@use \"config\" as *; @use \"./config\" as other; a { color: $primary; }"
+                ),
+                config
+            ])
+            .unwrap(),
+            ".config{color:red;}a{color:red;}"
+        );
+        let error = compile_files(&[
+            (
+                "styles/main.scss",
+                "@use \"config\"; a { b: config.$-secret; }",
+            ),
+            config,
+        ])
+        .unwrap_err();
+        assert_eq!(error.reason, "Undefined variable config.$-secret");
+        assert_eq!(
+            compile_files(&[
+                (
+                    "main.scss",
+                    "@use \"lib\"; a { color: lib.$primary; width: lib.double(1px); }"
+                ),
+                (
+                    "lib/_index.scss",
+                    "@forward \"../styles/config\" show $primary, double;"
+                ),
+                config
+            ])
+            .unwrap(),
+            ".config{color:red;}a{color:red;width:2px;}"
+        );
+        let error = compile_files(&[
+            ("main.scss", "@use \"lib\"; a { @include lib.box; }"),
+            ("lib.scss", "@forward \"styles/config\" hide box;"),
+            config,
+        ])
+        .unwrap_err();
+        assert_eq!(error.reason, "Undefined mixin 'lib.box'");
+        // Indented syntax modules
+        assert_eq!(
+            compile_files(&[
+                (
+                    "main.sass",
+                    "@use \"theme\"\na\n  color: theme.$accent // Blue"
+                ),
+                ("_theme.sass", "$accent: blue\n\n.theme\n  color: $accent\n"),
+            ])
+            .unwrap(),
+            ".theme{color:blue;}a{color:blue;}"
+        );
+    }
+
+    #[test]
+    fn imports() {
+        assert_eq!(
+            compile_files(&[
+                (
+                    "main.scss",
+                    "$size: 2px; @import \"vars\"; a { width: $width; }"
+                ),
+                ("_vars.scss", "$width: $size * 2; b { c: d; }")
+            ])
+            .unwrap(),
+            "b{c:d;}a{width:4px;}"
+        );
+        assert_eq!(
+            compile_files(&[("main.scss", "@import \"theme.css\"; a { b: c; }")]).unwrap(),
+            "@import \"theme.css\";a{b:c;}"
+        );
+    }
+
+    #[test]
+    fn module_errors() {
+        let error = compile_files(&[("main.scss", "@use \"missing\";")]).unwrap_err();
+        assert_eq!(error.reason, "Could not find stylesheet 'missing'");
+        let error = compile_files(&[("main.scss", "@use \"a\";"), ("a.scss", "@use \"main\";")])
+            .unwrap_err();
+        assert_eq!(error.reason, "'main.scss' is loaded by itself");
+        let error = compile_files(&[
+            ("main.scss", "@use \"a\"; @use \"b/a\";"),
+            ("a.scss", ""),
+            ("b/a.scss", ""),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            error.reason,
+            "There is already a module with the namespace 'a'"
+        );
+        let error =
+            compile_files(&[("main.scss", "@use \"a\";"), ("a.scss", "a { b: $c; }")]).unwrap_err();
+        assert_eq!(error.reason, "Undefined variable $c");
+        let (path, source) = error.position.source_id.get_file().unwrap();
+        assert_eq!(path, PathBuf::from("a.scss"));
+        assert_eq!(&source[error.position.start..error.position.end], "$c");
+    }
+
+    #[test]
+    fn maps_and_lists() {
+        let source = "$breakpoints: (small: 576px, medium: 768px);
+$theme: map.merge((primary: red), (secondary: blue));
+@each $name, $width in $breakpoints {
+    .container { max-width: $width; }
+}
+a {
+    color: map.get($theme, secondary);
+    width: list.nth(10px 20px 30px, -1);
+    height: list.length([a b c]) * 1px;
+    font-family: string.unquote(\"Inter\");
+    margin: math.round(math.div(10px, 3));
+    content: meta.type-of($theme);
+    grid-template-columns: [full-start] 1fr [full-end];
+}";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            ".container{max-width:576px;}.container{max-width:768px;}a{color:blue;width:30px;\
+            height:3px;font-family:Inter;margin:3px;content:map;\
+            grid-template-columns:[full-start] 1fr [full-end];}"
+        );
+        // Nulls are left out of lists and declarations
+        assert_eq!(
+            compile_source(
+                "$n: null; a { b: 1px $n; c: $n, 2px, $n; d: $n $n; e: meta.type-of($n); \
+                f: #{1px $n}; }"
+            )
+            .unwrap(),
+            "a{b:1px;c:2px;e:null;f:1px;}"
+        );
+        let error = compile_source("$m: (a: 1); a { b: $m; }").unwrap_err();
+        assert_eq!(error.reason, "(a:1) isn't a valid CSS value");
+        let error = compile_source("a { b: (a: 1, a: 2); }").unwrap_err();
+        assert_eq!(error.reason, "Duplicate key a in map");
+        let error = compile_source("a { b: list.nth(a b, 3); }").unwrap_err();
+        assert_eq!(error.reason, "Invalid index 3 for a list with 2 elements");
+        let error = compile_source("a { b: map.missing(1); }").unwrap_err();
+        assert_eq!(error.reason, "Undefined function map.missing");
+    }
+
+    #[test]
+    fn built_in_modules() {
+        assert_eq!(
+            compile_source("@use \"sass:math\"; a { w: math.div(10px, 2) }").unwrap(),
+            "a{w:5px;}"
+        );
+        assert_eq!(
+            compile_source(
+                "@use \"sass:map\" as m; @use \"sass:math\" as *; \
+                a { w: m.get((a: 2px), a); h: round(div(10px, 3)); }"
+            )
+            .unwrap(),
+            "a{w:2px;h:3px;}"
+        );
+        // Declarations with a `null` value are removed
+        assert_eq!(
+            compile_source(
+                "@use \"sass:map\"; $m: (a: 1px); a { w: map.get($m, b); h: map.get($m, a); }"
+            )
+            .unwrap(),
+            "a{h:1px;}"
+        );
+        let error = compile_source("@use \"sass:math\"; @use \"math\";").unwrap_err();
+        assert_eq!(
+            error.reason,
+            "There is already a module with the namespace 'math'"
+        );
+        let error = compile_source("@use \"sass:maths\";").unwrap_err();
+        assert_eq!(error.reason, "Unknown built-in module 'sass:maths'");
+    }
+
+    #[test]
+    fn interpolation() {
+        let source = "$name: search; $side: top; $breakpoint: 600px; $property: position;
+.icon-#{$name} { background: url(\"/icons/#{$name}.svg\"); margin-#{$side}: 1px; }
+@each $size in small, large { .btn-#{$size}, #{$size}-button { width: #{$size}-width; } }
+@media (min-width: #{$breakpoint}) { a { color: red; } }
+@media #{\"screen and (max-width: 100px)\"} { b { color: blue; } }
+@supports (#{$property}: sticky) { c { top: 0; } }";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            ".icon-search{background:url(\"/icons/search.svg\");margin-top:1px;}\
+            .btn-small,small-button{width:small-width;}.btn-large,large-button{width:large-width;}\
+            @media (min-width:600px){a{color:red;}}\
+            @media screen and (max-width:100px){b{color:blue;}}\
+            @supports (position: sticky){c{top:0;}}"
+        );
+        // Variables and interpolation in unquoted urls
+        let source = "$image: \"a.png\"; $base: \"/img\";
+a { b: url($image); c: url( #{$base}/c.png ); d: url(d.png); e: url(#{$missing}); }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Undefined variable $missing");
+        assert_eq!(
+            &source[error.position.start..error.position.end],
+            "$missing"
+        );
+        assert_eq!(
+            compile_source(&source.replace(" e: url(#{$missing});", "")).unwrap(),
+            "a{b:url(\"a.png\");c:url(/img/c.png);d:url(d.png);}"
+        );
+        let source = "$a: \".x,\"; #{$a} { b: c; }";
+        let error = compile_source(source).unwrap_err();
+        assert_eq!(error.reason, "Expected valid selector found 'EOS' in '.x,'");
+        assert_eq!(&source[error.position.start..error.position.end], "#{$a}");
+    }
+
+    #[test]
+    fn colors() {
+        let source = "$primary: #3c78b4;
+@function tint($color, $amount: 50%) { @return mix(white, $color, $amount); }
+a {
+    color: darken($primary, 10%);
+    background: rgba($primary, 0.5);
+    border-color: color.adjust($primary, $lightness: 10%, $alpha: -0.25);
+    outline-color: tint($amount: 100%, $color: red);
+    box-shadow: 0 0 1px rgba(0, 0, 0, 0.2);
+}";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            "a{color:#2f5f8e;background:rgba(60,120,180,.5);\
+            border-color:rgba(91,146,200,.75);outline-color:#fff;\
+            box-shadow:0 0 1px rgba(0,0,0,0.2);}"
+        );
+    }
+
+    #[test]
+    fn native_functions() {
+        let mut options = CompileOptions::default();
+        options
+            .functions
+            .register("rem", |arguments| match arguments.as_slice() {
+                [value] => match value.as_number() {
+                    Some((pixels, "px")) => Ok(CSSValue::from_number(pixels / 16.0, "rem")),
+                    _ => Err("rem takes a px value".to_owned()),
+                },
+                _ => Err("rem takes 1 argument".to_owned()),
+            });
+        let source = "$size: 24px; a { font-size: rem($size); width: math.div(1, 4); }";
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Scss)
+                .unwrap();
+        compile_with_options(&mut style_sheet, &options).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "a{font-size:1.5rem;width:0.25;}"
+        );
+        let mut style_sheet = StyleSheet::from_string_with_syntax(
+            "a { b: rem(1em); }".to_owned(),
+            SourceId::null(),
+            Syntax::Scss,
+        )
+        .unwrap();
+        let error = compile_with_options(&mut style_sheet, &options).unwrap_err();
+        assert_eq!(error.reason, "rem takes a px value");
+    }
+
+    #[test]
+    fn custom_properties() {
+        let source = "$gap: 2px; :root { --gap: #{$gap * 2}; --theme: $gap or dark; \
+            --empty:; --block: { a: b }; --x: 1px !important; }";
+        assert_eq!(
+            compile_source(source).unwrap(),
+            ":root{--gap:4px;--theme:$gap or dark;--empty:;--block:{ a: b };--x:1px!important;}"
+        );
+    }
+
+    #[test]
+    fn plain_css() {
+        let source = ":root { --b: x or y; --c: 1 < 2; } \
+            a { u: 1 == 1; v: x or y; w: not a; x: 2 + 3; y: rgba(0, 0, 0, .5); }";
+        let mut style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        if let Entry::Rule(rule) = &style_sheet.entries[1] {
+            assert!(matches!(rule.declarations[1].value, CSSValue::List(_)));
+            assert!(matches!(rule.declarations[2].value, CSSValue::List(_)));
+        } else {
+            panic!("Expected rule, found {:?}", style_sheet.entries[1]);
+        }
+        compile(&mut style_sheet).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            ":root{--b:x or y;--c:1 < 2;}\
+            a{u:1 == 1;v:x or y;w:not a;x:2 + 3;y:rgba(0,0,0,.5);}"
+        );
+    }
+
+    #[test]
+    fn uncompiled_to_string() {
+        let source = "$x: 1px !default;\n\na {\n    $y: $x;\n    width: $y;\n}";
+        let style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        assert_eq!(style_sheet.to_string(None), source);
+    }
+}
@@ -0,0 +1,157 @@
+use super::{
+    token_as_ident, ASTNode, CSSToken, CSSValue, Interpolation, ParseError, ToStringSettings,
+};
+use source_map::{Span, ToString};
+use tokenizer_lib::{Token, TokenReader};
+
+/// A property declaration e.g. `color: red !important`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: String,
+    /// Name containing SCSS interpolation e.g. `margin-#{$side}`. `name` holds it as written until
+    /// [`compile`](crate::compile) replaces it with the evaluated name
+    pub interpolated_name: Option<Interpolation>,
+    pub value: CSSValue,
+    /// Whether the declaration ends with `!important`
+    pub important: bool,
+    pub position: Option<Span>,
+}
+
+impl ASTNode for Declaration {
+    /// Parses `name: value !important`. Does not consume the trailing `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let is_interpolated = Interpolation::is_ahead(reader, |token| {
+            matches!(
+                token,
+                CSSToken::Colon | CSSToken::SemiColon | CSSToken::OpenCurly | CSSToken::CloseCurly
+            )
+        });
+        let (name, interpolated_name, start_span) = if is_interpolated {
+            let interpolation =
+                Interpolation::from_reader_until(reader, |token| *token == CSSToken::Colon)?;
+            let start_span = interpolation.position.clone();
+            (
+                interpolation.to_source_string(),
+                Some(interpolation),
+                start_span,
+            )
+        } else {
+            let (name, start_span) = token_as_ident(reader.next().unwrap())?;
+            (name, None, start_span)
+        };
+        reader.expect_next(CSSToken::Colon)?;
+
+        let mut end_span =
+            CSSValue::end_position_ahead(reader).unwrap_or_else(|| start_span.clone());
+
+        let value = if let Some(Token(CSSToken::CustomPropertyValue(_), _)) = reader.peek() {
+            // Only interpolation is evaluated in custom properties
+            match reader.next().unwrap() {
+                Token(CSSToken::CustomPropertyValue(value), position) if value.contains("#{") => {
+                    CSSValue::Interpolation(Interpolation::from_text(
+                        &value,
+                        position.start,
+                        &position,
+                    )?)
+                }
+                Token(CSSToken::CustomPropertyValue(value), _) => CSSValue::Keyword(value),
+                _ => unreachable!(),
+            }
+        } else {
+            CSSValue::from_reader(reader)?
+        };
+        let important = if let Some(Token(CSSToken::Exclamation, _)) = reader.peek() {
+            reader.next();
+            match reader.next().unwrap() {
+                Token(CSSToken::Ident(ident), position)
+                    if ident.eq_ignore_ascii_case("important") =>
+                {
+                    end_span = position;
+                    true
+                }
+                Token(token, position) => {
+                    return Err(ParseError {
+                        reason: format!("Expected 'important' after '!' found '{}'", token),
+                        position,
+                    });
+                }
+            }
+        } else {
+            false
+        };
+        Ok(Self {
+            name,
+            interpolated_name,
+            value,
+            important,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str(&self.name);
+        buf.push(':');
+        if !settings.minify {
+            buf.push(' ');
+        }
+        self.value.to_string_from_buffer(buf, settings, depth);
+        if self.important {
+            if !settings.minify {
+                buf.push(' ');
+            }
+            buf.push_str("!important");
+        }
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod declaration_tests {
+    use super::*;
+    use source_map::SourceId;
+
+    #[test]
+    fn important() {
+        let declaration = Declaration::from_string(
+            "color: rgb(0, 0, 0) !IMPORTANT".to_owned(),
+            SourceId::null(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(declaration.name, "color");
+        assert!(declaration.important);
+        assert_eq!(declaration.position.as_ref().unwrap().start, 0);
+        assert_eq!(declaration.position.as_ref().unwrap().end, 30);
+        assert_eq!(
+            declaration.to_string(&ToStringSettings::default()),
+            "color: rgb(0, 0, 0) !important"
+        );
+        assert_eq!(
+            declaration.to_string(&ToStringSettings::minified()),
+            "color:rgb(0,0,0)!important"
+        );
+    }
+
+    #[test]
+    fn position() {
+        let declaration =
+            Declaration::from_string("margin: 0 auto".to_owned(), SourceId::null(), None).unwrap();
+        assert!(!declaration.important);
+        assert_eq!(declaration.position.as_ref().unwrap().end, 14);
+        assert!(
+            Declaration::from_string("color: red !default".to_owned(), SourceId::null(), None)
+                .is_err()
+        );
+    }
+}
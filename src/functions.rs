@@ -0,0 +1,475 @@
+use super::{ASTNode, BinaryOperator, CSSValue, Color, ToStringSettings};
+use crate::compiler::{arithmetic, list_items, null};
+use source_map::{SourceId, Span};
+use std::{collections::HashMap, convert::TryInto};
+
+/// A function implemented in Rust which can be called from SCSS. Takes the evaluated arguments,
+/// with keyword arguments as [`CSSValue::NamedArgument`], and returns the value or the reason for
+/// an error
+pub type NativeFunction = Box<dyn Fn(Vec<CSSValue>) -> Result<CSSValue, String>>;
+
+/// Native functions callable by name during [`compile`](crate::compile). The default registry has
+/// the built-in module functions e.g. `map.get`, `list.nth` and `math.div`, the colour
+/// functions e.g. `darken` and the Less type and colour functions e.g. `iscolor` and `fade`
+pub struct FunctionRegistry {
+    functions: HashMap<String, NativeFunction>,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("map.get", map_get);
+        registry.register("map.merge", map_merge);
+        registry.register("list.nth", list_nth);
+        registry.register("list.length", list_length);
+        registry.register("string.unquote", string_unquote);
+        registry.register("math.round", math_round);
+        registry.register("math.div", math_div);
+        registry.register("meta.type-of", meta_type_of);
+        registry.register("lighten", |arguments| {
+            adjust_lightness("lighten", arguments, 1.0)
+        });
+        registry.register("darken", |arguments| {
+            adjust_lightness("darken", arguments, -1.0)
+        });
+        registry.register("mix", mix);
+        registry.register("rgba", rgba);
+        registry.register("color.adjust", color_adjust);
+        registry.register("iscolor", |arguments| {
+            is_type("iscolor", arguments, |value| {
+                Color::from_value(value).is_some()
+            })
+        });
+        registry.register("isnumber", |arguments| {
+            is_type("isnumber", arguments, |value| value.as_number().is_some())
+        });
+        registry.register("isstring", |arguments| {
+            is_type("isstring", arguments, |value| {
+                matches!(value, CSSValue::StringLiteral(_))
+            })
+        });
+        registry.register("iskeyword", |arguments| {
+            is_type("iskeyword", arguments, |value| {
+                matches!(value, CSSValue::Keyword(_)) && Color::from_value(value).is_none()
+            })
+        });
+        for (name, unit) in [("ispixel", "px"), ("ispercentage", "%"), ("isem", "em")] {
+            registry.register(name, move |arguments| {
+                is_type(name, arguments, |value| {
+                    matches!(value.as_number(), Some((_, value_unit)) if value_unit == unit)
+                })
+            });
+        }
+        registry.register("lightness", lightness);
+        registry.register("fade", fade);
+        registry
+    }
+}
+
+impl FunctionRegistry {
+    /// A registry without the built-in functions
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Adds a function. Replaces any function with the same name
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        function: impl Fn(Vec<CSSValue>) -> Result<CSSValue, String> + 'static,
+    ) {
+        self.functions.insert(name.into(), Box::new(function));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.get(name)
+    }
+}
+
+fn to_string(value: &CSSValue) -> String {
+    value.to_string(&ToStringSettings::minified())
+}
+
+/// Checks the number of arguments, returning them as an array
+fn expect_arguments<const N: usize>(
+    name: &str,
+    arguments: Vec<CSSValue>,
+) -> Result<[CSSValue; N], String> {
+    let count = arguments.len();
+    arguments.try_into().map_err(|_| {
+        format!(
+            "{} takes {} argument{} but {} were passed",
+            name,
+            N,
+            if N == 1 { "" } else { "s" },
+            count
+        )
+    })
+}
+
+/// Entries of a map. An empty list is an empty map
+fn map_entries(value: CSSValue) -> Result<Vec<(CSSValue, CSSValue)>, String> {
+    match value {
+        CSSValue::Map(entries) => Ok(entries),
+        CSSValue::List(items) | CSSValue::CommaSeparatedList(items) if items.is_empty() => {
+            Ok(Vec::new())
+        }
+        value => Err(format!("{} is not a map", to_string(&value))),
+    }
+}
+
+fn map_get(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    if arguments.len() < 2 {
+        return Err(format!(
+            "map.get takes at least 2 arguments but {} were passed",
+            arguments.len()
+        ));
+    }
+    let mut arguments = arguments.into_iter();
+    let mut value = arguments.next().unwrap();
+    // Further keys get from nested maps
+    for key in arguments {
+        let found = map_entries(value)?
+            .into_iter()
+            .find(|(entry_key, _)| entry_key.scss_equals(&key));
+        match found {
+            Some((_, found)) => value = found,
+            None => return Ok(null()),
+        }
+    }
+    Ok(value)
+}
+
+fn map_merge(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [map, other] = expect_arguments("map.merge", arguments)?;
+    let mut entries = map_entries(map)?;
+    for (key, value) in map_entries(other)? {
+        match entries
+            .iter_mut()
+            .find(|(entry_key, _)| entry_key.scss_equals(&key))
+        {
+            Some(entry) => entry.1 = value,
+            None => entries.push((key, value)),
+        }
+    }
+    Ok(CSSValue::Map(entries))
+}
+
+fn list_nth(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [list, n] = expect_arguments("list.nth", arguments)?;
+    let mut items = list_items(list);
+    let index = match n.as_number() {
+        Some((n, "")) if n.fract() == 0.0 => n as i64,
+        _ => {
+            return Err(format!(
+                "Expected an integer index, found {}",
+                to_string(&n)
+            ))
+        }
+    };
+    // Negative indices count from the end
+    let position = if index < 0 {
+        items.len() as i64 + index
+    } else {
+        index - 1
+    };
+    if index == 0 || position < 0 || position >= items.len() as i64 {
+        return Err(format!(
+            "Invalid index {} for a list with {} element{}",
+            index,
+            items.len(),
+            if items.len() == 1 { "" } else { "s" }
+        ));
+    }
+    Ok(items.swap_remove(position as usize))
+}
+
+fn list_length(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [list] = expect_arguments("list.length", arguments)?;
+    Ok(CSSValue::from_number(list_items(list).len() as f64, ""))
+}
+
+fn string_unquote(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    match expect_arguments("string.unquote", arguments)? {
+        [CSSValue::StringLiteral(content) | CSSValue::Keyword(content)] => {
+            Ok(CSSValue::Keyword(content))
+        }
+        [value] => Err(format!("{} is not a string", to_string(&value))),
+    }
+}
+
+fn math_round(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [value] = expect_arguments("math.round", arguments)?;
+    match value.as_number() {
+        Some((number, unit)) => Ok(CSSValue::from_number(number.round(), unit)),
+        None => Err(format!("{} is not a number", to_string(&value))),
+    }
+}
+
+fn math_div(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [lhs, rhs] = expect_arguments("math.div", arguments)?;
+    if lhs.as_number().is_none() || rhs.as_number().is_none() {
+        return Err(format!(
+            "Expected numbers for math.div, found '{}' and '{}'",
+            to_string(&lhs),
+            to_string(&rhs)
+        ));
+    }
+    let position = Span {
+        start: 0,
+        end: 0,
+        source_id: SourceId::null(),
+    };
+    arithmetic(&lhs, BinaryOperator::Divide, &rhs, position).map_err(|error| error.reason)
+}
+
+fn meta_type_of(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [value] = expect_arguments("meta.type-of", arguments)?;
+    let type_name = match value {
+        CSSValue::Number(_) | CSSValue::NumberWithUnit(..) | CSSValue::Percentage(_) => "number",
+        CSSValue::Keyword(keyword) if keyword == "true" || keyword == "false" => "bool",
+        CSSValue::Null => "null",
+        ref value if Color::from_value(value).is_some() => "color",
+        CSSValue::List(_) | CSSValue::CommaSeparatedList(_) | CSSValue::Bracketed(_) => "list",
+        CSSValue::Map(_) => "map",
+        _ => "string",
+    };
+    Ok(CSSValue::Keyword(type_name.to_owned()))
+}
+
+fn color_argument(name: &str, value: &CSSValue) -> Result<Color, String> {
+    Color::from_value(value).ok_or_else(|| format!("{}: {} is not a color", name, to_string(value)))
+}
+
+/// A percentage from 0 to 100. The `%` is optional
+fn percentage_argument(name: &str, value: &CSSValue) -> Result<f64, String> {
+    match value.as_number() {
+        Some((amount, "" | "%")) if (0.0..=100.0).contains(&amount) => Ok(amount),
+        _ => Err(format!(
+            "{}: Expected {} to be a percentage between 0% and 100%",
+            name,
+            to_string(value)
+        )),
+    }
+}
+
+/// `lighten` and `darken`. Adds `direction` times the amount to the HSL lightness
+fn adjust_lightness(
+    name: &str,
+    arguments: Vec<CSSValue>,
+    direction: f64,
+) -> Result<CSSValue, String> {
+    let [color, amount] = expect_arguments(name, arguments)?;
+    let color = color_argument(name, &color)?;
+    let amount = percentage_argument(name, &amount)?;
+    let (hue, saturation, lightness) = color.to_hsl();
+    let lightness = lightness + direction * amount;
+    Ok(Color::from_hsl(hue, saturation, lightness, color.alpha).to_value())
+}
+
+/// The Less type functions e.g. `iscolor(@value)`
+fn is_type(
+    name: &str,
+    arguments: Vec<CSSValue>,
+    is_type: impl Fn(&CSSValue) -> bool,
+) -> Result<CSSValue, String> {
+    let [value] = expect_arguments(name, arguments)?;
+    Ok(CSSValue::from_bool(is_type(&value)))
+}
+
+/// `lightness(@color)`. The HSL lightness as a percentage
+fn lightness(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [color] = expect_arguments("lightness", arguments)?;
+    let (_, _, lightness) = color_argument("lightness", &color)?.to_hsl();
+    Ok(CSSValue::from_number(lightness, "%"))
+}
+
+/// `fade(@color, @amount)`. Sets the alpha to the percentage
+fn fade(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let [color, amount] = expect_arguments("fade", arguments)?;
+    let color = color_argument("fade", &color)?;
+    let alpha = percentage_argument("fade", &amount)? / 100.0;
+    Ok(Color { alpha, ..color }.to_value())
+}
+
+/// `mix($color1, $color2, $weight: 50%)`
+fn mix(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let mut positional = Vec::new();
+    let mut weight = None;
+    for argument in arguments {
+        match argument {
+            CSSValue::NamedArgument(name, value) if name == "weight" => weight = Some(*value),
+            CSSValue::NamedArgument(name, _) => {
+                return Err(format!("No argument named ${} for mix", name))
+            }
+            value => positional.push(value),
+        }
+    }
+    if positional.len() == 3 && weight.is_none() {
+        weight = positional.pop();
+    }
+    let [color1, color2] = expect_arguments("mix", positional)?;
+    let weight = match weight {
+        Some(weight) => percentage_argument("mix", &weight)? / 100.0,
+        None => 0.5,
+    };
+    let color1 = color_argument("mix", &color1)?;
+    let color2 = color_argument("mix", &color2)?;
+    Ok(color1.mix(color2, weight).to_value())
+}
+
+/// `rgba($color, $alpha)`. Other `rgba()` calls are left as CSS
+fn rgba(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    if let [color, alpha] = arguments.as_slice() {
+        if let (Some(color), Some((alpha, unit))) = (Color::from_value(color), alpha.as_number()) {
+            let alpha = if unit == "%" { alpha / 100.0 } else { alpha };
+            return Ok(Color { alpha, ..color }.to_value());
+        }
+    }
+    Ok(CSSValue::Function("rgba".to_owned(), arguments))
+}
+
+/// `color.adjust($color, $red: 0, $green: 0, $blue: 0, $hue: 0, $saturation: 0%,
+/// $lightness: 0%, $alpha: 0)`. Adds to the channels
+fn color_adjust(arguments: Vec<CSSValue>) -> Result<CSSValue, String> {
+    let mut arguments = arguments.into_iter();
+    let color = match arguments.next() {
+        Some(CSSValue::NamedArgument(..)) | None => {
+            return Err("color.adjust takes a color as its first argument".to_owned())
+        }
+        Some(color) => color_argument("color.adjust", &color)?,
+    };
+    let mut rgb = [0.0; 3];
+    let mut hsl = [0.0; 3];
+    let mut alpha = 0.0;
+    for argument in arguments {
+        let (name, value) = match argument {
+            CSSValue::NamedArgument(name, value) => (name, value),
+            value => {
+                return Err(format!(
+                    "color.adjust only takes one positional argument, found {}",
+                    to_string(&value)
+                ))
+            }
+        };
+        let amount = match value.as_number() {
+            Some((amount, _)) => amount,
+            None => return Err(format!("${}: {} is not a number", name, to_string(&value))),
+        };
+        match name.as_str() {
+            "red" => rgb[0] = amount,
+            "green" => rgb[1] = amount,
+            "blue" => rgb[2] = amount,
+            "hue" => hsl[0] = amount,
+            "saturation" => hsl[1] = amount,
+            "lightness" => hsl[2] = amount,
+            "alpha" => alpha = amount,
+            _ => return Err(format!("No argument named ${} for color.adjust", name)),
+        }
+    }
+    let mut color = Color::new(
+        color.red + rgb[0],
+        color.green + rgb[1],
+        color.blue + rgb[2],
+        color.alpha + alpha,
+    );
+    if hsl != [0.0; 3] {
+        let (hue, saturation, lightness) = color.to_hsl();
+        color = Color::from_hsl(
+            hue + hsl[0],
+            saturation + hsl[1],
+            lightness + hsl[2],
+            color.alpha,
+        );
+    }
+    Ok(color.to_value())
+}
+
+#[cfg(test)]
+mod functions_tests {
+    use super::*;
+
+    fn call(name: &str, arguments: &[&str]) -> Result<String, String> {
+        let arguments = arguments
+            .iter()
+            .map(|argument| {
+                CSSValue::from_string((*argument).to_owned(), SourceId::null(), None).unwrap()
+            })
+            .collect();
+        let registry = FunctionRegistry::default();
+        registry.get(name).unwrap()(arguments).map(|value| to_string(&value))
+    }
+
+    #[test]
+    fn built_in_functions() {
+        assert_eq!(call("map.get", &["(a: 1, b: 2)", "b"]), Ok("2".to_owned()));
+        assert_eq!(call("map.get", &["(a: 1)", "c"]), Ok("null".to_owned()));
+        assert_eq!(
+            call("map.get", &["(a: (b: red))", "a", "b"]),
+            Ok("red".to_owned())
+        );
+        assert_eq!(
+            call("map.merge", &["(a: 1, b: 2)", "(b: 3, c: 4)"]),
+            Ok("(a:1,b:3,c:4)".to_owned())
+        );
+        assert_eq!(call("list.nth", &["a b c", "2"]), Ok("b".to_owned()));
+        assert_eq!(call("list.nth", &["a, b, c", "-1"]), Ok("c".to_owned()));
+        assert_eq!(
+            call("list.nth", &["a b", "3"]),
+            Err("Invalid index 3 for a list with 2 elements".to_owned())
+        );
+        assert_eq!(call("list.length", &["[a b c]"]), Ok("3".to_owned()));
+        assert_eq!(call("list.length", &["(a: 1, b: 2)"]), Ok("2".to_owned()));
+        assert_eq!(call("string.unquote", &["\"a b\""]), Ok("a b".to_owned()));
+        assert_eq!(call("math.round", &["2.5px"]), Ok("3px".to_owned()));
+        assert_eq!(call("math.div", &["10px", "4"]), Ok("2.5px".to_owned()));
+        assert_eq!(call("meta.type-of", &["(a: 1)"]), Ok("map".to_owned()));
+        assert_eq!(call("meta.type-of", &["1px 2px"]), Ok("list".to_owned()));
+        assert_eq!(call("meta.type-of", &["true"]), Ok("bool".to_owned()));
+        assert_eq!(
+            call("map.get", &["a"]),
+            Err("map.get takes at least 2 arguments but 1 were passed".to_owned())
+        );
+        assert_eq!(
+            call("map.merge", &["1", "(a: 1)"]),
+            Err("1 is not a map".to_owned())
+        );
+    }
+
+    #[test]
+    fn color_functions() {
+        assert_eq!(
+            call("darken", &["#3c78b4", "10%"]),
+            Ok("#2f5f8e".to_owned())
+        );
+        assert_eq!(call("lighten", &["red", "50"]), Ok("#fff".to_owned()));
+        assert_eq!(call("darken", &["white", "100%"]), Ok("#000".to_owned()));
+        assert_eq!(call("mix", &["#f00", "#00f"]), Ok("purple".to_owned()));
+        assert_eq!(
+            call("mix", &["#f00", "#00f", "25%"]),
+            Ok("#4000bf".to_owned())
+        );
+        assert_eq!(
+            call("rgba", &["#000", "0.5"]),
+            Ok("rgba(0,0,0,.5)".to_owned())
+        );
+        assert_eq!(
+            call("rgba", &["0, 0, 0, 1"]),
+            Ok("rgba(0,0,0,1)".to_owned())
+        );
+        assert_eq!(
+            call("meta.type-of", &["rgb(1, 2, 3)"]),
+            Ok("color".to_owned())
+        );
+        assert_eq!(
+            call("darken", &["auto", "10%"]),
+            Err("darken: auto is not a color".to_owned())
+        );
+        assert_eq!(
+            call("lighten", &["red", "150%"]),
+            Err("lighten: Expected 150% to be a percentage between 0% and 100%".to_owned())
+        );
+    }
+}
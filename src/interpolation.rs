@@ -0,0 +1,239 @@
+use super::{ASTNode, CSSToken, CSSValue, ParseError, ToStringSettings};
+use source_map::{Span, ToString};
+use std::mem;
+use tokenizer_lib::{Token, TokenReader};
+
+/// Text containing SCSS interpolated expressions e.g. `icon-#{$name}`. Replaced by the text with
+/// the values of the expressions during compilation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpolation {
+    pub parts: Vec<InterpolationPart>,
+    pub position: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpolationPart {
+    Text(String),
+    /// `#{$expression}`
+    Expression(CSSValue),
+}
+
+impl Interpolation {
+    /// Whether a `#{` comes before the first token which `is_end` returns true for. Does not
+    /// advance the reader
+    pub(crate) fn is_ahead(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        is_end: impl Fn(&CSSToken) -> bool,
+    ) -> bool {
+        let mut is_interpolated = false;
+        reader.scan(|token, _| match token {
+            CSSToken::InterpolationStart => {
+                is_interpolated = true;
+                true
+            }
+            // Scanning stops at the end of the source rather than on the `EOS`, which would wait
+            // for a token after it
+            CSSToken::EOS => false,
+            token => is_end(token),
+        });
+        is_interpolated
+    }
+
+    /// Whether the next tokens are a word containing a `#{` e.g. `icon-#{$name}`. Words are
+    /// identifiers, numbers and interpolations without whitespace between them
+    pub(crate) fn is_word_ahead(reader: &mut impl TokenReader<CSSToken, Span>) -> bool {
+        let mut last_span: Option<Span> = None;
+        let mut in_interpolation = false;
+        let mut is_interpolated = false;
+        reader.scan(|token, position| {
+            if let CSSToken::EOS = token {
+                return false;
+            }
+            if in_interpolation {
+                if let CSSToken::CloseCurly = token {
+                    in_interpolation = false;
+                    last_span = Some(position.clone());
+                }
+                return false;
+            }
+            if matches!(last_span, Some(ref last_span) if !last_span.is_adjacent_to(position)) {
+                return true;
+            }
+            last_span = Some(position.clone());
+            match token {
+                CSSToken::InterpolationStart => {
+                    is_interpolated = true;
+                    in_interpolation = true;
+                    false
+                }
+                token => !Self::is_word_token(token),
+            }
+        });
+        is_interpolated
+    }
+
+    fn is_word_token(token: &CSSToken) -> bool {
+        matches!(
+            token,
+            CSSToken::Ident(_) | CSSToken::Number(_) | CSSToken::Minus | CSSToken::Percentage
+        )
+    }
+
+    /// Parses tokens up to (but not including) the first token outside of a `#{...}` which
+    /// `is_end` returns true for. Tokens are kept as text, separated by a space where there is
+    /// whitespace between them
+    pub(crate) fn from_reader_until(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        is_end: impl Fn(&CSSToken) -> bool,
+    ) -> Result<Self, ParseError> {
+        Self::parts_from_reader(reader, |token, _| is_end(token))
+    }
+
+    /// Parses a word containing interpolation, see [`Interpolation::is_word_ahead`]
+    pub(crate) fn word_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<Self, ParseError> {
+        Self::parts_from_reader(reader, |token, is_adjacent| {
+            !is_adjacent || !(Self::is_word_token(token) || *token == CSSToken::InterpolationStart)
+        })
+    }
+
+    /// `is_end` is passed the next token and whether it directly follows the previous token
+    fn parts_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        is_end: impl Fn(&CSSToken, bool) -> bool,
+    ) -> Result<Self, ParseError> {
+        let start_span = reader.peek().unwrap().1.clone();
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut last_span: Option<Span> = None;
+        loop {
+            let Token(token, position) = reader.peek().unwrap();
+            let is_adjacent = match last_span {
+                Some(ref last_span) => last_span.is_adjacent_to(position),
+                None => true,
+            };
+            if *token == CSSToken::EOS || is_end(token, is_adjacent) {
+                break;
+            }
+            let Token(token, position) = reader.next().unwrap();
+            if let CSSToken::Comment(_) = token {
+                continue;
+            }
+            if !is_adjacent {
+                text.push(' ');
+            }
+            if let CSSToken::InterpolationStart = token {
+                let value = CSSValue::from_reader(reader)?;
+                last_span = Some(reader.expect_next(CSSToken::CloseCurly)?);
+                if !text.is_empty() {
+                    parts.push(InterpolationPart::Text(mem::take(&mut text)));
+                }
+                parts.push(InterpolationPart::Expression(value));
+            } else {
+                text.push_str(&token.to_string());
+                last_span = Some(position);
+            }
+        }
+        if !text.is_empty() {
+            parts.push(InterpolationPart::Text(text));
+        }
+        let position = match last_span {
+            Some(last_span) => start_span.union(&last_span),
+            None => start_span,
+        };
+        Ok(Self { parts, position })
+    }
+
+    /// Parses the content of a quoted string token containing `#{`. `position` is the position
+    /// of the string token
+    pub(crate) fn from_string_content(content: &str, position: &Span) -> Result<Self, ParseError> {
+        // After the opening quote
+        Self::from_text(content, position.start + 1, position)
+    }
+
+    /// Parses text containing `#{` which starts at `content_start` in the source, e.g. the value
+    /// of a custom property. The interpolation has `position`
+    pub(crate) fn from_text(
+        content: &str,
+        content_start: usize,
+        position: &Span,
+    ) -> Result<Self, ParseError> {
+        let mut parts = Vec::new();
+        let mut rest_start = 0;
+        while let Some(start) = content[rest_start..].find("#{") {
+            let expression_start = rest_start + start + 2;
+            let mut depth = 0u32;
+            let expression_end = content[expression_start..]
+                .char_indices()
+                .find_map(|(idx, chr)| match chr {
+                    '{' => {
+                        depth += 1;
+                        None
+                    }
+                    '}' if depth == 0 => Some(expression_start + idx),
+                    '}' => {
+                        depth -= 1;
+                        None
+                    }
+                    _ => None,
+                })
+                .ok_or_else(|| ParseError {
+                    reason: "Could not find closing '}' for '#{'".to_owned(),
+                    position: position.clone(),
+                })?;
+            if start > 0 {
+                parts.push(InterpolationPart::Text(
+                    content[rest_start..(rest_start + start)].to_owned(),
+                ));
+            }
+            let value = CSSValue::from_string(
+                content[expression_start..expression_end].to_owned(),
+                position.source_id,
+                Some(content_start + expression_start),
+            )?;
+            parts.push(InterpolationPart::Expression(value));
+            rest_start = expression_end + 1;
+        }
+        if rest_start < content.len() {
+            parts.push(InterpolationPart::Text(content[rest_start..].to_owned()));
+        }
+        Ok(Self {
+            parts,
+            position: position.clone(),
+        })
+    }
+
+    /// Whether any part is a `#{...}`
+    pub(crate) fn has_expressions(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, InterpolationPart::Expression(_)))
+    }
+
+    /// Prints as it would appear in source
+    pub(crate) fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        for part in self.parts.iter() {
+            match part {
+                InterpolationPart::Text(text) => buf.push_str(text),
+                InterpolationPart::Expression(value) => {
+                    buf.push_str("#{");
+                    value.to_string_from_buffer(buf, settings, depth);
+                    buf.push('}');
+                }
+            }
+        }
+    }
+
+    /// Text as it would appear in source
+    pub(crate) fn to_source_string(&self) -> String {
+        let mut buf = String::new();
+        self.to_string_from_buffer(&mut buf, &ToStringSettings::default(), 0);
+        buf
+    }
+}
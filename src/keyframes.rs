@@ -0,0 +1,334 @@
+use super::{
+    recover, token_as_ident, ASTNode, CSSToken, Declaration, Number, ParseError, ToStringSettings,
+};
+use source_map::{Span, ToString};
+use tokenizer_lib::{Token, TokenReader};
+
+/// A [`@keyframes`](https://developer.mozilla.org/en-US/docs/Web/CSS/@keyframes) rule
+#[derive(Debug)]
+pub struct KeyframesRule {
+    /// e.g. `-webkit-` for `@-webkit-keyframes`
+    pub vendor_prefix: Option<String>,
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+    pub position: Option<Span>,
+}
+
+/// A block in a `@keyframes` rule e.g. `from, 50% { opacity: 0; }`
+#[derive(Debug)]
+pub struct Keyframe {
+    pub selectors: Vec<KeyframeSelector>,
+    pub declarations: Vec<Declaration>,
+    pub position: Option<Span>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyframeSelector {
+    /// Equivalent to `0%`
+    From,
+    /// Equivalent to `100%`
+    To,
+    Percentage(Number),
+}
+
+impl KeyframesRule {
+    /// Returns the vendor prefix if the at-rule name is for a keyframes rule
+    pub(crate) fn is_keyframes_at_rule(name: &str) -> Option<Option<&str>> {
+        let prefix_length = name.len().checked_sub("keyframes".len())?;
+        if !name[prefix_length..].eq_ignore_ascii_case("keyframes") {
+            return None;
+        }
+        match &name[..prefix_length] {
+            "" => Some(None),
+            prefix @ ("-webkit-" | "-moz-" | "-o-" | "-ms-") => Some(Some(prefix)),
+            _ => None,
+        }
+    }
+}
+
+impl ASTNode for KeyframesRule {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        Self::from_reader_with_recovery(reader, None)
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push('@');
+        if let Some(ref vendor_prefix) = self.vendor_prefix {
+            buf.push_str(vendor_prefix);
+        }
+        buf.push_str("keyframes ");
+        buf.push_str(&self.name);
+        if !settings.minify {
+            buf.push(' ');
+        }
+        buf.push('{');
+        for (idx, keyframe) in self.keyframes.iter().enumerate() {
+            if !settings.minify {
+                if idx > 0 {
+                    buf.push_new_line();
+                }
+                buf.push_new_line();
+                buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
+            }
+            keyframe.to_string_from_buffer(buf, settings, depth + 1);
+        }
+        if !settings.minify && !self.keyframes.is_empty() {
+            buf.push_new_line();
+            buf.push_str(&settings.indent_with.repeat(depth as usize));
+        }
+        buf.push('}');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl KeyframesRule {
+    /// If `errors` is `Some`, invalid keyframes are skipped and their errors collected
+    pub(crate) fn from_reader_with_recovery(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        mut errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self, ParseError> {
+        let (vendor_prefix, start_span) = match reader.next().unwrap() {
+            Token(CSSToken::AtKeyword(name), position) => match Self::is_keyframes_at_rule(&name) {
+                Some(vendor_prefix) => (vendor_prefix.map(ToOwned::to_owned), position),
+                None => {
+                    return Err(ParseError {
+                        reason: format!("Expected '@keyframes' found '@{}'", name),
+                        position,
+                    })
+                }
+            },
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected '@keyframes' found '{:?}'", token),
+                    position,
+                })
+            }
+        };
+        let (name, _) = token_as_ident(reader.next().unwrap())?;
+        reader.expect_next(CSSToken::OpenCurly)?;
+        let mut keyframes = Vec::new();
+        while let Some(Token(token, _)) = reader.peek() {
+            match token {
+                CSSToken::CloseCurly | CSSToken::EOS => break,
+                CSSToken::Comment(_) => {
+                    reader.next();
+                }
+                _ => {
+                    let keyframe = match errors.as_deref_mut() {
+                        Some(errors) => recover(reader, errors, |reader, errors| {
+                            Keyframe::from_reader_with_recovery(reader, Some(errors))
+                        }),
+                        None => Some(Keyframe::from_reader_with_recovery(reader, None)?),
+                    };
+                    keyframes.extend(keyframe);
+                }
+            }
+        }
+        let end_span = reader.expect_next(CSSToken::CloseCurly)?;
+        Ok(Self {
+            vendor_prefix,
+            name,
+            keyframes,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+}
+
+impl ASTNode for Keyframe {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        Self::from_reader_with_recovery(reader, None)
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        for (idx, selector) in self.selectors.iter().enumerate() {
+            match selector {
+                // "0%" is shorter than "from", but "100%" is longer than "to"
+                KeyframeSelector::From if settings.minify => buf.push_str("0%"),
+                KeyframeSelector::From => buf.push_str("from"),
+                KeyframeSelector::To => buf.push_str("to"),
+                KeyframeSelector::Percentage(percentage) => {
+                    buf.push_str(&percentage.0);
+                    buf.push('%');
+                }
+            }
+            if idx + 1 < self.selectors.len() {
+                buf.push(',');
+                if !settings.minify {
+                    buf.push(' ');
+                }
+            }
+        }
+        if !settings.minify {
+            buf.push(' ');
+        }
+        buf.push('{');
+        for declaration in self.declarations.iter() {
+            if !settings.minify {
+                buf.push_new_line();
+                buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
+            }
+            declaration.to_string_from_buffer(buf, settings, depth);
+            buf.push(';');
+        }
+        if !settings.minify && !self.declarations.is_empty() {
+            buf.push_new_line();
+            buf.push_str(&settings.indent_with.repeat(depth as usize));
+        }
+        buf.push('}');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl Keyframe {
+    /// If `errors` is `Some`, invalid declarations are skipped and their errors collected
+    pub(crate) fn from_reader_with_recovery(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        mut errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self, ParseError> {
+        let mut selectors = Vec::new();
+        let mut start_span: Option<Span> = None;
+        loop {
+            let (selector, position) = match reader.next().unwrap() {
+                Token(CSSToken::Ident(ident), position) if ident.eq_ignore_ascii_case("from") => {
+                    (KeyframeSelector::From, position)
+                }
+                Token(CSSToken::Ident(ident), position) if ident.eq_ignore_ascii_case("to") => {
+                    (KeyframeSelector::To, position)
+                }
+                Token(CSSToken::Number(number), position) => {
+                    let percentage_position = reader.expect_next(CSSToken::Percentage)?;
+                    if !position.is_adjacent_to(&percentage_position) {
+                        return Err(ParseError {
+                            reason: "Expected percentage".to_owned(),
+                            position,
+                        });
+                    }
+                    (KeyframeSelector::Percentage(Number(number)), position)
+                }
+                Token(token, position) => {
+                    return Err(ParseError {
+                        reason: format!("Expected 'from', 'to' or percentage found '{:?}'", token),
+                        position,
+                    })
+                }
+            };
+            selectors.push(selector);
+            start_span.get_or_insert(position);
+            if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+                reader.next();
+            } else {
+                break;
+            }
+        }
+        reader.expect_next(CSSToken::OpenCurly)?;
+        let mut declarations = Vec::new();
+        while let Some(Token(token, _)) = reader.peek() {
+            match token {
+                CSSToken::CloseCurly | CSSToken::EOS => break,
+                CSSToken::SemiColon | CSSToken::Comment(_) => {
+                    reader.next();
+                }
+                _ => {
+                    let declaration = match errors.as_deref_mut() {
+                        Some(errors) => {
+                            recover(reader, errors, |reader, _| Declaration::from_reader(reader))
+                        }
+                        None => Some(Declaration::from_reader(reader)?),
+                    };
+                    declarations.extend(declaration);
+                }
+            }
+        }
+        let end_span = reader.expect_next(CSSToken::CloseCurly)?;
+        Ok(Self {
+            selectors,
+            declarations,
+            position: Some(start_span.unwrap().union(&end_span)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod keyframes_tests {
+    use super::*;
+    use source_map::SourceId;
+
+    const NULL_SOURCE_ID: SourceId = SourceId::null();
+
+    const SOURCE: &str = "@keyframes fade {
+    from {
+        opacity: 0;
+    }
+
+    50%, 75.5% {
+        opacity: 0.5;
+    }
+
+    to {
+        opacity: 1;
+    }
+}";
+
+    #[test]
+    fn keyframe_selectors() {
+        let rule = KeyframesRule::from_string(SOURCE.to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(rule.name, "fade");
+        assert_eq!(rule.keyframes.len(), 3);
+        assert_eq!(rule.keyframes[0].selectors, vec![KeyframeSelector::From]);
+        assert_eq!(
+            rule.keyframes[1].selectors,
+            vec![
+                KeyframeSelector::Percentage(Number("50".to_owned())),
+                KeyframeSelector::Percentage(Number("75.5".to_owned()))
+            ]
+        );
+        assert_eq!(rule.keyframes[2].selectors, vec![KeyframeSelector::To]);
+    }
+
+    #[test]
+    fn to_string() {
+        let rule = KeyframesRule::from_string(SOURCE.to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(rule.to_string(&ToStringSettings::default()), SOURCE);
+        assert_eq!(
+            rule.to_string(&ToStringSettings::minified()),
+            "@keyframes fade{0%{opacity:0;}50%,75.5%{opacity:0.5;}to{opacity:1;}}"
+        );
+    }
+
+    #[test]
+    fn vendor_prefix() {
+        let rule = KeyframesRule::from_string(
+            "@-webkit-keyframes spin { to { opacity: 1 } }".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert_eq!(rule.vendor_prefix.as_deref(), Some("-webkit-"));
+        assert_eq!(
+            rule.to_string(&ToStringSettings::minified()),
+            "@-webkit-keyframes spin{to{opacity:1;}}"
+        );
+    }
+}
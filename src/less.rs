@@ -0,0 +1,618 @@
+use super::{
+    lexer::{lex_braced_source, Syntax},
+    BlockItem, CSSToken, CSSValue, Entry, InterpolationPart, MixinDefinition, ParseError, Rule,
+    ScssStatement, VariableDeclaration,
+};
+use source_map::{SourceId, Span};
+use std::{collections::HashMap, mem};
+use tokenizer_lib::{BufferedTokenQueue, Token, TokenReader, TokenSender};
+
+/// Lexes Less into the tokens of the equivalent SCSS, so it is parsed and compiled as SCSS:
+/// - `@name: value` and `@name` in values are variables and `@{name}` is interpolation
+/// - `.name(@a; @b: 1) when (guard) { ... }` is `@mixin name($a, $b: 1) { @if guard { ... } }`
+/// - `.name(...);` and `.name;` are `@include name(...);`
+/// - `@media @query` is `@media #{$query}`
+///
+/// Variables and mixins are also changed after parsing, see [`resolve_variables`] and
+/// [`resolve_mixins`]
+pub(crate) fn lex_less_source(
+    source: &str,
+    sender: &mut impl TokenSender<CSSToken, Span>,
+    source_id: SourceId,
+    start_offset: Option<usize>,
+) -> Result<(), ParseError> {
+    let mut reader = BufferedTokenQueue::new();
+    lex_braced_source(source, &mut reader, source_id, start_offset, Syntax::Less)?;
+
+    let mut at_statement_start = true;
+    // For each open block, whether it is the body of a mixin with a guard. The `@if` block of the
+    // guard is closed with it
+    let mut blocks: Vec<bool> = Vec::new();
+    // Depth of brackets in the prelude of a `@media`. Variables outside of brackets are the
+    // whole or part of the query so are interpolated
+    let mut media_prelude: Option<u32> = None;
+    loop {
+        let Token(token, position) = reader.next().unwrap();
+        let was_at_statement_start = at_statement_start;
+        at_statement_start = match token {
+            CSSToken::OpenCurly | CSSToken::CloseCurly | CSSToken::SemiColon => true,
+            CSSToken::Comment(_) => at_statement_start,
+            _ => false,
+        };
+        media_prelude = match (&token, media_prelude) {
+            (CSSToken::AtKeyword(name), _) if was_at_statement_start && name == "media" => Some(0),
+            (CSSToken::OpenCurly | CSSToken::SemiColon, _) => None,
+            (CSSToken::OpenBracket, Some(depth)) => Some(depth + 1),
+            (CSSToken::CloseBracket, Some(depth)) => Some(depth.saturating_sub(1)),
+            (_, media_prelude) => media_prelude,
+        };
+        match token {
+            CSSToken::EOS => {
+                sender.push(Token(CSSToken::EOS, position));
+                return Ok(());
+            }
+            CSSToken::AtKeyword(name) if media_prelude == Some(0) && !was_at_statement_start => {
+                let start = Span {
+                    end: position.start,
+                    ..position.clone()
+                };
+                let end = Span {
+                    start: position.end,
+                    ..position.clone()
+                };
+                sender.push(Token(CSSToken::InterpolationStart, start));
+                sender.push(Token(CSSToken::Variable(name), position));
+                sender.push(Token(CSSToken::CloseCurly, end));
+            }
+            // At-rules are at the start of statements
+            CSSToken::AtKeyword(name)
+                if !was_at_statement_start || is_colon_ahead(&mut reader, &position) =>
+            {
+                sender.push(Token(CSSToken::Variable(name), position));
+            }
+            CSSToken::Dot if was_at_statement_start && is_mixin_ahead(&mut reader, &position) => {
+                at_statement_start = mixin_from_reader(&mut reader, sender, position, &mut blocks)?;
+            }
+            CSSToken::OpenCurly => {
+                blocks.push(false);
+                sender.push(Token(CSSToken::OpenCurly, position));
+            }
+            CSSToken::CloseCurly => {
+                if blocks.pop() == Some(true) {
+                    sender.push(Token(CSSToken::CloseCurly, position.clone()));
+                }
+                sender.push(Token(CSSToken::CloseCurly, position));
+            }
+            CSSToken::InterpolationStart => {
+                sender.push(Token(CSSToken::InterpolationStart, position));
+                if let Some(Token(CSSToken::Ident(_), _)) = reader.peek() {
+                    if let Token(CSSToken::Ident(name), position) = reader.next().unwrap() {
+                        sender.push(Token(CSSToken::Variable(name), position));
+                    }
+                }
+            }
+            CSSToken::String(content) => {
+                let content = content.replace("@{", "#{$");
+                sender.push(Token(CSSToken::String(content), position));
+            }
+            CSSToken::Url(url) => {
+                let url = url.replace("@{", "#{$");
+                sender.push(Token(CSSToken::Url(url), position));
+            }
+            CSSToken::CustomPropertyValue(value) => {
+                let value = value.replace("@{", "#{$");
+                sender.push(Token(CSSToken::CustomPropertyValue(value), position));
+            }
+            token => {
+                sender.push(Token(token, position));
+            }
+        }
+    }
+}
+
+/// Whether a `:` directly follows the at-keyword, making it a variable declaration
+fn is_colon_ahead(reader: &mut impl TokenReader<CSSToken, Span>, position: &Span) -> bool {
+    matches!(reader.peek(), Some(Token(CSSToken::Colon, colon_position)) if position.is_adjacent_to(colon_position))
+}
+
+/// After the `.`, whether the tokens are a mixin definition or call rather than a selector: a
+/// name followed by `(`, `;` or `}`
+fn is_mixin_ahead(reader: &mut impl TokenReader<CSSToken, Span>, dot_position: &Span) -> bool {
+    let mut last_span = dot_position.clone();
+    let mut count = 0;
+    let mut is_mixin = false;
+    reader.scan(|token, position| {
+        count += 1;
+        let is_adjacent = last_span.is_adjacent_to(position);
+        last_span = position.clone();
+        match (count, token) {
+            (1, CSSToken::Ident(_)) => !is_adjacent,
+            (2, CSSToken::OpenBracket) => {
+                is_mixin = is_adjacent;
+                true
+            }
+            (2, CSSToken::SemiColon | CSSToken::CloseCurly) => {
+                is_mixin = true;
+                true
+            }
+            _ => true,
+        }
+    });
+    is_mixin
+}
+
+/// Emits the `@mixin` or `@include` for the mixin after the `.`. Returns whether it ends at the
+/// start of a statement, which is after the `{` of a guard
+fn mixin_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+    sender: &mut impl TokenSender<CSSToken, Span>,
+    dot_position: Span,
+    blocks: &mut Vec<bool>,
+) -> Result<bool, ParseError> {
+    let (name, name_position) = match reader.next().unwrap() {
+        Token(CSSToken::Ident(name), position) => (name, position),
+        _ => unreachable!(),
+    };
+    let mut arguments = Vec::new();
+    if let Some(Token(CSSToken::OpenBracket, _)) = reader.peek() {
+        let mut depth = 0u32;
+        loop {
+            let Token(token, position) = reader.next().unwrap();
+            match token {
+                CSSToken::OpenBracket => depth += 1,
+                CSSToken::CloseBracket => depth -= 1,
+                CSSToken::EOS => {
+                    return Err(ParseError {
+                        reason: format!("Could not find closing ')' for '.{}('", name),
+                        position: name_position.union(&position),
+                    })
+                }
+                _ => {}
+            }
+            let token = match token {
+                // Less arguments can be separated by `;`
+                CSSToken::SemiColon if depth == 1 => CSSToken::Comma,
+                CSSToken::AtKeyword(name) => CSSToken::Variable(name),
+                token => token,
+            };
+            arguments.push(Token(token, position));
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
+    let is_guarded =
+        matches!(reader.peek(), Some(Token(CSSToken::Ident(ident), _)) if ident == "when");
+    let is_definition = is_guarded || matches!(reader.peek(), Some(Token(CSSToken::OpenCurly, _)));
+    let keyword = if is_definition { "mixin" } else { "include" };
+    sender.push(Token(CSSToken::AtKeyword(keyword.to_owned()), dot_position));
+    sender.push(Token(CSSToken::Ident(name), name_position));
+    for argument in arguments {
+        sender.push(argument);
+    }
+    if !is_guarded {
+        return Ok(false);
+    }
+
+    let when_position = reader.next().unwrap().1;
+    let mut guard = Vec::new();
+    let mut depth = 0u32;
+    let open_position = loop {
+        let Token(token, position) = reader.next().unwrap();
+        let token = match token {
+            CSSToken::OpenCurly if depth == 0 => break position,
+            CSSToken::EOS => {
+                return Err(ParseError {
+                    reason: "Expected '{' after mixin guard".to_owned(),
+                    position,
+                })
+            }
+            CSSToken::OpenBracket => {
+                depth += 1;
+                CSSToken::OpenBracket
+            }
+            CSSToken::CloseBracket => {
+                depth = depth.saturating_sub(1);
+                CSSToken::CloseBracket
+            }
+            // Guards separated by `,` match if any does
+            CSSToken::Comma if depth == 0 => CSSToken::Ident("or".to_owned()),
+            // `=<` is Less for `<=`
+            CSSToken::Equal if matches!(reader.peek(), Some(Token(CSSToken::OpenAngle, angle_position)) if position.is_adjacent_to(angle_position)) =>
+            {
+                reader.next();
+                CSSToken::LessThanEqual
+            }
+            CSSToken::Equal => CSSToken::DoubleEqual,
+            CSSToken::AtKeyword(name) => CSSToken::Variable(name),
+            token => token,
+        };
+        guard.push(Token(token, position));
+    };
+    sender.push(Token(CSSToken::OpenCurly, open_position.clone()));
+    sender.push(Token(CSSToken::AtKeyword("if".to_owned()), when_position));
+    for token in guard {
+        sender.push(token);
+    }
+    sender.push(Token(CSSToken::OpenCurly, open_position));
+    blocks.push(true);
+    Ok(true)
+}
+
+/// Less variables are lazy: a variable has the value of its last definition in the scope, which
+/// may be after it is used. Moves the last declaration of each variable to the start of its scope,
+/// after the declarations of the variables it uses. Errors if variables depend on each other
+pub(crate) fn resolve_variables(entries: &mut Vec<Entry>) -> Result<(), ParseError> {
+    hoist_variables(
+        entries,
+        |entry| match entry {
+            Entry::ScssStatement(ScssStatement::Variable(declaration)) => Some(declaration),
+            _ => None,
+        },
+        |declaration| Entry::ScssStatement(declaration.into()),
+    )?;
+    for entry in entries.iter_mut() {
+        match entry {
+            Entry::Rule(rule) => resolve_rule_variables(rule)?,
+            Entry::MediaRule(media_rule) => resolve_variables(&mut media_rule.block.entries)?,
+            Entry::AtRule(at_rule) => {
+                if let Some(block) = &mut at_rule.block {
+                    resolve_variables(&mut block.entries)?;
+                }
+            }
+            Entry::ScssStatement(statement) => resolve_statement_variables(statement)?,
+            Entry::KeyframesRule(_) | Entry::Comment(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn resolve_rule_variables(rule: &mut Rule) -> Result<(), ParseError> {
+    if let Some(items) = &mut rule.scss_body {
+        resolve_item_variables(items)?;
+    }
+    for nested_rule in rule.nested_rules.iter_mut().flatten() {
+        resolve_rule_variables(nested_rule)?;
+    }
+    Ok(())
+}
+
+fn resolve_item_variables(items: &mut Vec<BlockItem>) -> Result<(), ParseError> {
+    hoist_variables(
+        items,
+        |item| match item {
+            BlockItem::Statement(ScssStatement::Variable(declaration)) => Some(declaration),
+            _ => None,
+        },
+        |declaration| BlockItem::Statement(declaration.into()),
+    )?;
+    for item in items.iter_mut() {
+        match item {
+            BlockItem::Rule(rule) => resolve_rule_variables(rule)?,
+            BlockItem::Statement(statement) => resolve_statement_variables(statement)?,
+            BlockItem::Declaration(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Mixin bodies and the `@if` of mixin guards
+fn resolve_statement_variables(statement: &mut ScssStatement) -> Result<(), ParseError> {
+    match statement {
+        ScssStatement::Mixin(mixin) => resolve_item_variables(&mut mixin.body)?,
+        ScssStatement::If(statement) => {
+            for (_, body) in statement.branches.iter_mut() {
+                resolve_item_variables(body)?;
+            }
+            if let Some(body) = &mut statement.else_branch {
+                resolve_item_variables(body)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Moves the last declaration of each variable in `items` to the start. A declaration is moved
+/// after the declarations of the variables in its value. Errors if declarations depend on each
+/// other
+fn hoist_variables<T>(
+    items: &mut Vec<T>,
+    as_declaration: fn(&T) -> Option<&VariableDeclaration>,
+    from_declaration: fn(VariableDeclaration) -> T,
+) -> Result<(), ParseError> {
+    let mut declarations: Vec<(VariableDeclaration, Vec<String>)> = Vec::new();
+    let mut others = Vec::with_capacity(items.len());
+    for item in mem::take(items) {
+        match as_declaration(&item) {
+            Some(declaration) => {
+                let declaration = declaration.clone();
+                declarations.retain(|(existing, _)| existing.name != declaration.name);
+                let mut uses = Vec::new();
+                variables_in(&declaration.value, &mut uses);
+                uses.retain(|name| *name != declaration.name);
+                declarations.push((declaration, uses));
+            }
+            None => others.push(item),
+        }
+    }
+    while !declarations.is_empty() {
+        let is_pending = |name: &String| {
+            declarations
+                .iter()
+                .any(|(declaration, _)| declaration.name == *name)
+        };
+        let idx = match declarations
+            .iter()
+            .position(|(_, uses)| !uses.iter().any(is_pending))
+        {
+            Some(idx) => idx,
+            None => {
+                let (declaration, _) = declarations.remove(0);
+                return Err(ParseError {
+                    reason: format!("Recursive variable definition for @{}", declaration.name),
+                    position: declaration.position.unwrap_or(Span {
+                        start: 0,
+                        end: 0,
+                        source_id: SourceId::null(),
+                    }),
+                });
+            }
+        };
+        let (declaration, _) = declarations.remove(idx);
+        items.push(from_declaration(declaration));
+    }
+    items.extend(others);
+    Ok(())
+}
+
+/// Names of the variables used in the value
+fn variables_in(value: &CSSValue, names: &mut Vec<String>) {
+    match value {
+        CSSValue::Variable {
+            namespace: None,
+            name,
+            ..
+        } => names.push(name.clone()),
+        CSSValue::Function(_, values)
+        | CSSValue::List(values)
+        | CSSValue::CommaSeparatedList(values) => {
+            for value in values {
+                variables_in(value, names);
+            }
+        }
+        CSSValue::Map(entries) => {
+            for (key, value) in entries {
+                variables_in(key, names);
+                variables_in(value, names);
+            }
+        }
+        CSSValue::Bracketed(value)
+        | CSSValue::Parenthesized(value)
+        | CSSValue::NamedArgument(_, value)
+        | CSSValue::UnaryOperation { operand: value, .. } => variables_in(value, names),
+        CSSValue::BinaryOperation { lhs, rhs, .. } => {
+            variables_in(lhs, names);
+            variables_in(rhs, names);
+        }
+        CSSValue::Interpolation(interpolation) | CSSValue::InterpolatedString(interpolation) => {
+            for part in &interpolation.parts {
+                if let InterpolationPart::Expression(value) = part {
+                    variables_in(value, names);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Changes the parsed mixins of a Less stylesheet to behave as in Less:
+/// - Mixins are moved to the start of the stylesheet, as they can be used before they are defined
+/// - Mixins with the same name and number of parameters are all applied, so their bodies are
+///   merged
+/// - Rules of a single class e.g. `.bordered { ... }` can be included as mixins
+pub(crate) fn resolve_mixins(entries: &mut Vec<Entry>) {
+    let mut mixins: Vec<MixinDefinition> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    let mut other_entries = Vec::with_capacity(entries.len());
+    let mut class_mixins = Vec::new();
+    for entry in mem::take(entries) {
+        match entry {
+            Entry::ScssStatement(ScssStatement::Mixin(mixin)) => {
+                add_mixin(&mut mixins, &mut indexes, mixin)
+            }
+            Entry::Rule(rule) => {
+                if let [selector] = rule.selectors.as_slice() {
+                    if let Some(class_name) = selector.as_class_name() {
+                        class_mixins.push(MixinDefinition {
+                            name: class_name.to_owned(),
+                            parameters: Vec::new(),
+                            body: rule_body(&rule),
+                            position: rule.position.clone(),
+                        });
+                    }
+                }
+                other_entries.push(Entry::Rule(rule));
+            }
+            entry => other_entries.push(entry),
+        }
+    }
+    let defined: Vec<String> = indexes.keys().cloned().collect();
+    for mixin in class_mixins {
+        // Parametric mixins take precedence
+        if !defined.contains(&mixin.name) {
+            add_mixin(&mut mixins, &mut indexes, mixin);
+        }
+    }
+
+    entries.extend(
+        mixins
+            .into_iter()
+            .map(|mixin| Entry::ScssStatement(mixin.into())),
+    );
+    entries.extend(other_entries);
+}
+
+fn add_mixin(
+    mixins: &mut Vec<MixinDefinition>,
+    indexes: &mut HashMap<String, usize>,
+    mixin: MixinDefinition,
+) {
+    if let Some(&idx) = indexes.get(&mixin.name) {
+        let existing = &mut mixins[idx];
+        if existing.parameters.len() == mixin.parameters.len() {
+            merge_mixin(existing, mixin);
+            return;
+        }
+    }
+    indexes.insert(mixin.name.clone(), mixins.len());
+    mixins.push(mixin);
+}
+
+/// Appends the body of `mixin` to `existing`. Parameters of `mixin` with different names are
+/// assigned from the parameters of `existing`
+fn merge_mixin(existing: &mut MixinDefinition, mixin: MixinDefinition) {
+    let position = mixin.position.clone().unwrap_or(Span {
+        start: 0,
+        end: 0,
+        source_id: SourceId::null(),
+    });
+    for (parameter, existing_parameter) in mixin.parameters.iter().zip(&existing.parameters) {
+        if parameter.name != existing_parameter.name {
+            let declaration = VariableDeclaration {
+                name: parameter.name.clone(),
+                value: CSSValue::Variable {
+                    namespace: None,
+                    name: existing_parameter.name.clone(),
+                    position: position.clone(),
+                },
+                default: false,
+                global: false,
+                position: mixin.position.clone(),
+            };
+            existing.body.push(BlockItem::Statement(declaration.into()));
+        }
+    }
+    existing.body.extend(mixin.body);
+}
+
+fn rule_body(rule: &Rule) -> Vec<BlockItem> {
+    match &rule.scss_body {
+        Some(items) => items.clone(),
+        None => rule
+            .declarations
+            .iter()
+            .cloned()
+            .map(BlockItem::Declaration)
+            .chain(
+                rule.nested_rules
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .map(BlockItem::Rule),
+            )
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod less_tests {
+    use crate::{compile, raise_nested_rules, StyleSheet, Syntax, ToStringSettings};
+    use source_map::SourceId;
+
+    fn compile_less(source: &str) -> String {
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Less)
+                .unwrap();
+        compile(&mut style_sheet).unwrap();
+        raise_nested_rules(&mut style_sheet).unwrap();
+        style_sheet.to_string(Some(ToStringSettings::minified()))
+    }
+
+    #[test]
+    fn variables_and_operations() {
+        let source = "// Settings
+@gutter: 30px;
+@brand-primary: #428bca;
+@selector: nav;
+.@{selector}-bar {
+    padding: (@gutter / 2) @gutter * 2;
+    width: ~\"calc(100% - @{gutter})\";
+    color: darken(@brand-primary, 10%);
+    background: url(@image) url(@{selector}/a.png);
+}
+@image: \"a.png\";
+@breakpoint: 300px;
+@media (min-width: @breakpoint) { a { margin: -@gutter; } }";
+        assert_eq!(
+            compile_less(source),
+            ".nav-bar{padding:15px 60px;width:calc(100% - 30px);color:#3071a9;\
+            background:url(\"a.png\") url(nav/a.png);}\
+            @media (min-width:300px){a{margin:-30px;}}"
+        );
+    }
+
+    #[test]
+    fn lazy_variables() {
+        assert_eq!(compile_less("@a: 1px; .x { w: @a } @a: 2px;"), ".x{w:2px;}");
+        let source = "@size: @base * 2;
+.x { w: @size; @base: 1px; h: @base; }
+@base: 4px;
+.y { @c: red; @c: blue; color: @c; }";
+        assert_eq!(compile_less(source), ".x{w:8px;h:1px;}.y{color:blue;}");
+    }
+
+    #[test]
+    fn media_query_variables() {
+        let source = "@phone: ~\"(max-width: 768px)\";
+@media @phone { a { b: c; } }
+@media screen and @phone { a { b: d; } }
+@media @phone and (min-width: @min) { a { b: e; } }
+@min: 100px;";
+        assert_eq!(
+            compile_less(source),
+            "@media (max-width:768px){a{b:c;}}@media screen and (max-width:768px){a{b:d;}}\
+            @media (max-width:768px) and (min-width:100px){a{b:e;}}"
+        );
+    }
+
+    #[test]
+    fn mixins_and_guards() {
+        let source = ".bordered { border: 1px solid; }
+.text(@color) when (lightness(@color) >= 50%) { color: black; }
+.text(@background) when (lightness(@background) < 50%) { color: white; }
+.box(@color; @padding: 2px) when (iscolor(@color)), (@padding = 0) {
+    padding: @padding;
+}
+a { .bordered; .text(#ddd); .box(red); }
+b { .text(#222); .box(1px; 0); .box(1px); .late(); }
+.late() { margin: 0; }";
+        assert_eq!(
+            compile_less(source),
+            ".bordered{border:1px solid;}a{border:1px solid;color:black;padding:2px;}\
+            b{color:white;padding:0;margin:0;}"
+        );
+    }
+
+    #[test]
+    fn variable_errors() {
+        let source = ".x { w: @undefined; }";
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Less)
+                .unwrap();
+        let error = compile(&mut style_sheet).unwrap_err();
+        assert_eq!(error.reason, "Undefined variable @undefined");
+        assert_eq!(
+            &source[error.position.start..error.position.end],
+            "@undefined"
+        );
+
+        let source = "@a: @b; @b: @a; .x { w: @a; }";
+        let error =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Less)
+                .unwrap_err();
+        assert_eq!(error.reason, "Recursive variable definition for @a");
+        assert_eq!(&source[error.position.start..error.position.end], "@a: @b;");
+    }
+}
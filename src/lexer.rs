@@ -1,7 +1,12 @@
 use source_map::{SourceId, Span};
+use std::{fmt, ops::Range, path::Path};
 use tokenizer_lib::{Token, TokenSender};
 
-use crate::ParseError;
+use crate::{
+    less, sass,
+    tokenizer::{number_length, SyntaxToken, Tokenizer},
+    ParseError,
+};
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum CSSToken {
@@ -10,10 +15,20 @@ pub enum CSSToken {
     /// HashPrefixedValue. Is a separate member to prevent lexing #0f5421 as a number.
     /// e.g #my-idx, #ffffff
     HashPrefixedValue(String),
-    /// e.g 42
+    /// e.g @media, @font-face
+    AtKeyword(String),
+    /// e.g 42, -1.5, 1e3. The unit of a dimension e.g `px` is a following [`CSSToken::Ident`]
     Number(String),
-    /// e.g "SF Pro Display"
+    /// SCSS variable e.g `$primary`
+    Variable(String),
+    /// Content as written e.g "SF Pro Display". Single quoted strings are converted to double
+    /// quoted
     String(String),
+    /// Unquoted url, e.g `url(image.png)`
+    Url(String),
+    /// Value of a custom property as written e.g. `1px solid` in `--border: 1px solid`. Custom
+    /// properties can hold any tokens so their values are not parsed
+    CustomPropertyValue(String),
     OpenCurly,
     CloseCurly,
     OpenBracket,
@@ -25,10 +40,125 @@ pub enum CSSToken {
     Comma,
     Asterisk,
     Percentage,
+    OpenAngle,
+    /// `/`. Separator e.g `16/9`, `12px/1.5`
+    Slash,
+    Equal,
+    /// `<=`
+    LessThanEqual,
+    /// `>=`
+    GreaterThanEqual,
+    OpenSquareBracket,
+    CloseSquareBracket,
+    /// `~=`
+    IncludesMatch,
+    /// `|=`
+    DashMatch,
+    /// `^=`
+    PrefixMatch,
+    /// `$=`
+    SuffixMatch,
+    /// `*=`
+    SubstringMatch,
+    /// `+`
+    Plus,
+    /// `-` not starting a identifier or number. SCSS subtraction or negation
+    Minus,
+    /// `~`
+    Tilde,
+    /// `||`
+    Column,
+    /// `!` e.g. `!important`
+    Exclamation,
+    /// SCSS parent selector `&`
+    Ampersand,
+    /// SCSS `==`
+    DoubleEqual,
+    /// SCSS `!=`
+    NotEqual,
+    /// Start of SCSS interpolation `#{`. The expression is followed by a `}`
+    InterpolationStart,
     /// END of source
     EOS,
 }
 
+/// Prints the token as it would appear in source
+impl fmt::Display for CSSToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CSSToken::Ident(value) | CSSToken::Number(value) => f.write_str(value),
+            CSSToken::Comment(comment) => write!(f, "/*{}*/", comment),
+            CSSToken::HashPrefixedValue(value) => write!(f, "#{}", value),
+            CSSToken::AtKeyword(name) => write!(f, "@{}", name),
+            CSSToken::Variable(name) => write!(f, "${}", name),
+            CSSToken::String(content) => write!(f, "\"{}\"", content),
+            CSSToken::Url(url) => write!(f, "url({})", url),
+            CSSToken::CustomPropertyValue(value) => f.write_str(value),
+            CSSToken::OpenCurly => f.write_str("{"),
+            CSSToken::CloseCurly => f.write_str("}"),
+            CSSToken::OpenBracket => f.write_str("("),
+            CSSToken::CloseBracket => f.write_str(")"),
+            CSSToken::Colon => f.write_str(":"),
+            CSSToken::SemiColon => f.write_str(";"),
+            CSSToken::Dot => f.write_str("."),
+            CSSToken::CloseAngle => f.write_str(">"),
+            CSSToken::Comma => f.write_str(","),
+            CSSToken::Asterisk => f.write_str("*"),
+            CSSToken::Percentage => f.write_str("%"),
+            CSSToken::OpenAngle => f.write_str("<"),
+            CSSToken::Slash => f.write_str("/"),
+            CSSToken::Equal => f.write_str("="),
+            CSSToken::LessThanEqual => f.write_str("<="),
+            CSSToken::GreaterThanEqual => f.write_str(">="),
+            CSSToken::OpenSquareBracket => f.write_str("["),
+            CSSToken::CloseSquareBracket => f.write_str("]"),
+            CSSToken::IncludesMatch => f.write_str("~="),
+            CSSToken::DashMatch => f.write_str("|="),
+            CSSToken::PrefixMatch => f.write_str("^="),
+            CSSToken::SuffixMatch => f.write_str("$="),
+            CSSToken::SubstringMatch => f.write_str("*="),
+            CSSToken::Plus => f.write_str("+"),
+            CSSToken::Minus => f.write_str("-"),
+            CSSToken::Tilde => f.write_str("~"),
+            CSSToken::Column => f.write_str("||"),
+            CSSToken::Exclamation => f.write_str("!"),
+            CSSToken::Ampersand => f.write_str("&"),
+            CSSToken::DoubleEqual => f.write_str("=="),
+            CSSToken::NotEqual => f.write_str("!="),
+            CSSToken::InterpolationStart => f.write_str("#{"),
+            CSSToken::EOS => Ok(()),
+        }
+    }
+}
+
+/// The language of a source, which decides how some characters are lexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Css,
+    /// SCSS, where `//` starts a comment up to the end of the line
+    Scss,
+    /// The indented syntax of SCSS, where indentation rather than `{}` and `;` delimits blocks
+    /// and statements
+    Sass,
+    /// Less, which is lexed into the equivalent SCSS e.g. `@primary` is a variable and
+    /// `.bordered();` a `@include`. Also has `//` comments
+    Less,
+}
+
+impl Syntax {
+    /// Syntax from the extension of the path. Paths without a `.scss`, `.sass` or `.less`
+    /// extension are CSS
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("scss") => Syntax::Scss,
+            Some("sass") => Syntax::Sass,
+            Some("less") => Syntax::Less,
+            _ => Syntax::Css,
+        }
+    }
+}
+
 /// Lexes the source returning CSSToken sequence
 /// byte_offset marks spans
 pub fn lex_source(
@@ -37,227 +167,461 @@ pub fn lex_source(
     source_id: SourceId,
     start_offset: Option<usize>,
 ) -> Result<(), ParseError> {
-    #[derive(PartialEq)]
-    enum ParsingState {
-        Ident,
-        Number,
-        /// Used to decide whether class identifier or number
-        Dot,
-        String {
-            escaped: bool,
-        },
-        HashPrefixedValue,
-        Comment {
-            found_asterisk: bool,
-        },
-        None,
-    }
+    lex_source_with_syntax(source, sender, source_id, start_offset, Syntax::Css)
+}
 
-    let mut state = ParsingState::None;
+/// Same as [`lex_source`] for a source in the given syntax
+pub fn lex_source_with_syntax(
+    source: &str,
+    sender: &mut impl TokenSender<CSSToken, Span>,
+    source_id: SourceId,
+    start_offset: Option<usize>,
+    syntax: Syntax,
+) -> Result<(), ParseError> {
+    match syntax {
+        Syntax::Sass => sass::lex_indented_source(source, sender, source_id, start_offset),
+        Syntax::Less => less::lex_less_source(source, sender, source_id, start_offset),
+        syntax => lex_braced_source(source, sender, source_id, start_offset, syntax),
+    }
+}
 
-    // Used for getting string slices from source
-    let mut start = 0;
+/// Lexes CSS, SCSS or Less, where `{}` and `;` delimit blocks and statements. Less is lexed as
+/// written, without translating it to SCSS.
+///
+/// The source is split by [`Tokenizer`], which follows CSS Syntax Level 3, and its tokens are
+/// converted to [`CSSToken`]s. Token values are kept as written (with their escapes) so they
+/// print as in source. Whitespace, `<!--` and `-->` are dropped: whitespace is found from
+/// positions. Comments are only kept between statements, where they are parsed. Functions,
+/// percentages and dimensions are split into their parts e.g. `10px` is a number and an
+/// identifier
+pub(crate) fn lex_braced_source(
+    source: &str,
+    sender: &mut impl TokenSender<CSSToken, Span>,
+    source_id: SourceId,
+    start_offset: Option<usize>,
+    syntax: Syntax,
+) -> Result<(), ParseError> {
     let start_offset = start_offset.unwrap_or_default();
+    let span = |range: Range<usize>| Span {
+        start: start_offset + range.start,
+        end: start_offset + range.end,
+        source_id,
+    };
+    let invalid_character = |chr: char, range: Range<usize>| ParseError {
+        reason: format!("Invalid character '{}'", chr),
+        position: span(range),
+    };
 
-    for (idx, chr) in source.char_indices() {
-        macro_rules! set_state {
-            ($s:expr) => {{
-                start = idx;
-                state = $s;
-            }};
-        }
+    macro_rules! push_token {
+        ($t:expr, $range:expr) => {{
+            if !sender.push(Token($t, span($range))) {
+                return Ok(());
+            }
+        }};
+    }
 
-        macro_rules! push_token {
-            ($t:expr) => {{
-                if !sender.push(Token($t, current_position!())) {
-                    return Ok(());
-                };
-            }};
+    let mut tokens = Lookahead {
+        tokenizer: Tokenizer::new(source, syntax),
+        peeked: None,
+    };
+    // Unicode ranges e.g. `U+0025-00FF` are only tokenized in declaration values, up to the end
+    // of the declaration. Elsewhere `u+a` is a selector
+    let mut after_property_name = false;
+    let mut after_custom_property_name = false;
+    let mut at_statement_start = true;
+    // Number of `#{`s without their `}`
+    let mut interpolations = 0u32;
+    while let Some((token, range)) = tokens.next() {
+        let text = &source[range.clone()];
+        let starts_custom_property_value =
+            after_custom_property_name && token == SyntaxToken::Colon;
+        let was_at_statement_start = at_statement_start;
+        match token {
+            SyntaxToken::Whitespace | SyntaxToken::Comment(_) => {}
+            SyntaxToken::Ident(ref name) => {
+                after_property_name = at_statement_start;
+                after_custom_property_name = name.starts_with("--");
+                at_statement_start = false;
+            }
+            SyntaxToken::Colon if after_property_name => {
+                after_property_name = false;
+                let is_declaration = is_declaration_ahead(&mut tokens);
+                tokens.set_position(range.end);
+                tokens.tokenizer.set_unicode_ranges_allowed(is_declaration);
+            }
+            SyntaxToken::CloseCurly if interpolations > 0 => {
+                interpolations -= 1;
+                after_property_name = false;
+                after_custom_property_name = false;
+            }
+            SyntaxToken::Semicolon | SyntaxToken::OpenCurly | SyntaxToken::CloseCurly => {
+                after_property_name = false;
+                after_custom_property_name = false;
+                at_statement_start = true;
+                tokens.tokenizer.set_unicode_ranges_allowed(false);
+            }
+            _ => {
+                after_property_name = false;
+                after_custom_property_name = false;
+                at_statement_start = false;
+            }
         }
-
-        macro_rules! current_position {
-            () => {
-                Span {
-                    start: start_offset + start,
-                    end: idx,
-                    source_id,
-                }
-            };
+        if starts_custom_property_value {
+            push_token!(CSSToken::Colon, range.clone());
+            let end = custom_property_value_end(&mut tokens, source.len());
+            let value = &source[range.end..end];
+            let start = end - value.trim_start().len();
+            let value = value.trim();
+            push_token!(
+                CSSToken::CustomPropertyValue(value.to_owned()),
+                start..(start + value.len())
+            );
+            tokens.set_position(end);
+            continue;
         }
-
-        match state {
-            ParsingState::Ident => match chr {
-                'A'..='Z' | 'a'..='z' | '0'..='9' | '-' => {}
-                _ => {
-                    push_token!(CSSToken::Ident(source[start..idx].to_owned()));
-                    set_state!(ParsingState::None);
+        match token {
+            SyntaxToken::Whitespace | SyntaxToken::CDO | SyntaxToken::CDC => {}
+            SyntaxToken::Comment(content) => {
+                if !text.ends_with("*/") || text.len() < 4 {
+                    return Err(ParseError {
+                        reason: "Could not find end to comment".to_owned(),
+                        position: span(range),
+                    });
                 }
-            },
-            ParsingState::HashPrefixedValue => match chr {
-                'A'..='Z' | 'a'..='z' | '0'..='9' | '-' => {}
-                _ => {
-                    push_token!(CSSToken::HashPrefixedValue(
-                        source[(start + 1)..idx].to_owned()
-                    ));
-                    set_state!(ParsingState::None);
+                // Comments in statements e.g. `a: /* b */ c` are dropped
+                if was_at_statement_start {
+                    push_token!(CSSToken::Comment(content), range)
                 }
-            },
-            ParsingState::Dot => {
-                if matches!(chr, '0'..='9') {
-                    state = ParsingState::Number;
+            }
+            SyntaxToken::Ident(_) | SyntaxToken::UnicodeRange { .. } => {
+                // Unicode ranges e.g. `U+0025-00FF` are kept as written, as keywords
+                push_token!(CSSToken::Ident(text.to_owned()), range)
+            }
+            SyntaxToken::Function(_) => {
+                let bracket = range.start + text.find('(').unwrap();
+                push_token!(
+                    CSSToken::Ident(source[range.start..bracket].to_owned()),
+                    range.start..bracket
+                );
+                push_token!(CSSToken::OpenBracket, bracket..(bracket + 1));
+            }
+            SyntaxToken::AtKeyword(_) => {
+                push_token!(CSSToken::AtKeyword(text[1..].to_owned()), range)
+            }
+            SyntaxToken::Hash { .. } => {
+                push_token!(CSSToken::HashPrefixedValue(text[1..].to_owned()), range)
+            }
+            SyntaxToken::String(_) if !is_terminated_string(text) => {
+                return Err(ParseError {
+                    reason: "Could not find end to string".to_owned(),
+                    position: span(range),
+                })
+            }
+            SyntaxToken::String(_) => push_token!(CSSToken::String(string_content(text)), range),
+            SyntaxToken::BadString => {
+                return Err(ParseError {
+                    reason: "Found newline in string".to_owned(),
+                    position: span(range),
+                })
+            }
+            // In SCSS and Less `url()` can contain expressions e.g. `url($base + "/a.png")` or
+            // `url($image)` (`url(@image)` in Less), which are function calls
+            SyntaxToken::Url(_)
+                if match syntax {
+                    Syntax::Css => false,
+                    Syntax::Less => url_content(text).1.starts_with('@'),
+                    Syntax::Scss | Syntax::Sass => url_content(text).1.starts_with('$'),
+                } =>
+            {
+                let bracket = range.start + text.find('(').unwrap();
+                push_token!(
+                    CSSToken::Ident(source[range.start..bracket].to_owned()),
+                    range.start..bracket
+                );
+                push_token!(CSSToken::OpenBracket, bracket..(bracket + 1));
+                tokens.set_position(bracket + 1);
+            }
+            SyntaxToken::Url(_) => {
+                let (content_start, content) = url_content(text);
+                // Interpolation in the url is parsed from the content, so the token is at the
+                // position of the content
+                if syntax != Syntax::Css && (content.contains("#{") || content.contains("@{")) {
+                    let start = range.start + content_start;
+                    push_token!(
+                        CSSToken::Url(content.to_owned()),
+                        start..(start + content.len())
+                    )
                 } else {
-                    push_token!(CSSToken::Dot);
-                    set_state!(ParsingState::Ident);
+                    push_token!(CSSToken::Url(content.to_owned()), range)
                 }
             }
-            ParsingState::Number => match chr {
-                '0'..='9' | '.' => {}
-                _ => {
-                    push_token!(CSSToken::Number(source[start..idx].to_owned()));
-                    set_state!(ParsingState::None);
-                }
-            },
-            ParsingState::String { ref mut escaped } => match chr {
-                '\\' => {
-                    *escaped = true;
-                }
-                '"' if !*escaped => {
-                    push_token!(CSSToken::String(source[(start + 1)..idx].to_owned()));
-                    set_state!(ParsingState::None);
-                    continue;
-                }
-                _ => *escaped = false,
-            },
-            ParsingState::Comment {
-                ref mut found_asterisk,
-            } => match chr {
-                '/' if *found_asterisk => {
-                    push_token!(CSSToken::Comment(source[(start + 2)..(idx - 1)].to_owned()));
-                    set_state!(ParsingState::None);
-                    continue;
+            SyntaxToken::BadUrl if syntax != Syntax::Css => {
+                let bracket = range.start + text.find('(').unwrap();
+                push_token!(
+                    CSSToken::Ident(source[range.start..bracket].to_owned()),
+                    range.start..bracket
+                );
+                push_token!(CSSToken::OpenBracket, bracket..(bracket + 1));
+                tokens.set_position(bracket + 1);
+            }
+            SyntaxToken::BadUrl => {
+                return Err(ParseError {
+                    reason: "Invalid url".to_owned(),
+                    position: span(range),
+                })
+            }
+            SyntaxToken::Number { .. }
+            | SyntaxToken::Percentage(_)
+            | SyntaxToken::Dimension { .. } => {
+                let mut number_start = range.start;
+                // So `$a+1` is an addition
+                if text.starts_with('+') && syntax != Syntax::Css {
+                    number_start += 1;
+                    push_token!(CSSToken::Plus, range.start..number_start);
                 }
-                chr => {
-                    *found_asterisk = chr == '*';
+                let number_end = range.start + number_length(text);
+                push_token!(
+                    CSSToken::Number(source[number_start..number_end].to_owned()),
+                    number_start..number_end
+                );
+                if let SyntaxToken::Percentage(_) = token {
+                    push_token!(CSSToken::Percentage, number_end..range.end);
+                } else if number_end < range.end {
+                    push_token!(
+                        CSSToken::Ident(source[number_end..range.end].to_owned()),
+                        number_end..range.end
+                    );
                 }
-            },
-            ParsingState::None => {}
-        }
-
-        if state == ParsingState::None {
-            match chr {
-                'A'..='Z' | 'a'..='z' => set_state!(ParsingState::Ident),
-                '/' => set_state!(ParsingState::Comment {
-                    found_asterisk: true
-                }),
-                '.' => set_state!(ParsingState::Dot),
-                '"' => set_state!(ParsingState::String { escaped: false }),
-                '#' => set_state!(ParsingState::HashPrefixedValue),
-                '0'..='9' => set_state!(ParsingState::Number),
-                chr if chr.is_whitespace() => {
+            }
+            SyntaxToken::Delim(first) => {
+                let second = tokens.next_if_adjacent(range.end, |token| {
+                    matches!(token, SyntaxToken::Delim(second) if two_character_token(first, *second).is_some())
+                });
+                if let Some((SyntaxToken::Delim(second), second_range)) = second {
+                    let token = two_character_token(first, second).unwrap();
+                    push_token!(token, range.start..second_range.end);
                     continue;
                 }
-                chr => {
-                    let token = match chr {
-                        '{' => CSSToken::OpenCurly,
-                        '}' => CSSToken::CloseCurly,
-                        '(' => CSSToken::OpenBracket,
-                        ')' => CSSToken::CloseBracket,
-                        ':' => CSSToken::Colon,
-                        ';' => CSSToken::SemiColon,
-                        ',' => CSSToken::Comma,
-                        '>' => CSSToken::CloseAngle,
-                        '.' => CSSToken::Dot,
-                        '*' => CSSToken::Asterisk,
-                        '%' => CSSToken::Percentage,
-                        chr => {
-                            return Err(ParseError {
-                                reason: format!("Invalid character '{}'", chr),
-                                position: current_position!(),
-                            })
+                match first {
+                    '$' => match tokens
+                        .next_if_adjacent(range.end, |token| matches!(token, SyntaxToken::Ident(_)))
+                    {
+                        Some((_, name_range)) => push_token!(
+                            CSSToken::Variable(source[name_range.clone()].to_owned()),
+                            range.start..name_range.end
+                        ),
+                        None => return Err(invalid_character(first, range)),
+                    },
+                    '#' | '@' => {
+                        // SCSS `#{` and Less `@{` interpolation
+                        let is_interpolation = first == '#' || syntax == Syntax::Less;
+                        let open_curly = tokens.next_if_adjacent(range.end, |token| {
+                            is_interpolation && *token == SyntaxToken::OpenCurly
+                        });
+                        match (open_curly, first) {
+                            (Some((_, curly_range)), _) => {
+                                interpolations += 1;
+                                push_token!(
+                                    CSSToken::InterpolationStart,
+                                    range.start..curly_range.end
+                                )
+                            }
+                            // A `#` without a name is a delim, which is not valid anywhere
+                            (None, '#') => return Err(invalid_character(first, range)),
+                            (None, _) => {
+                                return Err(ParseError {
+                                    reason: "Expected name after '@'".to_owned(),
+                                    position: span(range),
+                                })
+                            }
                         }
-                    };
-                    start = idx;
-                    push_token!(token);
-                    continue;
+                    }
+                    chr => match delim_token(chr) {
+                        Some(token) => push_token!(token, range),
+                        None => return Err(invalid_character(chr, range)),
+                    },
                 }
             }
+            SyntaxToken::Colon => push_token!(CSSToken::Colon, range),
+            SyntaxToken::Semicolon => push_token!(CSSToken::SemiColon, range),
+            SyntaxToken::Comma => push_token!(CSSToken::Comma, range),
+            SyntaxToken::OpenSquareBracket => push_token!(CSSToken::OpenSquareBracket, range),
+            SyntaxToken::CloseSquareBracket => push_token!(CSSToken::CloseSquareBracket, range),
+            SyntaxToken::OpenBracket => push_token!(CSSToken::OpenBracket, range),
+            SyntaxToken::CloseBracket => push_token!(CSSToken::CloseBracket, range),
+            SyntaxToken::OpenCurly => push_token!(CSSToken::OpenCurly, range),
+            SyntaxToken::CloseCurly => push_token!(CSSToken::CloseCurly, range),
         }
     }
 
-    let end_of_source = source.len();
+    push_token!(CSSToken::EOS, source.len()..source.len());
+    Ok(())
+}
 
-    match state {
-        ParsingState::Ident => {
-            sender.push(Token(
-                CSSToken::Ident(source[start..].to_owned()),
-                Span {
-                    start,
-                    end: end_of_source,
-                    source_id,
-                },
-            ));
-        }
-        ParsingState::Number => {
-            sender.push(Token(
-                CSSToken::Number(source[start..].to_owned()),
-                Span {
-                    start,
-                    end: end_of_source,
-                    source_id,
-                },
-            ));
+/// [`Tokenizer`] with one token of lookahead, for joining tokens e.g. `<` and `=`
+struct Lookahead<'a> {
+    tokenizer: Tokenizer<'a>,
+    peeked: Option<(SyntaxToken, Range<usize>)>,
+}
+
+impl Lookahead<'_> {
+    fn next(&mut self) -> Option<(SyntaxToken, Range<usize>)> {
+        self.peeked.take().or_else(|| self.tokenizer.next())
+    }
+
+    /// The next token if it starts at `end` (without whitespace between) and `predicate` returns
+    /// true for it
+    fn next_if_adjacent(
+        &mut self,
+        end: usize,
+        predicate: impl Fn(&SyntaxToken) -> bool,
+    ) -> Option<(SyntaxToken, Range<usize>)> {
+        if self.peeked.is_none() {
+            self.peeked = self.tokenizer.next();
         }
-        ParsingState::HashPrefixedValue => {
-            sender.push(Token(
-                CSSToken::HashPrefixedValue(source[(start + 1)..].to_owned()),
-                Span {
-                    start,
-                    end: end_of_source,
-                    source_id,
-                },
-            ));
+        match self.peeked {
+            Some((ref token, ref range)) if range.start == end && predicate(token) => {
+                self.peeked.take()
+            }
+            _ => None,
         }
-        ParsingState::Comment { .. } => {
-            return Err(ParseError {
-                reason: "Could not find end to comment".to_owned(),
-                position: Span {
-                    start,
-                    end: end_of_source,
-                    source_id,
-                },
-            })
+    }
+
+    fn set_position(&mut self, position: usize) {
+        self.peeked = None;
+        self.tokenizer.set_position(position);
+    }
+}
+
+/// The end of a custom property value: the next `;`, `!` or unmatched closing bracket, or the end
+/// of the source
+fn custom_property_value_end(tokens: &mut Lookahead, source_length: usize) -> usize {
+    let mut depth = 0u32;
+    while let Some((token, range)) = tokens.next() {
+        match token {
+            SyntaxToken::OpenBracket
+            | SyntaxToken::OpenSquareBracket
+            | SyntaxToken::OpenCurly
+            | SyntaxToken::Function(_) => depth += 1,
+            SyntaxToken::CloseBracket
+            | SyntaxToken::CloseSquareBracket
+            | SyntaxToken::CloseCurly => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => return range.start,
+            },
+            SyntaxToken::Semicolon | SyntaxToken::Delim('!') if depth == 0 => return range.start,
+            _ => {}
         }
-        ParsingState::String { .. } => {
-            return Err(ParseError {
-                reason: "Could not find end to string".to_owned(),
-                position: Span {
-                    start,
-                    end: end_of_source,
-                    source_id,
-                },
-            })
+    }
+    source_length
+}
+
+/// Whether the statement the tokenizer is in is a declaration, ending with a `;` or the `}` of
+/// its block rather than a `{` e.g. `a:hover { ... }`. Moves the tokenizer
+fn is_declaration_ahead(tokens: &mut Lookahead) -> bool {
+    // Number of `#{`s without their `}`
+    let mut interpolations = 0u32;
+    while let Some((token, range)) = tokens.next() {
+        match token {
+            SyntaxToken::Delim('#' | '@') => {
+                let open_curly =
+                    tokens.next_if_adjacent(range.end, |token| *token == SyntaxToken::OpenCurly);
+                if open_curly.is_some() {
+                    interpolations += 1;
+                }
+            }
+            SyntaxToken::CloseCurly if interpolations > 0 => interpolations -= 1,
+            SyntaxToken::Semicolon | SyntaxToken::CloseCurly => return true,
+            SyntaxToken::OpenCurly => return false,
+            _ => {}
         }
-        ParsingState::Dot => {
-            return Err(ParseError {
-                reason: "Found trailing \".\"".to_owned(),
-                position: Span {
-                    start,
-                    end: end_of_source,
-                    source_id,
-                },
-            })
+    }
+    true
+}
+
+/// The content of a url token without whitespace, and its offset in the token
+fn url_content(text: &str) -> (usize, &str) {
+    let content_start = text.find('(').unwrap() + 1;
+    let content = &text[content_start..];
+    let content = content.strip_suffix(')').unwrap_or(content);
+    let whitespace = content.len() - content.trim_start().len();
+    (content_start + whitespace, content.trim())
+}
+
+/// Whether a string token ends with its quote. Strings at the end of the source may not
+fn is_terminated_string(text: &str) -> bool {
+    let quote = text.chars().next().unwrap();
+    text[1..]
+        .strip_suffix(quote)
+        .is_some_and(|rest| rest.chars().rev().take_while(|chr| *chr == '\\').count() % 2 == 0)
+}
+
+/// Content of a string token as written. Single quoted strings are converted to the content of
+/// a double quoted string
+fn string_content(text: &str) -> String {
+    let quote = text.chars().next().unwrap();
+    let mut content = &text[1..];
+    let escapes_before_end = content
+        .strip_suffix(quote)
+        .map(|rest| rest.chars().rev().take_while(|chr| *chr == '\\').count());
+    if escapes_before_end.is_some_and(|count| count % 2 == 0) {
+        content = &content[..(content.len() - 1)];
+    }
+    if quote == '"' {
+        return content.to_owned();
+    }
+    let mut converted = String::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => match chars.next() {
+                Some('\'') => converted.push('\''),
+                Some(escaped) => {
+                    converted.push('\\');
+                    converted.push(escaped);
+                }
+                None => converted.push('\\'),
+            },
+            '"' => converted.push_str("\\\""),
+            chr => converted.push(chr),
         }
-        ParsingState::None => {}
     }
+    converted
+}
 
-    sender.push(Token(
-        CSSToken::EOS,
-        Span {
-            start: end_of_source,
-            end: end_of_source,
-            source_id,
-        },
-    ));
+/// Token for two adjacent characters e.g. `<=`
+fn two_character_token(first: char, second: char) -> Option<CSSToken> {
+    match (first, second) {
+        ('<', '=') => Some(CSSToken::LessThanEqual),
+        ('>', '=') => Some(CSSToken::GreaterThanEqual),
+        ('~', '=') => Some(CSSToken::IncludesMatch),
+        ('|', '=') => Some(CSSToken::DashMatch),
+        ('|', '|') => Some(CSSToken::Column),
+        ('^', '=') => Some(CSSToken::PrefixMatch),
+        ('$', '=') => Some(CSSToken::SuffixMatch),
+        ('*', '=') => Some(CSSToken::SubstringMatch),
+        ('=', '=') => Some(CSSToken::DoubleEqual),
+        ('!', '=') => Some(CSSToken::NotEqual),
+        _ => None,
+    }
+}
 
-    Ok(())
+/// Token for a delim character not followed by a character which would make it a two
+/// character token
+fn delim_token(chr: char) -> Option<CSSToken> {
+    match chr {
+        '.' => Some(CSSToken::Dot),
+        '<' => Some(CSSToken::OpenAngle),
+        '>' => Some(CSSToken::CloseAngle),
+        '/' => Some(CSSToken::Slash),
+        '*' => Some(CSSToken::Asterisk),
+        '~' => Some(CSSToken::Tilde),
+        '=' => Some(CSSToken::Equal),
+        '!' => Some(CSSToken::Exclamation),
+        '%' => Some(CSSToken::Percentage),
+        '+' => Some(CSSToken::Plus),
+        '-' => Some(CSSToken::Minus),
+        '&' => Some(CSSToken::Ampersand),
+        _ => None,
+    }
 }
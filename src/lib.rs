@@ -2,19 +2,50 @@
 //!
 //! Simple CSS parser and "renderer"
 
+mod at_rules;
+mod color;
+mod compiler;
+mod declarations;
+mod functions;
+mod interpolation;
+mod keyframes;
+mod less;
 mod lexer;
+mod media;
+mod modules;
 mod rules;
+mod sass;
+mod scss;
 mod selectors;
+mod tokenizer;
 mod values;
 
+pub use at_rules::{AtRule, AtRuleBlock};
+pub use color::Color;
+pub use compiler::{compile, compile_with_options, CompileOptions};
+pub use declarations::Declaration;
 use derive_more::From;
-pub use lexer::{lex_source, CSSToken};
+pub use functions::{FunctionRegistry, NativeFunction};
+pub use interpolation::{Interpolation, InterpolationPart};
+pub use keyframes::{Keyframe, KeyframeSelector, KeyframesRule};
+pub use lexer::{lex_source, lex_source_with_syntax, CSSToken, Syntax};
+pub use media::{
+    MediaComparison, MediaCondition, MediaEnvironment, MediaFeature, MediaFeatureValue, MediaQuery,
+    MediaQueryList, MediaRule, MediaTypeModifier,
+};
+pub use modules::{FileLoader, FileSystemLoader, MemoryFileLoader};
 pub use rules::Rule;
+pub use scss::{
+    Argument, BlockItem, EachStatement, Extend, ForStatement, ForwardFilter, ForwardStatement,
+    FunctionDefinition, IfStatement, ImportStatement, Include, MixinDefinition, Parameter,
+    ReturnStatement, ScssStatement, UseStatement, VariableDeclaration, WhileStatement,
+};
 pub use selectors::Selector;
 use source_map::{Counter, SourceId, Span, StringWithSourceMap, ToString};
 use std::{mem, path::Path};
-use tokenizer_lib::{BufferedTokenQueue, Token, TokenReader};
-pub use values::CSSValue;
+pub use tokenizer::{SyntaxToken, Tokenizer};
+use tokenizer_lib::{BufferedTokenQueue, Token, TokenReader, TokenSender};
+pub use values::{BinaryOperator, CSSValue, Number, UnaryOperator};
 
 #[derive(Debug)]
 pub struct ParseError {
@@ -75,6 +106,114 @@ pub(crate) fn token_as_ident(token: Token<CSSToken, Span>) -> Result<(String, Sp
     }
 }
 
+/// Parses the next item (declaration, rule or at-rule) in a stylesheet or block in isolation. If
+/// `parser` errors the rest of the item is skipped (up to the next `;` or the matching `}`) and the
+/// error is collected. Blocks still open at the end of the source are closed, as in CSS Syntax
+/// Level 3, with an error
+pub(crate) fn recover<T>(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+    errors: &mut Vec<ParseError>,
+    parser: impl FnOnce(
+        &mut BufferedTokenQueue<CSSToken, Span>,
+        &mut Vec<ParseError>,
+    ) -> Result<T, ParseError>,
+) -> Option<T> {
+    let mut item_reader = BufferedTokenQueue::new();
+    let mut is_empty = true;
+    let mut depth = 0u32;
+    // The `}` of a `#{...}` does not close a block
+    let mut in_interpolation = false;
+    loop {
+        match reader.peek().unwrap() {
+            Token(CSSToken::EOS, _) => break,
+            // End of parent block. Consumed when it is stray at the top level to make progress
+            Token(CSSToken::CloseCurly, _) if depth == 0 && !in_interpolation && !is_empty => break,
+            _ => {}
+        }
+        let token = reader.next().unwrap();
+        let is_end = match token.0 {
+            CSSToken::Comment(_) => is_empty,
+            CSSToken::SemiColon => depth == 0,
+            CSSToken::InterpolationStart => {
+                in_interpolation = true;
+                false
+            }
+            CSSToken::CloseCurly if in_interpolation => {
+                in_interpolation = false;
+                false
+            }
+            CSSToken::OpenCurly => {
+                depth += 1;
+                false
+            }
+            CSSToken::CloseCurly => {
+                depth = depth.saturating_sub(1);
+                // `@if { ... } @else { ... }` is one item
+                depth == 0
+                    && !matches!(
+                        reader.peek(),
+                        Some(Token(CSSToken::AtKeyword(name), _)) if name == "else"
+                    )
+            }
+            _ => false,
+        };
+        item_reader.push(token);
+        is_empty = false;
+        if is_end {
+            break;
+        }
+    }
+    let next_start = reader.peek().unwrap().1.start;
+    let end = Span {
+        start: next_start,
+        end: next_start,
+        source_id: reader.peek().unwrap().1.source_id,
+    };
+    if depth > 0 {
+        errors.push(ParseError {
+            reason: "Expected 'CloseCurly' found 'EOS'".to_owned(),
+            position: end.clone(),
+        });
+        for _ in 0..depth {
+            item_reader.push(Token(CSSToken::CloseCurly, end.clone()));
+        }
+    }
+    item_reader.push(Token(CSSToken::EOS, end));
+
+    let result = parser(&mut item_reader, errors).and_then(|item| {
+        if let Some(Token(CSSToken::SemiColon, _)) = item_reader.peek() {
+            item_reader.next();
+        }
+        item_reader.expect_next(CSSToken::EOS)?;
+        Ok(item)
+    });
+    match result {
+        Ok(item) => Some(item),
+        Err(err) => {
+            errors.push(err);
+            None
+        }
+    }
+}
+
+/// Ends the stream read by a parsing thread. Lexing stops at an error so an `EOS` is sent at its
+/// position. Dropping the sender means lookahead past the `EOS` finds the end of the stream rather
+/// than waiting for a token which is never sent
+#[cfg(not(target_arch = "wasm32"))]
+fn end_parallel_stream(
+    mut sender: tokenizer_lib::ParallelTokenSender<CSSToken, Span>,
+    lexer_result: &Result<(), ParseError>,
+) {
+    if let Err(ParseError { position, .. }) = lexer_result {
+        let position = Span {
+            start: position.start,
+            end: position.start,
+            source_id: position.source_id,
+        };
+        sender.push(Token(CSSToken::EOS, position));
+    }
+}
+
 pub trait ASTNode: Sized + Send + Sync + 'static {
     /// Parses structure from string
     #[cfg(not(target_arch = "wasm32"))]
@@ -95,11 +234,14 @@ pub trait ASTNode: Sized + Send + Sync + 'static {
                 }
                 res
             });
-            lexer::lex_source(&string, &mut sender, source_id, None)?;
-            parsing_thread.join().expect("Parsing thread panicked")
+            let lexer_result = lexer::lex_source(&string, &mut sender, source_id, None);
+            end_parallel_stream(sender, &lexer_result);
+            let parse_result = parsing_thread.join().expect("Parsing thread panicked");
+            lexer_result?;
+            parse_result
         } else {
             let mut reader = BufferedTokenQueue::new();
-            lexer::lex_source(&string, &mut reader, SourceId::null(), offset)?;
+            lexer::lex_source(&string, &mut reader, source_id, offset)?;
             let this = Self::from_reader(&mut reader);
             reader.expect_next(CSSToken::EOS)?;
             this
@@ -141,49 +283,133 @@ pub trait ASTNode: Sized + Send + Sync + 'static {
 #[derive(Debug)]
 pub struct StyleSheet {
     pub entries: Vec<Entry>,
+    /// The language the stylesheet is written in. [`compile`] leaves plain CSS as written
+    pub syntax: Syntax,
 }
 
 #[derive(Debug, From)]
 pub enum Entry {
     Rule(Rule),
+    AtRule(AtRule),
+    MediaRule(MediaRule),
+    KeyframesRule(KeyframesRule),
+    ScssStatement(ScssStatement),
     Comment(String),
 }
 
+impl Entry {
+    /// If `errors` is `Some`, invalid items in blocks are skipped and their errors collected
+    pub(crate) fn from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self, ParseError> {
+        if matches!(reader.peek(), Some(Token(CSSToken::AtKeyword(name), _)) if name == "import")
+            && ImportStatement::is_scss_import_ahead(reader)
+        {
+            return Ok(ScssStatement::from_reader(reader)?.into());
+        }
+        // `@media #{$query}` is parsed as a at-rule until its queries are known
+        let is_media = matches!(
+            reader.peek(),
+            Some(Token(CSSToken::AtKeyword(name), _)) if name.eq_ignore_ascii_case("media")
+        );
+        let is_interpolated_media = is_media
+            && Interpolation::is_ahead(reader, |token| {
+                matches!(
+                    token,
+                    CSSToken::OpenCurly | CSSToken::SemiColon | CSSToken::CloseCurly
+                )
+            });
+        match reader.peek().unwrap() {
+            Token(CSSToken::Comment(_), _) => {
+                if let Token(CSSToken::Comment(comment), _) = reader.next().unwrap() {
+                    Ok(Entry::Comment(comment))
+                } else {
+                    unreachable!()
+                }
+            }
+            Token(CSSToken::AtKeyword(name), _)
+                if name.eq_ignore_ascii_case("media") && !is_interpolated_media =>
+            {
+                Ok(MediaRule::from_reader_with_recovery(reader, errors)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _)
+                if KeyframesRule::is_keyframes_at_rule(name).is_some() =>
+            {
+                Ok(KeyframesRule::from_reader_with_recovery(reader, errors)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if ScssStatement::is_scss_at_rule(name) => {
+                Ok(ScssStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(_), _) => {
+                Ok(AtRule::from_reader_with_recovery(reader, errors)?.into())
+            }
+            Token(CSSToken::Variable(_), _) => Ok(ScssStatement::from_reader(reader)?.into()),
+            _ => Ok(Rule::from_reader_with_recovery(reader, errors, false)?.into()),
+        }
+    }
+
+    pub(crate) fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        match self {
+            Entry::Rule(rule) => {
+                rule.to_string_from_buffer(buf, settings, depth);
+            }
+            Entry::AtRule(at_rule) => {
+                at_rule.to_string_from_buffer(buf, settings, depth);
+            }
+            Entry::MediaRule(media_rule) => {
+                media_rule.to_string_from_buffer(buf, settings, depth);
+            }
+            Entry::KeyframesRule(keyframes_rule) => {
+                keyframes_rule.to_string_from_buffer(buf, settings, depth);
+            }
+            Entry::ScssStatement(statement) => {
+                statement.to_string_from_buffer(buf, settings, depth);
+            }
+            Entry::Comment(comment) => {
+                if !settings.minify {
+                    buf.push_str("/*");
+                    buf.push_str_contains_new_line(comment);
+                    buf.push_str("*/");
+                }
+            }
+        }
+    }
+}
+
 impl StyleSheet {
-    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+    /// If `errors` is `Some`, invalid rules and declarations are skipped and their errors collected
+    fn from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        mut errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self, ParseError> {
         let mut entries: Vec<Entry> = Vec::new();
         while let Some(peek) = reader.peek() {
-            match peek {
-                Token(CSSToken::EOS, _) => break,
-                Token(CSSToken::Comment(_), _) => {
-                    if let Token(CSSToken::Comment(comment), _) = reader.next().unwrap() {
-                        entries.push(Entry::Comment(comment));
-                    } else {
-                        unreachable!()
-                    }
-                }
-                _ => {
-                    entries.push(Rule::from_reader(reader)?.into());
-                }
+            if let Token(CSSToken::EOS, _) = peek {
+                break;
             }
+            let entry = match errors.as_deref_mut() {
+                Some(errors) => recover(reader, errors, |reader, errors| {
+                    Entry::from_reader(reader, Some(errors))
+                }),
+                None => Some(Entry::from_reader(reader, None)?),
+            };
+            entries.extend(entry);
         }
-        Ok(Self { entries })
+        Ok(Self {
+            entries,
+            syntax: Syntax::Css,
+        })
     }
 
     fn to_string_from_buffer(&self, buf: &mut impl ToString, settings: &ToStringSettings) {
         for (idx, entry) in self.entries.iter().enumerate() {
-            match entry {
-                Entry::Rule(rule) => {
-                    rule.to_string_from_buffer(buf, settings, 0);
-                }
-                Entry::Comment(comment) => {
-                    if !settings.minify {
-                        buf.push_str("/*");
-                        buf.push_str_contains_new_line(comment);
-                        buf.push_str("*/");
-                    }
-                }
-            }
+            entry.to_string_from_buffer(buf, settings, 0);
             if !settings.minify && idx + 1 < self.entries.len() {
                 buf.push_new_line();
                 buf.push_new_line();
@@ -218,59 +444,264 @@ impl StyleSheet {
         use std::fs;
 
         let path_buf = path.as_ref().to_path_buf();
+        let syntax = Syntax::from_path(&path_buf);
         let source = fs::read_to_string(path).unwrap();
         let source_id = SourceId::new(path_buf, source.clone());
-        Self::from_string(source, source_id)
+        Self::from_string_with_syntax(source, source_id, syntax)
     }
 
     pub fn from_string(source: String, source_id: SourceId) -> Result<Self, ParseError> {
+        Self::from_string_with_syntax(source, source_id, Syntax::Css)
+    }
+
+    /// Same as [`StyleSheet::from_string`] for a source in the given syntax
+    pub fn from_string_with_syntax(
+        source: String,
+        source_id: SourceId,
+        syntax: Syntax,
+    ) -> Result<Self, ParseError> {
         use std::thread;
         use tokenizer_lib::ParallelTokenQueue;
 
         let (mut sender, mut reader) = ParallelTokenQueue::new();
         let parsing_thread = thread::spawn(move || {
-            let res = Self::from_reader(&mut reader);
+            let res = Self::from_reader(&mut reader, None);
             if res.is_ok() {
                 reader.expect_next(CSSToken::EOS)?;
             }
             res
         });
 
-        lexer::lex_source(&source, &mut sender, source_id, None)?;
-        parsing_thread.join().unwrap()
+        let lexer_result =
+            lexer::lex_source_with_syntax(&source, &mut sender, source_id, None, syntax);
+        end_parallel_stream(sender, &lexer_result);
+        let parse_result = parsing_thread.join().unwrap();
+        lexer_result?;
+        let mut style_sheet = parse_result?;
+        style_sheet.resolve_syntax(syntax)?;
+        Ok(style_sheet)
+    }
+
+    /// Same as [`StyleSheet::from_path`] but recovers from errors, see [`StyleSheet::from_string_recovering`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path_recovering(path: impl AsRef<Path>) -> (Self, Vec<ParseError>) {
+        use std::fs;
+
+        let path_buf = path.as_ref().to_path_buf();
+        let syntax = Syntax::from_path(&path_buf);
+        let source = fs::read_to_string(path).unwrap();
+        let source_id = SourceId::new(path_buf, source.clone());
+        Self::from_string_recovering_with_syntax(source, source_id, syntax)
+    }
+
+    /// Parses the source, skipping invalid declarations (up to the next `;`) and invalid rules (up to
+    /// the matching `}`) as browsers do. Returns the partial stylesheet and all the errors found.
+    /// Tokenizing errors stop parsing at the position of the error
+    pub fn from_string_recovering(source: String, source_id: SourceId) -> (Self, Vec<ParseError>) {
+        Self::from_string_recovering_with_syntax(source, source_id, Syntax::Css)
+    }
+
+    /// Same as [`StyleSheet::from_string_recovering`] for a source in the given syntax
+    pub fn from_string_recovering_with_syntax(
+        source: String,
+        source_id: SourceId,
+        syntax: Syntax,
+    ) -> (Self, Vec<ParseError>) {
+        let mut reader = BufferedTokenQueue::new();
+        let lexer_error =
+            lexer::lex_source_with_syntax(&source, &mut reader, source_id, None, syntax).err();
+        if let Some(ref lexer_error) = lexer_error {
+            let position = Span {
+                start: lexer_error.position.start,
+                end: lexer_error.position.start,
+                source_id,
+            };
+            reader.push(Token(CSSToken::EOS, position));
+        }
+        let mut errors = Vec::new();
+        let mut style_sheet = Self::from_reader(&mut reader, Some(&mut errors))
+            .expect("recovering parse of stylesheet should not error");
+        if let Err(error) = style_sheet.resolve_syntax(syntax) {
+            errors.push(error);
+        }
+        if let Some(lexer_error) = lexer_error {
+            // Errors from the source being cut short are caused by the lexer error
+            errors.retain(|error| error.position.start < lexer_error.position.start);
+            errors.push(lexer_error);
+        }
+        (style_sheet, errors)
+    }
+
+    /// After parsing, changes which depend on the syntax of the source
+    fn resolve_syntax(&mut self, syntax: Syntax) -> Result<(), ParseError> {
+        self.syntax = syntax;
+        match syntax {
+            Syntax::Css => plain_css_values_in_entries(&mut self.entries),
+            Syntax::Less => {
+                less::resolve_variables(&mut self.entries)?;
+                less::resolve_mixins(&mut self.entries);
+            }
+            Syntax::Scss | Syntax::Sass => {}
+        }
+        Ok(())
     }
 }
 
-/// Will "raise" or "unnest" rules in the stylesheet. Mutates StyleSheet
-pub fn raise_nested_rules(stylesheet: &mut StyleSheet) {
-    let mut raised_rules: Vec<Rule> = Vec::new();
-    for entry in stylesheet.entries.iter_mut() {
-        if let Entry::Rule(rule) = entry {
-            raise_subrules(rule, &mut raised_rules);
+/// Values are parsed with SCSS operators, see [`CSSValue::into_plain_css`]
+fn plain_css_values_in_entries(entries: &mut [Entry]) {
+    fn plain_css_declarations(declarations: &mut [Declaration]) {
+        for declaration in declarations {
+            let value = mem::replace(&mut declaration.value, CSSValue::List(Vec::new()));
+            declaration.value = value.into_plain_css();
+        }
+    }
+
+    for entry in entries {
+        match entry {
+            Entry::Rule(rule) => {
+                let mut rules = vec![rule];
+                while let Some(rule) = rules.pop() {
+                    plain_css_declarations(&mut rule.declarations);
+                    rules.extend(rule.nested_rules.iter_mut().flatten());
+                }
+            }
+            Entry::AtRule(AtRule {
+                block: Some(block), ..
+            })
+            | Entry::MediaRule(MediaRule { block, .. }) => {
+                plain_css_declarations(&mut block.declarations);
+                plain_css_values_in_entries(&mut block.entries);
+            }
+            Entry::KeyframesRule(keyframes_rule) => {
+                for keyframe in keyframes_rule.keyframes.iter_mut() {
+                    plain_css_declarations(&mut keyframe.declarations);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How [`resolve_nesting`] outputs rules nested in rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NestingMode {
+    /// Raises nested rules, combining their selectors with their parents', see
+    /// [`raise_nested_rules`]
+    #[default]
+    Flatten,
+    /// Keeps nested rules for browsers which support
+    /// [CSS Nesting](https://www.w3.org/TR/css-nesting-1/). A `&` is added before nested selectors
+    /// starting with a tag name. Rules with a `&` suffix e.g. `&__title` are raised as in
+    /// [`NestingMode::Flatten`] as CSS Nesting cannot express them
+    Preserve,
+}
+
+/// Will "raise" or "unnest" rules in the stylesheet. Mutates StyleSheet. Errors if a `&` suffix
+/// cannot be added to a parent selector
+pub fn raise_nested_rules(stylesheet: &mut StyleSheet) -> Result<(), ParseError> {
+    resolve_nesting(stylesheet, NestingMode::Flatten)
+}
+
+/// Makes nested rules valid CSS, either raising them or keeping them for CSS Nesting. Mutates
+/// StyleSheet
+pub fn resolve_nesting(stylesheet: &mut StyleSheet, mode: NestingMode) -> Result<(), ParseError> {
+    resolve_nesting_in_entries(&mut stylesheet.entries, mode)
+}
+
+/// Raised rules are placed directly after the rule they were nested under. Also resolves rules
+/// under at-rule blocks (e.g. `@media`)
+fn resolve_nesting_in_entries(
+    entries: &mut Vec<Entry>,
+    mode: NestingMode,
+) -> Result<(), ParseError> {
+    for entry in mem::take(entries) {
+        match entry {
+            Entry::Rule(mut rule) => {
+                let mut raised_rules: Vec<Rule> = Vec::new();
+                match mode {
+                    NestingMode::Flatten => raise_subrules(&mut rule, &mut raised_rules)?,
+                    NestingMode::Preserve => {
+                        let parent_selectors = rule.selectors.clone();
+                        preserve_subrules(&mut rule, &parent_selectors, &mut raised_rules)?
+                    }
+                }
+                entries.push(rule.into());
+                entries.extend(raised_rules.into_iter().map(Into::into));
+            }
+            Entry::AtRule(mut at_rule) => {
+                if let Some(ref mut block) = at_rule.block {
+                    resolve_nesting_in_entries(&mut block.entries, mode)?;
+                }
+                entries.push(at_rule.into());
+            }
+            Entry::MediaRule(mut media_rule) => {
+                resolve_nesting_in_entries(&mut media_rule.block.entries, mode)?;
+                entries.push(media_rule.into());
+            }
+            entry => entries.push(entry),
         }
     }
-    stylesheet
-        .entries
-        .extend(raised_rules.into_iter().map(Into::into));
+    Ok(())
 }
 
 /// Will remove nested rules leaving declarations in place
-fn raise_subrules(rule: &mut Rule, raised_rules: &mut Vec<Rule>) {
+fn raise_subrules(rule: &mut Rule, raised_rules: &mut Vec<Rule>) -> Result<(), ParseError> {
     if let Some(nested_rules) = &mut rule.nested_rules {
         // Changing nested rule here
         for mut nested_rule in nested_rules.drain(..) {
-            let old_selectors = mem::replace(&mut nested_rule.selectors, vec![]);
-            for selector in rule.selectors.iter() {
-                for nested_selector in old_selectors.iter().cloned() {
-                    nested_rule
-                        .selectors
-                        .push(selector.nest_selector(nested_selector));
-                }
-            }
-            raise_subrules(&mut nested_rule, raised_rules);
+            nested_rule.selectors = nest_selectors(&rule.selectors, &nested_rule.selectors)?;
+            let mut raised_subrules = Vec::new();
+            raise_subrules(&mut nested_rule, &mut raised_subrules)?;
             raised_rules.push(nested_rule);
+            raised_rules.append(&mut raised_subrules);
         }
     }
+    Ok(())
+}
+
+/// Keeps nested rules which CSS Nesting can express. `parent_selectors` are the selectors of
+/// `rule` with the selectors of the rules it is nested in
+fn preserve_subrules(
+    rule: &mut Rule,
+    parent_selectors: &[Selector],
+    raised_rules: &mut Vec<Rule>,
+) -> Result<(), ParseError> {
+    if let Some(nested_rules) = &mut rule.nested_rules {
+        for mut nested_rule in mem::take(nested_rules) {
+            let full_selectors = nest_selectors(parent_selectors, &nested_rule.selectors)?;
+            if nested_rule
+                .selectors
+                .iter()
+                .any(Selector::has_parent_suffix)
+            {
+                nested_rule.selectors = full_selectors.clone();
+                let mut raised_subrules = Vec::new();
+                preserve_subrules(&mut nested_rule, &full_selectors, &mut raised_subrules)?;
+                raised_rules.push(nested_rule);
+                raised_rules.append(&mut raised_subrules);
+            } else {
+                nested_rule.selectors = mem::take(&mut nested_rule.selectors)
+                    .into_iter()
+                    .map(Selector::with_nesting_selector)
+                    .collect();
+                preserve_subrules(&mut nested_rule, &full_selectors, raised_rules)?;
+                nested_rules.push(nested_rule);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Each of `nested` under each of `parents`
+fn nest_selectors(parents: &[Selector], nested: &[Selector]) -> Result<Vec<Selector>, ParseError> {
+    parents
+        .iter()
+        .flat_map(|parent| {
+            nested
+                .iter()
+                .map(move |selector| parent.nest_selector(selector.clone()))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -293,4 +724,280 @@ mod tests {
         let style_sheet = StyleSheet::from_string(source.clone(), SourceId::null()).unwrap();
         assert_eq!(style_sheet.to_string(None), source.replace('\r', ""));
     }
+
+    #[test]
+    fn at_rules() {
+        let source = include_str!("../examples/example5.css").to_owned();
+        let style_sheet = StyleSheet::from_string(source.clone(), SourceId::null()).unwrap();
+        assert_eq!(style_sheet.entries.len(), 3);
+        if let Entry::AtRule(at_rule) = &style_sheet.entries[1] {
+            assert_eq!(at_rule.name, "font-face");
+            assert_eq!(at_rule.block.as_ref().unwrap().declarations.len(), 2);
+        } else {
+            panic!("Expected at-rule, found {:?}", style_sheet.entries[1]);
+        }
+        if let Entry::MediaRule(media_rule) = &style_sheet.entries[2] {
+            assert_eq!(media_rule.queries.queries.len(), 1);
+            assert_eq!(media_rule.block.entries.len(), 2);
+        } else {
+            panic!("Expected media rule, found {:?}", style_sheet.entries[2]);
+        }
+        assert_eq!(style_sheet.to_string(None), source.replace('\r', ""));
+    }
+
+    #[test]
+    fn at_rules_minified() {
+        let style_sheet = StyleSheet::from_string(
+            "@media print { h1 { color: red; } } @import \"a.css\";".to_owned(),
+            SourceId::null(),
+        )
+        .unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "@media print{h1{color:red;}}@import \"a.css\";"
+        );
+    }
+
+    #[test]
+    fn nested_rules_round_trip() {
+        let source = "nav {
+    color: red;
+    ul {
+        margin: 0;
+        li {}
+    }
+    &:hover {
+        color: blue;
+    }
+}
+
+@media print {
+    nav {
+        a {
+            display: none;
+        }
+    }
+}";
+        let style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        assert_eq!(style_sheet.to_string(None), source);
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "nav{color:red;ul{margin:0;li{}}&:hover{color:blue;}}\
+            @media print{nav{a{display:none;}}}"
+        );
+    }
+
+    #[test]
+    fn pseudo_class_rules() {
+        let mut style_sheet = StyleSheet::from_string(
+            "nav { color: red; a:hover { color: blue; } }".to_owned(),
+            SourceId::null(),
+        )
+        .unwrap();
+        raise_nested_rules(&mut style_sheet).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "nav{color:red;}nav a:hover{color:blue;}"
+        );
+    }
+
+    #[test]
+    fn preserve_nesting() {
+        let mut style_sheet = StyleSheet::from_string(
+            "nav { color: red; a, .active { color: blue; &:hover { color: green; } } &__item { margin: 0; } }"
+                .to_owned(),
+            SourceId::null(),
+        )
+        .unwrap();
+        resolve_nesting(&mut style_sheet, NestingMode::Preserve).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "nav{color:red;& a,.active{color:blue;&:hover{color:green;}}}nav__item{margin:0;}"
+        );
+        assert_eq!(
+            style_sheet.to_string(None),
+            "nav {\n    color: red;\n    & a, .active {\n        color: blue;\n        \
+            &:hover {\n            color: green;\n        }\n    }\n}\n\nnav__item {\n    margin: 0;\n}"
+        );
+    }
+
+    #[test]
+    fn nested_leading_combinators() {
+        let source = "ul { > li { margin: 0; } + p, ~ .note { color: red; } }";
+        let parse = || StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        let mut style_sheet = parse();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "ul{>li{margin:0;}+p,~.note{color:red;}}"
+        );
+        raise_nested_rules(&mut style_sheet).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "ul{}ul>li{margin:0;}ul+p,ul~.note{color:red;}"
+        );
+        let mut style_sheet = parse();
+        resolve_nesting(&mut style_sheet, NestingMode::Preserve).unwrap();
+        assert_eq!(
+            style_sheet.to_string(None),
+            "ul {\n    > li {\n        margin: 0;\n    }\n    + p, ~ .note {\n        color: red;\n    }\n}"
+        );
+        // Only nested rules are relative to a parent
+        assert!(
+            StyleSheet::from_string("> li { margin: 0; }".to_owned(), SourceId::null()).is_err()
+        );
+    }
+
+    #[test]
+    fn parent_selector_rules() {
+        let mut style_sheet = StyleSheet::from_string(
+            ".card { &__title { color: red; } &:hover, .dark & { color: blue; } }".to_owned(),
+            SourceId::null(),
+        )
+        .unwrap();
+        raise_nested_rules(&mut style_sheet).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            ".card{}.card__title{color:red;}.card:hover,.dark .card{color:blue;}"
+        );
+    }
+
+    #[test]
+    fn important_declarations() {
+        let source = "h1 {\n    color: red !important;\n    margin: 0;\n}";
+        let style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        if let Entry::Rule(rule) = &style_sheet.entries[0] {
+            assert!(rule.declarations[0].important);
+            assert!(!rule.declarations[1].important);
+        } else {
+            panic!("Expected rule, found {:?}", style_sheet.entries[0]);
+        }
+        assert_eq!(style_sheet.to_string(None), source);
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "h1{color:red!important;margin:0;}"
+        );
+    }
+
+    #[test]
+    fn single_line_comments() {
+        let source =
+            "// Header\na { color: red; // Trailing\n    background: url(http://x.com/a.png); }";
+        let style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Scss)
+                .unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "a{color:red;background:url(http://x.com/a.png);}"
+        );
+        // `//` is not a comment in CSS
+        assert!(StyleSheet::from_string(source.to_owned(), SourceId::null()).is_err());
+    }
+
+    #[test]
+    fn recovering() {
+        let source = "h1 { color: red; margin: ; padding: 0 }
+.a { width: calc(1px; }
+h2 > { color: red }
+@media screen { .x { color: ) } .y { color: green } }
+footer { color: black }";
+        let (style_sheet, errors) =
+            StyleSheet::from_string_recovering(source.to_owned(), SourceId::null());
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert_eq!(
+            &source[errors[0].position.start..errors[0].position.end],
+            ";"
+        );
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "h1{color:red;padding:0;}.a{}@media screen{.x{}.y{color:green;}}footer{color:black;}"
+        );
+        // Same source errors on first error when not recovering
+        assert!(StyleSheet::from_string(source.to_owned(), SourceId::null()).is_err());
+
+        // Blocks open at the end of the source are closed
+        let source = "a { b: c } @media print { d { e: f; g { h: i";
+        let (style_sheet, errors) =
+            StyleSheet::from_string_recovering(source.to_owned(), SourceId::null());
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].reason, "Expected 'CloseCurly' found 'EOS'");
+        assert_eq!(errors[0].position.start, source.len());
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "a{b:c;}@media print{d{e:f;g{h:i;}}}"
+        );
+    }
+
+    #[test]
+    fn errors_at_end_of_source() {
+        // Lookahead reaching the end of the source must not wait for more tokens
+        for source in [
+            "}",
+            "a",
+            "a{b:c}}",
+            "a{b:c} )",
+            "@media screen",
+            "a { color: ^ }",
+        ] {
+            assert!(
+                StyleSheet::from_string(source.to_owned(), SourceId::null()).is_err(),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn css_syntax_tokens() {
+        let source = "<!-- .\\31 0, .café { --main-color: #fff; content: 'it\\'s \"q\"'; \
+            margin: 1e1px -.5em; background: url( a.png ) } -->
+            @font-face { unicode-range: U+0025-00FF, u+4??; }";
+        let style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            ".\\31 0,.café{--main-color:#fff;content:\"it's \\\"q\\\"\";margin:1e1px -.5em;\
+            background:url(a.png);}@font-face{unicode-range:U+0025-00FF,u+4??;}"
+        );
+
+        for (source, reason) in [
+            ("a { content: \"x\ny\" }", "Found newline in string"),
+            ("a { b: url(a b) }", "Invalid url"),
+            ("a { color: # }", "Invalid character '#'"),
+            ("a { content: \"x }", "Could not find end to string"),
+            ("a { b: c } /* d", "Could not find end to comment"),
+        ] {
+            let (_, errors) =
+                StyleSheet::from_string_recovering(source.to_owned(), SourceId::null());
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert_eq!(errors[0].reason, reason);
+        }
+    }
+
+    #[test]
+    fn comments_and_unicode_ranges() {
+        // Comments in statements and blocks of declarations are dropped. Unicode ranges are kept
+        // as written in declaration values, elsewhere `u+b` is a selector
+        let source = "/* a */ .a /* b */ .b { /* c */ c: /* d */ d; e: U+26; } \
+            @media print { /* e */ u+b { f: g; } }";
+        let style_sheet = StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            ".a .b{c:d;e:U+26;}@media print{u+b{f:g;}}"
+        );
+        assert!(matches!(&style_sheet.entries[0], Entry::Comment(comment) if comment == " a "));
+    }
+
+    #[test]
+    fn recovering_from_lexer_error() {
+        let (style_sheet, errors) = StyleSheet::from_string_recovering(
+            "a { color: red } b { color: ^ }".to_owned(),
+            SourceId::null(),
+        );
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].reason, "Invalid character '^'");
+        // The rule cut short by the lexer error is closed
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "a{color:red;}b{}"
+        );
+    }
 }
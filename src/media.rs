@@ -0,0 +1,849 @@
+use super::{
+    token_as_ident, ASTNode, AtRuleBlock, CSSToken, CSSValue, Number, ParseError, ToStringSettings,
+};
+use source_map::{Span, ToString};
+use std::collections::HashMap;
+use tokenizer_lib::{Token, TokenReader};
+
+/// A [`@media`](https://developer.mozilla.org/en-US/docs/Web/CSS/@media) rule
+#[derive(Debug)]
+pub struct MediaRule {
+    pub queries: MediaQueryList,
+    pub block: AtRuleBlock,
+    pub position: Option<Span>,
+}
+
+impl ASTNode for MediaRule {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        Self::from_reader_with_recovery(reader, None)
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@media");
+        if !self.queries.queries.is_empty() {
+            buf.push(' ');
+            self.queries.to_string_from_buffer(buf, settings, depth);
+        }
+        if !settings.minify {
+            buf.push(' ');
+        }
+        self.block.to_string_from_buffer(buf, settings, depth);
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl MediaRule {
+    /// If `errors` is `Some`, invalid items in the block are skipped and their errors collected
+    pub(crate) fn from_reader_with_recovery(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        errors: Option<&mut Vec<ParseError>>,
+    ) -> Result<Self, ParseError> {
+        let start_span = match reader.next().unwrap() {
+            Token(CSSToken::AtKeyword(name), position) if name.eq_ignore_ascii_case("media") => {
+                position
+            }
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected '@media' found '{:?}'", token),
+                    position,
+                })
+            }
+        };
+        let queries = MediaQueryList::from_reader(reader)?;
+        let (block, end_span) = AtRuleBlock::from_reader(reader, errors)?;
+        Ok(Self {
+            queries,
+            block,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+}
+
+/// Comma separated list of media queries, e.g. `screen and (min-width: 600px), print`
+#[derive(Debug, PartialEq, Eq)]
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+    pub position: Option<Span>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MediaQuery {
+    /// e.g. `screen`, `not print and (color)`
+    Type {
+        modifier: Option<MediaTypeModifier>,
+        /// Lowercase
+        media_type: String,
+        condition: Option<MediaCondition>,
+    },
+    /// e.g. `(min-width: 600px) and (orientation: landscape)`
+    Condition(MediaCondition),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MediaTypeModifier {
+    Not,
+    Only,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+}
+
+/// Feature names are lowercase
+#[derive(Debug, PartialEq, Eq)]
+pub enum MediaFeature {
+    /// e.g. `(color)`
+    Boolean(String),
+    /// e.g. `(min-width: 600px)`
+    Plain(String, MediaFeatureValue),
+    /// e.g. `(width >= 600px)`, `(400px <= width < 800px)`
+    Range {
+        start: Option<(MediaFeatureValue, MediaComparison)>,
+        name: String,
+        end: Option<(MediaComparison, MediaFeatureValue)>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MediaFeatureValue {
+    Value(CSSValue),
+    /// e.g. `16/9`
+    Ratio(Number, Number),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MediaComparison {
+    LessThan,
+    LessThanEqual,
+    GreaterThan,
+    GreaterThanEqual,
+    Equal,
+}
+
+/// Properties of the device that media queries are evaluated against
+#[derive(Debug, Clone)]
+pub struct MediaEnvironment {
+    /// e.g. `"screen"` or `"print"`
+    pub media_type: String,
+    /// Viewport width in CSS pixels
+    pub width: f64,
+    /// Viewport height in CSS pixels
+    pub height: f64,
+    /// Device pixels per CSS pixel
+    pub resolution: f64,
+    /// Initial font size in pixels, used for `em` and `rem` lengths
+    pub font_size: f64,
+    /// Bits per color component. 0 if the device is not a color device
+    pub color: u32,
+    /// Values of other discrete features, e.g. `"prefers-color-scheme"` → `"dark"`
+    pub discrete_features: HashMap<String, String>,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        Self {
+            media_type: "screen".to_owned(),
+            width: 1024.,
+            height: 768.,
+            resolution: 1.,
+            font_size: 16.,
+            color: 8,
+            discrete_features: HashMap::new(),
+        }
+    }
+}
+
+impl ASTNode for MediaQueryList {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let mut queries = Vec::new();
+        let mut position: Option<Span> = None;
+        while !matches!(
+            reader.peek().unwrap().0,
+            CSSToken::OpenCurly | CSSToken::SemiColon | CSSToken::EOS
+        ) {
+            let (query, query_position) = query_from_reader(reader)?;
+            queries.push(query);
+            position = Some(match position {
+                Some(position) => position.union(&query_position),
+                None => query_position,
+            });
+            if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+                reader.next();
+            } else {
+                break;
+            }
+        }
+        Ok(Self { queries, position })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        for (idx, query) in self.queries.iter().enumerate() {
+            query.to_string_from_buffer(buf, settings, depth);
+            if idx + 1 < self.queries.len() {
+                buf.push(',');
+                if !settings.minify {
+                    buf.push(' ');
+                }
+            }
+        }
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl MediaQueryList {
+    /// Whether rules under the query list apply in the environment. An empty list always matches
+    pub fn evaluate(&self, environment: &MediaEnvironment) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|query| query.evaluate(environment))
+    }
+}
+
+impl MediaQuery {
+    pub fn evaluate(&self, environment: &MediaEnvironment) -> bool {
+        match self {
+            MediaQuery::Type {
+                modifier,
+                media_type,
+                condition,
+            } => {
+                let matches = (media_type == "all"
+                    || media_type.eq_ignore_ascii_case(&environment.media_type))
+                    && condition
+                        .as_ref()
+                        .is_none_or(|condition| condition.evaluate(environment));
+                if let Some(MediaTypeModifier::Not) = modifier {
+                    !matches
+                } else {
+                    matches
+                }
+            }
+            MediaQuery::Condition(condition) => condition.evaluate(environment),
+        }
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        _depth: u8,
+    ) {
+        match self {
+            MediaQuery::Type {
+                modifier,
+                media_type,
+                condition,
+            } => {
+                match modifier {
+                    Some(MediaTypeModifier::Not) => buf.push_str("not "),
+                    Some(MediaTypeModifier::Only) => buf.push_str("only "),
+                    None => {}
+                }
+                buf.push_str(media_type);
+                if let Some(condition) = condition {
+                    buf.push_str(" and ");
+                    if let MediaCondition::Or(_) = condition {
+                        condition.operand_to_string_from_buffer(buf, settings);
+                    } else {
+                        condition.to_string_from_buffer(buf, settings);
+                    }
+                }
+            }
+            MediaQuery::Condition(condition) => condition.to_string_from_buffer(buf, settings),
+        }
+    }
+}
+
+impl MediaCondition {
+    pub fn evaluate(&self, environment: &MediaEnvironment) -> bool {
+        match self {
+            MediaCondition::Feature(feature) => feature.evaluate(environment),
+            MediaCondition::Not(condition) => !condition.evaluate(environment),
+            MediaCondition::And(conditions) => conditions
+                .iter()
+                .all(|condition| condition.evaluate(environment)),
+            MediaCondition::Or(conditions) => conditions
+                .iter()
+                .any(|condition| condition.evaluate(environment)),
+        }
+    }
+
+    fn to_string_from_buffer(&self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        match self {
+            MediaCondition::Feature(feature) => feature.to_string_from_buffer(buf, settings),
+            MediaCondition::Not(condition) => {
+                buf.push_str("not ");
+                condition.operand_to_string_from_buffer(buf, settings);
+            }
+            MediaCondition::And(conditions) | MediaCondition::Or(conditions) => {
+                let operator = if let MediaCondition::And(_) = self {
+                    " and "
+                } else {
+                    " or "
+                };
+                for (idx, condition) in conditions.iter().enumerate() {
+                    condition.operand_to_string_from_buffer(buf, settings);
+                    if idx + 1 < conditions.len() {
+                        buf.push_str(operator);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Conditions which are not features must be wrapped in brackets when used as operands
+    fn operand_to_string_from_buffer(&self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        if let MediaCondition::Feature(feature) = self {
+            feature.to_string_from_buffer(buf, settings);
+        } else {
+            buf.push('(');
+            self.to_string_from_buffer(buf, settings);
+            buf.push(')');
+        }
+    }
+}
+
+/// Value of a feature in a [`MediaEnvironment`]
+enum EnvironmentValue {
+    /// In CSS pixels
+    Length(f64),
+    /// In dppx
+    Resolution(f64),
+    Ratio(f64),
+    Integer(f64),
+    Discrete(String),
+}
+
+impl MediaFeature {
+    pub fn evaluate(&self, environment: &MediaEnvironment) -> bool {
+        match self {
+            MediaFeature::Boolean(name) => match environment_value(name, environment) {
+                Some(EnvironmentValue::Discrete(value)) => value != "none",
+                Some(EnvironmentValue::Length(value))
+                | Some(EnvironmentValue::Resolution(value))
+                | Some(EnvironmentValue::Ratio(value))
+                | Some(EnvironmentValue::Integer(value)) => value != 0.,
+                None => false,
+            },
+            MediaFeature::Plain(name, value) => {
+                if let Some(name) = name.strip_prefix("min-") {
+                    compare_feature(name, MediaComparison::GreaterThanEqual, value, environment)
+                } else if let Some(name) = name.strip_prefix("max-") {
+                    compare_feature(name, MediaComparison::LessThanEqual, value, environment)
+                } else {
+                    compare_feature(name, MediaComparison::Equal, value, environment)
+                }
+            }
+            MediaFeature::Range { start, name, end } => {
+                start.as_ref().is_none_or(|(value, comparison)| {
+                    compare_feature(name, comparison.flip(), value, environment)
+                }) && end.as_ref().is_none_or(|(comparison, value)| {
+                    compare_feature(name, *comparison, value, environment)
+                })
+            }
+        }
+    }
+
+    fn to_string_from_buffer(&self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        buf.push('(');
+        match self {
+            MediaFeature::Boolean(name) => buf.push_str(name),
+            MediaFeature::Plain(name, value) => {
+                buf.push_str(name);
+                buf.push(':');
+                if !settings.minify {
+                    buf.push(' ');
+                }
+                value.to_string_from_buffer(buf, settings);
+            }
+            MediaFeature::Range { start, name, end } => {
+                if let Some((value, comparison)) = start {
+                    value.to_string_from_buffer(buf, settings);
+                    comparison.to_string_from_buffer(buf, settings);
+                }
+                buf.push_str(name);
+                if let Some((comparison, value)) = end {
+                    comparison.to_string_from_buffer(buf, settings);
+                    value.to_string_from_buffer(buf, settings);
+                }
+            }
+        }
+        buf.push(')');
+    }
+}
+
+impl MediaFeatureValue {
+    fn to_string_from_buffer(&self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        match self {
+            MediaFeatureValue::Value(value) => value.to_string_from_buffer(buf, settings, 0),
+            MediaFeatureValue::Ratio(numerator, denominator) => {
+                buf.push_str(&numerator.0);
+                buf.push('/');
+                buf.push_str(&denominator.0);
+            }
+        }
+    }
+}
+
+impl MediaComparison {
+    /// For when the feature name is on the right hand side
+    fn flip(self) -> Self {
+        match self {
+            MediaComparison::LessThan => MediaComparison::GreaterThan,
+            MediaComparison::LessThanEqual => MediaComparison::GreaterThanEqual,
+            MediaComparison::GreaterThan => MediaComparison::LessThan,
+            MediaComparison::GreaterThanEqual => MediaComparison::LessThanEqual,
+            MediaComparison::Equal => MediaComparison::Equal,
+        }
+    }
+
+    fn is_less_than(self) -> bool {
+        matches!(
+            self,
+            MediaComparison::LessThan | MediaComparison::LessThanEqual
+        )
+    }
+
+    fn apply(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            MediaComparison::LessThan => lhs < rhs,
+            MediaComparison::LessThanEqual => lhs <= rhs,
+            MediaComparison::GreaterThan => lhs > rhs,
+            MediaComparison::GreaterThanEqual => lhs >= rhs,
+            MediaComparison::Equal => lhs == rhs,
+        }
+    }
+
+    fn to_string_from_buffer(self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        if !settings.minify {
+            buf.push(' ');
+        }
+        buf.push_str(match self {
+            MediaComparison::LessThan => "<",
+            MediaComparison::LessThanEqual => "<=",
+            MediaComparison::GreaterThan => ">",
+            MediaComparison::GreaterThanEqual => ">=",
+            MediaComparison::Equal => "=",
+        });
+        if !settings.minify {
+            buf.push(' ');
+        }
+    }
+}
+
+fn environment_value(name: &str, environment: &MediaEnvironment) -> Option<EnvironmentValue> {
+    match name {
+        "width" | "device-width" => Some(EnvironmentValue::Length(environment.width)),
+        "height" | "device-height" => Some(EnvironmentValue::Length(environment.height)),
+        "aspect-ratio" | "device-aspect-ratio" => Some(EnvironmentValue::Ratio(
+            environment.width / environment.height,
+        )),
+        "resolution" => Some(EnvironmentValue::Resolution(environment.resolution)),
+        "color" => Some(EnvironmentValue::Integer(environment.color as f64)),
+        "orientation" => Some(EnvironmentValue::Discrete(
+            if environment.height >= environment.width {
+                "portrait".to_owned()
+            } else {
+                "landscape".to_owned()
+            },
+        )),
+        name => environment
+            .discrete_features
+            .get(name)
+            .cloned()
+            .map(EnvironmentValue::Discrete),
+    }
+}
+
+/// Evaluates `feature comparison value`
+fn compare_feature(
+    name: &str,
+    comparison: MediaComparison,
+    value: &MediaFeatureValue,
+    environment: &MediaEnvironment,
+) -> bool {
+    let (actual, expected) = match (environment_value(name, environment), value) {
+        (Some(EnvironmentValue::Discrete(actual)), MediaFeatureValue::Value(value)) => {
+            return comparison == MediaComparison::Equal
+                && matches!(value, CSSValue::Keyword(keyword) if keyword.eq_ignore_ascii_case(&actual));
+        }
+        (Some(EnvironmentValue::Length(actual)), MediaFeatureValue::Value(value)) => {
+            (actual, length_in_pixels(value, environment))
+        }
+        (Some(EnvironmentValue::Resolution(actual)), MediaFeatureValue::Value(value)) => {
+            (actual, resolution_in_dppx(value))
+        }
+        (
+            Some(EnvironmentValue::Ratio(actual)),
+            MediaFeatureValue::Ratio(numerator, denominator),
+        ) => (
+            actual,
+            numerator
+                .as_f64()
+                .zip(denominator.as_f64())
+                .map(|(numerator, denominator)| numerator / denominator),
+        ),
+        (
+            Some(EnvironmentValue::Ratio(actual)) | Some(EnvironmentValue::Integer(actual)),
+            MediaFeatureValue::Value(CSSValue::Number(number)),
+        ) => (actual, number.as_f64()),
+        _ => return false,
+    };
+    expected.is_some_and(|expected| comparison.apply(actual, expected))
+}
+
+fn length_in_pixels(value: &CSSValue, environment: &MediaEnvironment) -> Option<f64> {
+    match value {
+        CSSValue::Number(number) => number.as_f64().filter(|value| *value == 0.),
+        CSSValue::NumberWithUnit(number, unit) => {
+            let pixels_per_unit = match unit.to_ascii_lowercase().as_str() {
+                "px" => 1.,
+                "em" | "rem" => environment.font_size,
+                "in" => 96.,
+                "cm" => 96. / 2.54,
+                "mm" => 96. / 25.4,
+                "q" => 96. / 101.6,
+                "pt" => 96. / 72.,
+                "pc" => 16.,
+                _ => return None,
+            };
+            number.as_f64().map(|value| value * pixels_per_unit)
+        }
+        _ => None,
+    }
+}
+
+fn resolution_in_dppx(value: &CSSValue) -> Option<f64> {
+    if let CSSValue::NumberWithUnit(number, unit) = value {
+        let dppx_per_unit = match unit.to_ascii_lowercase().as_str() {
+            "dppx" | "x" => 1.,
+            "dpi" => 1. / 96.,
+            "dpcm" => 2.54 / 96.,
+            _ => return None,
+        };
+        number.as_f64().map(|value| value * dppx_per_unit)
+    } else {
+        None
+    }
+}
+
+fn is_ident(reader: &mut impl TokenReader<CSSToken, Span>, expected: &str) -> bool {
+    matches!(reader.peek(), Some(Token(CSSToken::Ident(ident), _)) if ident.eq_ignore_ascii_case(expected))
+}
+
+fn query_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<(MediaQuery, Span), ParseError> {
+    // `not (color)` is a condition, `not print` is a media type
+    let is_condition = if is_ident(reader, "not") {
+        matches!(
+            reader.scan(|_, _| true),
+            Some(Token(CSSToken::OpenBracket, _))
+        )
+    } else {
+        matches!(reader.peek(), Some(Token(CSSToken::OpenBracket, _)))
+    };
+    if is_condition {
+        let (condition, position) = condition_from_reader(reader, true)?;
+        return Ok((MediaQuery::Condition(condition), position));
+    }
+
+    let (ident, start_span) = token_as_ident(reader.next().unwrap())?;
+    let modifier = match ident.to_ascii_lowercase().as_str() {
+        "not" => Some(MediaTypeModifier::Not),
+        "only" => Some(MediaTypeModifier::Only),
+        _ => None,
+    };
+    let (media_type, mut end_span) = if modifier.is_some() {
+        token_as_ident(reader.next().unwrap())?
+    } else {
+        (ident, start_span.clone())
+    };
+    let condition = if is_ident(reader, "and") {
+        reader.next();
+        let (condition, condition_span) = condition_from_reader(reader, false)?;
+        end_span = condition_span;
+        Some(condition)
+    } else {
+        None
+    };
+    Ok((
+        MediaQuery::Type {
+            modifier,
+            media_type: media_type.to_ascii_lowercase(),
+            condition,
+        },
+        start_span.union(&end_span),
+    ))
+}
+
+/// `allow_or` is false for conditions after a media type
+fn condition_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+    allow_or: bool,
+) -> Result<(MediaCondition, Span), ParseError> {
+    if is_ident(reader, "not") {
+        let start_span = reader.next().unwrap().1;
+        let (condition, end_span) = in_brackets_from_reader(reader)?;
+        return Ok((
+            MediaCondition::Not(Box::new(condition)),
+            start_span.union(&end_span),
+        ));
+    }
+
+    let (first, start_span) = in_brackets_from_reader(reader)?;
+    let mut end_span = start_span.clone();
+    let mut conditions = vec![first];
+    let mut is_and: Option<bool> = None;
+    loop {
+        let operator_is_and = if is_ident(reader, "and") {
+            true
+        } else if is_ident(reader, "or") {
+            false
+        } else {
+            break;
+        };
+        let operator_span = reader.next().unwrap().1;
+        if !operator_is_and && !allow_or {
+            return Err(ParseError {
+                reason: "'or' cannot follow a media type, wrap the condition in brackets"
+                    .to_owned(),
+                position: operator_span,
+            });
+        }
+        if matches!(is_and, Some(is_and) if is_and != operator_is_and) {
+            return Err(ParseError {
+                reason: "Cannot mix 'and' and 'or' without brackets".to_owned(),
+                position: operator_span,
+            });
+        }
+        is_and = Some(operator_is_and);
+        let (condition, condition_span) = in_brackets_from_reader(reader)?;
+        conditions.push(condition);
+        end_span = condition_span;
+    }
+
+    let condition = match is_and {
+        None => conditions.pop().unwrap(),
+        Some(true) => MediaCondition::And(conditions),
+        Some(false) => MediaCondition::Or(conditions),
+    };
+    Ok((condition, start_span.union(&end_span)))
+}
+
+/// Parses a feature or a nested condition, e.g. `(color)` or `((color) or (hover))`
+fn in_brackets_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<(MediaCondition, Span), ParseError> {
+    let start_span = reader.expect_next(CSSToken::OpenBracket)?;
+    let is_nested_condition =
+        matches!(reader.peek(), Some(Token(CSSToken::OpenBracket, _))) || is_ident(reader, "not");
+    let condition = if is_nested_condition {
+        condition_from_reader(reader, true)?.0
+    } else {
+        MediaCondition::Feature(feature_from_reader(reader)?)
+    };
+    let end_span = reader.expect_next(CSSToken::CloseBracket)?;
+    Ok((condition, start_span.union(&end_span)))
+}
+
+/// Parses the inside of a feature, not including brackets
+fn feature_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<MediaFeature, ParseError> {
+    if let Some(Token(CSSToken::Ident(_), _)) = reader.peek() {
+        let name = token_as_ident(reader.next().unwrap())?
+            .0
+            .to_ascii_lowercase();
+        match reader.peek().unwrap() {
+            Token(CSSToken::CloseBracket, _) => Ok(MediaFeature::Boolean(name)),
+            Token(CSSToken::Colon, _) => {
+                reader.next();
+                Ok(MediaFeature::Plain(
+                    name,
+                    feature_value_from_reader(reader)?,
+                ))
+            }
+            _ => {
+                let comparison = comparison_from_reader(reader)?;
+                let value = feature_value_from_reader(reader)?;
+                Ok(MediaFeature::Range {
+                    start: None,
+                    name,
+                    end: Some((comparison, value)),
+                })
+            }
+        }
+    } else {
+        let value = feature_value_from_reader(reader)?;
+        let comparison = comparison_from_reader(reader)?;
+        let name = token_as_ident(reader.next().unwrap())?
+            .0
+            .to_ascii_lowercase();
+        let end = if let Some(Token(CSSToken::CloseBracket, _)) = reader.peek() {
+            None
+        } else {
+            let comparison_position = reader.peek().unwrap().1.clone();
+            let end_comparison = comparison_from_reader(reader)?;
+            if comparison == MediaComparison::Equal
+                || comparison.is_less_than() != end_comparison.is_less_than()
+            {
+                return Err(ParseError {
+                    reason: "Both comparisons in a range must be '<' or '>'".to_owned(),
+                    position: comparison_position,
+                });
+            }
+            Some((end_comparison, feature_value_from_reader(reader)?))
+        };
+        Ok(MediaFeature::Range {
+            start: Some((value, comparison)),
+            name,
+            end,
+        })
+    }
+}
+
+fn comparison_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<MediaComparison, ParseError> {
+    match reader.next().unwrap() {
+        Token(CSSToken::OpenAngle, _) => Ok(MediaComparison::LessThan),
+        Token(CSSToken::LessThanEqual, _) => Ok(MediaComparison::LessThanEqual),
+        Token(CSSToken::CloseAngle, _) => Ok(MediaComparison::GreaterThan),
+        Token(CSSToken::GreaterThanEqual, _) => Ok(MediaComparison::GreaterThanEqual),
+        Token(CSSToken::Equal, _) => Ok(MediaComparison::Equal),
+        Token(token, position) => Err(ParseError {
+            reason: format!("Expected ':', ')' or comparison found '{:?}'", token),
+            position,
+        }),
+    }
+}
+
+fn feature_value_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<MediaFeatureValue, ParseError> {
+    let value = CSSValue::single_value_from_reader(reader)?;
+    if let Some(Token(CSSToken::Slash, _)) = reader.peek() {
+        let slash_position = reader.next().unwrap().1;
+        let denominator = CSSValue::single_value_from_reader(reader)?;
+        if let (CSSValue::Number(numerator), CSSValue::Number(denominator)) = (value, denominator) {
+            Ok(MediaFeatureValue::Ratio(numerator, denominator))
+        } else {
+            Err(ParseError {
+                reason: "Expected ratio of two numbers".to_owned(),
+                position: slash_position,
+            })
+        }
+    } else {
+        Ok(MediaFeatureValue::Value(value))
+    }
+}
+
+#[cfg(test)]
+mod media_tests {
+    use super::*;
+    use source_map::SourceId;
+
+    const NULL_SOURCE_ID: SourceId = SourceId::null();
+
+    fn parse(source: &str) -> MediaQueryList {
+        MediaQueryList::from_string(source.to_owned(), NULL_SOURCE_ID, None).unwrap()
+    }
+
+    #[test]
+    fn media_type_and_feature() {
+        let list = parse("only screen and (min-width: 600px)");
+        assert_eq!(
+            list.queries,
+            vec![MediaQuery::Type {
+                modifier: Some(MediaTypeModifier::Only),
+                media_type: "screen".to_owned(),
+                condition: Some(MediaCondition::Feature(MediaFeature::Plain(
+                    "min-width".to_owned(),
+                    MediaFeatureValue::Value(CSSValue::NumberWithUnit(
+                        Number("600".to_owned()),
+                        "px".to_owned()
+                    ))
+                )))
+            }]
+        );
+    }
+
+    #[test]
+    fn range_syntax() {
+        let list = parse("(400px <= width < 800px)");
+        assert!(matches!(
+            &list.queries[0],
+            MediaQuery::Condition(MediaCondition::Feature(MediaFeature::Range {
+                start: Some((_, MediaComparison::LessThanEqual)),
+                end: Some((MediaComparison::LessThan, _)),
+                ..
+            }))
+        ));
+        assert!(MediaQueryList::from_string(
+            "(400px < width > 800px)".to_owned(),
+            NULL_SOURCE_ID,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn evaluate() {
+        let mut environment = MediaEnvironment {
+            width: 700.,
+            height: 500.,
+            ..Default::default()
+        };
+        assert!(parse("screen and (min-width: 600px)").evaluate(&environment));
+        assert!(!parse("print and (min-width: 600px)").evaluate(&environment));
+        assert!(parse("not print").evaluate(&environment));
+        assert!(parse("(400px <= width < 800px)").evaluate(&environment));
+        assert!(parse("(max-width: 50em)").evaluate(&environment));
+        assert!(!parse("(width > 800px), (orientation: portrait)").evaluate(&environment));
+        assert!(parse("not ((width > 800px) or (color: 2))").evaluate(&environment));
+        assert!(!parse("(min-aspect-ratio: 16/9)").evaluate(&MediaEnvironment::default()));
+
+        environment.width = 900.;
+        assert!(!parse("(400px <= width < 800px)").evaluate(&environment));
+        environment
+            .discrete_features
+            .insert("prefers-color-scheme".to_owned(), "dark".to_owned());
+        assert!(parse("(prefers-color-scheme: dark)").evaluate(&environment));
+        assert!(!parse("(hover)").evaluate(&environment));
+    }
+
+    #[test]
+    fn to_string() {
+        let list = parse("SCREEN   and (min-width:600px),print and ((color) or (hover))");
+        assert_eq!(
+            list.to_string(&ToStringSettings::default()),
+            "screen and (min-width: 600px), print and ((color) or (hover))"
+        );
+        assert_eq!(
+            parse("(400px <= width < 800px), not (color)").to_string(&ToStringSettings::minified()),
+            "(400px<=width<800px),not (color)"
+        );
+    }
+}
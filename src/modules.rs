@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Reads the stylesheets loaded by `@use`, `@forward` and `@import`. Implement to load from
+/// somewhere other than the file system
+pub trait FileLoader {
+    /// Whether there is a file at the path
+    fn is_file(&self, path: &Path) -> bool;
+
+    fn read(&self, path: &Path) -> io::Result<String>;
+}
+
+/// Loads files from the file system
+pub struct FileSystemLoader;
+
+impl FileLoader for FileSystemLoader {
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Loads files added to it from memory
+#[derive(Default)]
+pub struct MemoryFileLoader {
+    files: HashMap<PathBuf, String>,
+}
+
+impl MemoryFileLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, path: impl AsRef<Path>, content: impl Into<String>) {
+        self.files.insert(normalize(path.as_ref()), content.into());
+    }
+}
+
+impl FileLoader for MemoryFileLoader {
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found"))
+    }
+}
+
+/// Finds the file a `@use`, `@forward` or `@import` url refers to, looking in `base_directory`
+/// (the directory of the importing file) then each of the load paths. Urls without an extension
+/// can refer to `name.scss`, the partial `_name.scss`, `name.sass`, `name.less`, `name.css` or a
+/// `name/_index.scss`
+pub(crate) fn resolve(
+    url: &str,
+    base_directory: Option<&Path>,
+    load_paths: &[PathBuf],
+    loader: &dyn FileLoader,
+) -> Option<PathBuf> {
+    let candidates = candidates(url);
+    base_directory
+        .into_iter()
+        .chain(load_paths.iter().map(PathBuf::as_path))
+        .find_map(|directory| {
+            candidates
+                .iter()
+                .map(|candidate| normalize(&directory.join(candidate)))
+                .find(|path| loader.is_file(path))
+        })
+}
+
+/// Relative paths the url could refer to, in order of preference
+fn candidates(url: &str) -> Vec<PathBuf> {
+    let path = Path::new(url);
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return Vec::new(),
+    };
+    let partial = |file_name: &str| directory.join(format!("_{}", file_name));
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("scss" | "sass" | "less" | "css") => vec![path.to_path_buf(), partial(name)],
+        _ => {
+            let mut candidates = Vec::new();
+            for extension in ["scss", "sass", "less", "css"] {
+                let file_name = format!("{}.{}", name, extension);
+                candidates.push(directory.join(&file_name));
+                candidates.push(partial(&file_name));
+            }
+            for index in ["_index.scss", "index.scss", "_index.sass", "index.sass"] {
+                candidates.push(path.join(index));
+            }
+            candidates
+        }
+    }
+}
+
+/// Removes `.` and `..` components, so that a file has one path for [`MemoryFileLoader`] and
+/// for checking whether it is already loaded
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod modules_tests {
+    use super::*;
+
+    #[test]
+    fn resolving() {
+        let mut loader = MemoryFileLoader::new();
+        loader.add_file("styles/_config.scss", "");
+        loader.add_file("styles/base.css", "");
+        loader.add_file("styles/_legacy.sass", "");
+        loader.add_file("lib/theme/_index.scss", "");
+        let base = Some(Path::new("styles"));
+        let load_paths = [PathBuf::from("lib")];
+        let resolve = |url| resolve(url, base, &load_paths, &loader);
+        assert_eq!(
+            resolve("config"),
+            Some(PathBuf::from("styles/_config.scss"))
+        );
+        assert_eq!(
+            resolve("./config.scss"),
+            Some(PathBuf::from("styles/_config.scss"))
+        );
+        assert_eq!(resolve("base"), Some(PathBuf::from("styles/base.css")));
+        assert_eq!(
+            resolve("legacy"),
+            Some(PathBuf::from("styles/_legacy.sass"))
+        );
+        assert_eq!(
+            resolve("../lib/theme"),
+            Some(PathBuf::from("lib/theme/_index.scss"))
+        );
+        assert_eq!(
+            resolve("theme"),
+            Some(PathBuf::from("lib/theme/_index.scss"))
+        );
+        assert_eq!(resolve("missing"), None);
+    }
+}
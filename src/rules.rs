@@ -1,71 +1,35 @@
-use crate::token_as_ident;
+use crate::{
+    recover,
+    selectors::{relative_selector_list_from_reader, selector_list_from_reader},
+};
 
-use super::{ASTNode, CSSToken, CSSValue, ParseError, Selector, ToStringSettings};
+use super::{
+    ASTNode, BlockItem, CSSToken, Declaration, Interpolation, ParseError, ScssStatement, Selector,
+    ToStringSettings,
+};
 use source_map::{Span, ToString};
 use tokenizer_lib::{Token, TokenReader};
 
 /// A css rule with a selector and collection of declarations
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
+    /// Selectors containing SCSS interpolation e.g. `.icon-#{$name}`. `selectors` is empty until
+    /// [`compile`](crate::compile) evaluates and parses this into them
+    pub interpolated_selectors: Option<Interpolation>,
+    /// Rules nested in this rule. Printed in the block after the declarations, one level deeper.
+    /// [`raise_nested_rules`](crate::raise_nested_rules) moves them out for CSS without nesting
     pub nested_rules: Option<Vec<Rule>>,
-    pub declarations: Vec<(String, CSSValue)>,
+    pub declarations: Vec<Declaration>,
+    /// The body of rules containing SCSS statements, in source order. `declarations` and
+    /// `nested_rules` are empty until [`compile`](crate::compile) evaluates this into them
+    pub scss_body: Option<Vec<BlockItem>>,
     pub position: Option<Span>,
 }
 
 impl ASTNode for Rule {
     fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
-        let mut selectors = vec![Selector::from_reader(reader)?];
-        while let Token(CSSToken::Comma, _) = reader.peek().unwrap() {
-            reader.next();
-            selectors.push(Selector::from_reader(reader)?);
-        }
-        let first_span = selectors.first().unwrap().get_position().unwrap();
-        reader.expect_next(CSSToken::OpenCurly)?;
-
-        // Parse declarations and nested rules
-        let mut declarations: Vec<(String, CSSValue)> = Vec::new();
-        let mut nested_rules: Option<Vec<Rule>> = None;
-        while let Some(Token(token_type, _)) = reader.peek() {
-            if token_type == &CSSToken::CloseCurly {
-                break;
-            }
-            let mut is_rule: Option<bool> = None;
-            reader.scan(|token, _| {
-                match token {
-                    CSSToken::Colon | CSSToken::CloseCurly => is_rule = Some(false),
-                    CSSToken::OpenCurly => is_rule = Some(true),
-                    _ => {}
-                }
-                is_rule.is_some()
-            });
-
-            if is_rule.unwrap_or_default() {
-                nested_rules
-                    .get_or_insert_with(|| Vec::new())
-                    .push(Rule::from_reader(reader)?);
-            } else {
-                let (property_name, _) = token_as_ident(reader.next().unwrap())?;
-                reader.expect_next(CSSToken::Colon)?;
-                let value = CSSValue::from_reader(reader)?;
-                declarations.push((property_name, value));
-                if let Token(CSSToken::CloseCurly, last_span) = reader.next().unwrap() {
-                    return Ok(Self {
-                        position: Some(first_span.union(&last_span)),
-                        selectors,
-                        declarations,
-                        nested_rules,
-                    });
-                }
-            }
-        }
-        let last_span = reader.expect_next(CSSToken::CloseCurly)?;
-        Ok(Self {
-            position: Some(first_span.union(&last_span)),
-            selectors,
-            declarations,
-            nested_rules,
-        })
+        Self::from_reader_with_recovery(reader, None, false)
     }
 
     fn to_string_from_buffer(
@@ -74,6 +38,9 @@ impl ASTNode for Rule {
         settings: &ToStringSettings,
         depth: u8,
     ) {
+        if let Some(interpolation) = &self.interpolated_selectors {
+            interpolation.to_string_from_buffer(buf, settings, depth);
+        }
         for (idx, selector) in self.selectors.iter().enumerate() {
             selector.to_string_from_buffer(buf, settings, depth);
             if idx + 1 < self.selectors.len() {
@@ -88,23 +55,31 @@ impl ASTNode for Rule {
             buf.push(' ');
         }
         buf.push('{');
-        for (idx, (name, value)) in self.declarations.iter().enumerate() {
+        if let Some(scss_body) = &self.scss_body {
+            BlockItem::items_to_string_from_buffer(scss_body, buf, settings, depth);
+            buf.push('}');
+            return;
+        }
+        for declaration in self.declarations.iter() {
             if !settings.minify {
                 buf.push_new_line();
                 buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
             }
-            buf.push_str(name);
-            buf.push(':');
-
-            if !settings.minify {
-                buf.push(' ');
-            }
-            value.to_string_from_buffer(buf, settings, depth);
+            declaration.to_string_from_buffer(buf, settings, depth);
             buf.push(';');
-            if !settings.minify && idx == self.declarations.len() - 1 {
+        }
+        let nested_rules = self.nested_rules.as_deref().unwrap_or_default();
+        for nested_rule in nested_rules {
+            if !settings.minify {
                 buf.push_new_line();
-                buf.push_str(&settings.indent_with.repeat(depth as usize));
+                buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
             }
+            nested_rule.to_string_from_buffer(buf, settings, depth + 1);
+        }
+        let is_empty = self.declarations.is_empty() && nested_rules.is_empty();
+        if !settings.minify && !is_empty {
+            buf.push_new_line();
+            buf.push_str(&settings.indent_with.repeat(depth as usize));
         }
         buf.push('}');
     }
@@ -113,3 +88,135 @@ impl ASTNode for Rule {
         self.position.as_ref()
     }
 }
+
+impl Rule {
+    /// If `errors` is `Some`, invalid declarations and nested rules are skipped and their errors
+    /// collected rather than returned. Selectors of nested rules may start with a combinator
+    /// e.g. `> b`
+    pub(crate) fn from_reader_with_recovery(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        errors: Option<&mut Vec<ParseError>>,
+        is_nested: bool,
+    ) -> Result<Self, ParseError> {
+        let (selectors, interpolated_selectors, first_span) =
+            if Interpolation::is_ahead(reader, |token| *token == CSSToken::OpenCurly) {
+                let interpolation = Interpolation::from_reader_until(reader, |token| {
+                    *token == CSSToken::OpenCurly
+                })?;
+                let first_span = interpolation.position.clone();
+                (Vec::new(), Some(interpolation), first_span)
+            } else if is_nested {
+                let selectors = relative_selector_list_from_reader(reader)?;
+                let first_span = selectors.first().unwrap().get_position().unwrap().clone();
+                (selectors, None, first_span)
+            } else {
+                let selectors = selector_list_from_reader(reader)?;
+                let first_span = selectors.first().unwrap().get_position().unwrap().clone();
+                (selectors, None, first_span)
+            };
+        let (items, last_span) = block_items_from_reader(reader, errors)?;
+
+        let mut declarations: Vec<Declaration> = Vec::new();
+        let mut nested_rules: Option<Vec<Rule>> = None;
+        let mut scss_body = None;
+        if items
+            .iter()
+            .any(|item| matches!(item, BlockItem::Statement(_)))
+        {
+            scss_body = Some(items);
+        } else {
+            for item in items {
+                match item {
+                    BlockItem::Declaration(declaration) => declarations.push(declaration),
+                    BlockItem::Rule(rule) => nested_rules.get_or_insert_with(Vec::new).push(rule),
+                    BlockItem::Statement(_) => unreachable!(),
+                }
+            }
+        }
+        Ok(Self {
+            position: Some(first_span.union(&last_span)),
+            selectors,
+            interpolated_selectors,
+            declarations,
+            nested_rules,
+            scss_body,
+        })
+    }
+}
+
+/// Parses a `{ ... }` block of declarations, nested rules and SCSS statements. Returns the items
+/// and the position of the closing `}`
+pub(crate) fn block_items_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+    mut errors: Option<&mut Vec<ParseError>>,
+) -> Result<(Vec<BlockItem>, Span), ParseError> {
+    reader.expect_next(CSSToken::OpenCurly)?;
+
+    let mut items: Vec<BlockItem> = Vec::new();
+    loop {
+        match reader.peek().unwrap() {
+            Token(CSSToken::CloseCurly, _) | Token(CSSToken::EOS, _) => break,
+            // Comments are not kept in blocks of declarations
+            Token(CSSToken::SemiColon, _) | Token(CSSToken::Comment(_), _) => {
+                reader.next();
+                continue;
+            }
+            Token(CSSToken::Variable(_), _) => {}
+            Token(CSSToken::AtKeyword(name), _) if ScssStatement::is_scss_at_rule(name) => {}
+            _ => {
+                if is_rule_ahead(reader) {
+                    let rule = match errors.as_deref_mut() {
+                        Some(errors) => recover(reader, errors, |reader, errors| {
+                            Rule::from_reader_with_recovery(reader, Some(errors), true)
+                        }),
+                        None => Some(Rule::from_reader_with_recovery(reader, None, true)?),
+                    };
+                    items.extend(rule.map(BlockItem::Rule));
+                } else {
+                    let declaration = match errors.as_deref_mut() {
+                        Some(errors) => {
+                            recover(reader, errors, |reader, _| Declaration::from_reader(reader))
+                        }
+                        None => Some(Declaration::from_reader(reader)?),
+                    };
+                    items.extend(declaration.map(BlockItem::Declaration));
+                }
+                continue;
+            }
+        }
+        let statement = match errors.as_deref_mut() {
+            Some(errors) => recover(reader, errors, |reader, _| {
+                ScssStatement::from_reader(reader)
+            }),
+            None => Some(ScssStatement::from_reader(reader)?),
+        };
+        items.extend(statement.map(BlockItem::Statement));
+    }
+    let last_span = reader.expect_next(CSSToken::CloseCurly)?;
+    Ok((items, last_span))
+}
+
+/// Whether the next item in a block is a rule rather than a declaration. Colons appear in both
+/// declarations and selectors (`a:hover`), so decides on whether a `{` or the end of a declaration
+/// is found first
+pub(crate) fn is_rule_ahead(reader: &mut impl TokenReader<CSSToken, Span>) -> bool {
+    let mut is_rule = false;
+    let mut in_interpolation = false;
+    reader.scan(|token, _| match token {
+        CSSToken::InterpolationStart => {
+            in_interpolation = true;
+            false
+        }
+        CSSToken::CloseCurly if in_interpolation => {
+            in_interpolation = false;
+            false
+        }
+        CSSToken::OpenCurly => {
+            is_rule = true;
+            true
+        }
+        CSSToken::SemiColon | CSSToken::CloseCurly => true,
+        _ => false,
+    });
+    is_rule
+}
@@ -0,0 +1,317 @@
+use super::{
+    lexer::{lex_braced_source, Syntax},
+    CSSToken, ParseError,
+};
+use source_map::{SourceId, Span};
+use tokenizer_lib::{Token, TokenSender};
+
+/// Lexes the indented syntax (`.sass`) into the tokens of the equivalent SCSS, so it is parsed
+/// and compiled as SCSS. Each line is lexed as SCSS and the `{`, `;` and `}` which indentation
+/// implies are added with empty positions at the end of the line before, so all positions are
+/// in the `.sass` source. `=name` and `+name` at the start of a line are `@mixin name` and
+/// `@include name`
+pub(crate) fn lex_indented_source(
+    source: &str,
+    sender: &mut impl TokenSender<CSSToken, Span>,
+    source_id: SourceId,
+    start_offset: Option<usize>,
+) -> Result<(), ParseError> {
+    let start_offset = start_offset.unwrap_or_default();
+    let span = |start: usize, end: usize| Span {
+        start: start_offset + start,
+        end: start_offset + end,
+        source_id,
+    };
+
+    // Indentation of the open blocks, outermost first
+    let mut indents = vec![0];
+    // End of the last statement line, where the tokens implied by the next line are placed
+    let mut last_end: Option<usize> = None;
+    // Whether the last statement line ended with a `,` and so continues on the next line e.g. a
+    // selector list
+    let mut continues = false;
+    // Whether the last statement is a declaration, which cannot have more indented lines after it
+    let mut is_declaration = false;
+    // Indentation of a `//` comment. More indented lines after it are part of the comment
+    let mut silent_comment: Option<usize> = None;
+    // Indentation, start and end of a `/*` comment without a `*/` on its line. More indented
+    // lines after it are part of the comment
+    let mut loud_comment: Option<(usize, usize, usize)> = None;
+    // `/*` comments, added before the next statement so they are not part of the one before
+    let mut comments: Vec<Token<CSSToken, Span>> = Vec::new();
+    // Tokens of the last statement line. Sent once the next line is found to be valid
+    let mut line_tokens = LineTokens(Vec::new());
+
+    let comment_token = |start: usize, end: usize| {
+        let content = &source[(start + 2)..end];
+        let content = content.strip_suffix("*/").unwrap_or(content);
+        Token(CSSToken::Comment(content.to_owned()), span(start, end))
+    };
+
+    let mut line_start = 0;
+    for line in source.split('\n') {
+        let indent = line.len() - line.trim_start().len();
+        let content = line.trim();
+        let content_start = line_start + indent;
+        let content_end = content_start + content.len();
+        let indent_span = span(line_start, content_start);
+        line_start += line.len() + 1;
+
+        if content.is_empty() {
+            continue;
+        }
+        if let Some(comment_indent) = silent_comment {
+            if indent > comment_indent {
+                continue;
+            }
+            silent_comment = None;
+        }
+        if let Some((comment_indent, start, _)) = loud_comment {
+            if indent > comment_indent {
+                if content.contains("*/") {
+                    comments.push(comment_token(start, content_end));
+                    loud_comment = None;
+                } else {
+                    loud_comment = Some((comment_indent, start, content_end));
+                }
+                continue;
+            }
+            comments.push(comment_token(start, loud_comment.take().unwrap().2));
+        }
+        if content.starts_with("//") {
+            silent_comment = Some(indent);
+            continue;
+        }
+        if content.starts_with("/*") {
+            if content.ends_with("*/") && content.len() >= 4 {
+                comments.push(comment_token(content_start, content_end));
+            } else {
+                loud_comment = Some((indent, content_start, content_end));
+            }
+            continue;
+        }
+
+        // Errors are found before the tokens of the line before are sent, so the parser does
+        // not error on a statement cut short
+        if !continues {
+            let last_indent = *indents.last().unwrap();
+            let reason = match last_end {
+                Some(_) if indent > last_indent && is_declaration => {
+                    Some("Declarations cannot have nested blocks")
+                }
+                Some(_) if indent < last_indent && !indents.contains(&indent) => {
+                    Some("Inconsistent indentation")
+                }
+                None if indent > 0 => Some("Unexpected indentation"),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                return Err(ParseError {
+                    reason: reason.to_owned(),
+                    position: indent_span,
+                });
+            }
+        }
+        for token in line_tokens.0.drain(..) {
+            sender.push(token);
+        }
+        if let (false, Some(end)) = (continues, last_end) {
+            let position = span(end, end);
+            if indent > *indents.last().unwrap() {
+                sender.push(Token(CSSToken::OpenCurly, position));
+                indents.push(indent);
+            } else {
+                sender.push(Token(CSSToken::SemiColon, position.clone()));
+                while indent < *indents.last().unwrap() {
+                    indents.pop();
+                    sender.push(Token(CSSToken::CloseCurly, position.clone()));
+                }
+            }
+        }
+        for comment in comments.drain(..) {
+            sender.push(comment);
+        }
+        if !continues {
+            is_declaration = is_declaration_line(content);
+        }
+
+        let shorthand = match content.chars().next() {
+            Some('=') => Some("mixin"),
+            Some('+')
+                if content[1..].starts_with(|chr: char| chr.is_alphabetic() || chr == '_') =>
+            {
+                Some("include")
+            }
+            _ => None,
+        };
+        let mut rest_start = content_start;
+        if let Some(name) = shorthand {
+            rest_start += 1;
+            line_tokens.push(Token(
+                CSSToken::AtKeyword(name.to_owned()),
+                span(content_start, rest_start),
+            ));
+        }
+        lex_braced_source(
+            &source[rest_start..content_end],
+            &mut line_tokens,
+            source_id,
+            Some(start_offset + rest_start),
+            Syntax::Scss,
+        )?;
+        continues = content.ends_with(',');
+        last_end = Some(content_end);
+    }
+
+    if let Some((_, start, end)) = loud_comment {
+        comments.push(comment_token(start, end));
+    }
+    for token in line_tokens.0 {
+        sender.push(token);
+    }
+    if let Some(end) = last_end {
+        sender.push(Token(CSSToken::SemiColon, span(end, end)));
+        for _ in indents.iter().skip(1) {
+            sender.push(Token(CSSToken::CloseCurly, span(end, end)));
+        }
+    }
+    for comment in comments {
+        sender.push(comment);
+    }
+    sender.push(Token(CSSToken::EOS, span(source.len(), source.len())));
+    Ok(())
+}
+
+/// Whether the line starts a declaration e.g. `color: red` or `$primary: red`. In selectors with
+/// pseudo classes e.g. `a:hover` whitespace does not follow the `:`
+fn is_declaration_line(content: &str) -> bool {
+    let name = content.strip_prefix('$').unwrap_or(content);
+    let name_end = name
+        .find(|chr: char| !(chr.is_alphanumeric() || chr == '-' || chr == '_'))
+        .unwrap_or(name.len());
+    name_end > 0
+        && name[name_end..]
+            .strip_prefix(':')
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Collects the tokens of a line, without the end of source token
+struct LineTokens(Vec<Token<CSSToken, Span>>);
+
+impl TokenSender<CSSToken, Span> for LineTokens {
+    fn push(&mut self, token: Token<CSSToken, Span>) -> bool {
+        if token.0 != CSSToken::EOS {
+            self.0.push(token);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod sass_tests {
+    use crate::{compile, raise_nested_rules, Entry, StyleSheet, Syntax, ToStringSettings};
+    use source_map::SourceId;
+
+    fn compile_sass(source: &str) -> String {
+        let mut style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Sass)
+                .unwrap();
+        compile(&mut style_sheet).unwrap();
+        raise_nested_rules(&mut style_sheet).unwrap();
+        style_sheet.to_string(Some(ToStringSettings::minified()))
+    }
+
+    #[test]
+    fn indentation() {
+        let source = "// Variables, with the comment
+   continued on more indented lines
+$primary: red
+
+=button($color)
+  color: $color
+  &:hover
+    color: blue
+
+nav,
+main
+  ul
+    margin: 0 // Reset
+    +button($primary)
+  @if $primary == red
+    padding: 1px
+  @else
+    padding: 2px
+";
+        assert_eq!(
+            compile_sass(source),
+            "nav,main{padding:1px;}nav ul,main ul{margin:0;color:red;}\
+            nav ul:hover,main ul:hover{color:blue;}"
+        );
+        // Comments in rules are dropped
+        assert_eq!(
+            compile_sass("a\n  /* b */\n  b: c /* d */\n  /* e\n    f */\n  e: f"),
+            "a{b:c;e:f;}"
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let source = "a\n  color: red\n/* Comment\n   over lines */\nb\n    margin: 0";
+        let style_sheet =
+            StyleSheet::from_string_with_syntax(source.to_owned(), SourceId::null(), Syntax::Sass)
+                .unwrap();
+        let rule = match &style_sheet.entries[0] {
+            Entry::Rule(rule) => rule,
+            entry => panic!("Expected rule, found {:?}", entry),
+        };
+        let position = rule.declarations[0].position.as_ref().unwrap();
+        assert_eq!(&source[position.start..position.end], "color: red");
+        assert!(
+            matches!(&style_sheet.entries[1], Entry::Comment(comment) if comment == " Comment\n   over lines ")
+        );
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "a{color:red;}b{margin:0;}"
+        );
+
+        let error = StyleSheet::from_string_with_syntax(
+            "a\n    color: red\n  margin: 0".to_owned(),
+            SourceId::null(),
+            Syntax::Sass,
+        )
+        .unwrap_err();
+        assert_eq!(error.reason, "Inconsistent indentation");
+        assert_eq!((error.position.start, error.position.end), (17, 19));
+    }
+
+    #[test]
+    fn indentation_errors() {
+        let error = StyleSheet::from_string_with_syntax(
+            "a\n  color: red\n    .b\n      margin: 0".to_owned(),
+            SourceId::null(),
+            Syntax::Sass,
+        )
+        .unwrap_err();
+        assert_eq!(error.reason, "Declarations cannot have nested blocks");
+        assert_eq!((error.position.start, error.position.end), (15, 19));
+        // Selectors with pseudo classes are not declarations
+        assert_eq!(
+            compile_sass("a:hover\n  color: blue\n  b::before\n    color: red"),
+            "a:hover{color:blue;}a:hover b::before{color:red;}"
+        );
+
+        for source in [
+            ".a\n  .b\n    color: red\n   margin: 0\n.c\n  d: e",
+            "a\n    color: red\n  margin: 0\nb\n  c: d",
+            ".a\n    .b\n  c: d",
+        ] {
+            let (_, errors) = StyleSheet::from_string_recovering_with_syntax(
+                source.to_owned(),
+                SourceId::null(),
+                Syntax::Sass,
+            );
+            let reasons: Vec<_> = errors.iter().map(|error| error.reason.as_str()).collect();
+            assert_eq!(reasons, ["Inconsistent indentation"], "{}", source);
+        }
+    }
+}
@@ -0,0 +1,1409 @@
+use super::{
+    rules::block_items_from_reader,
+    selectors::{selector_list_from_reader, selector_list_to_string_from_buffer},
+    token_as_ident, ASTNode, CSSToken, CSSValue, Declaration, ParseError, Rule, Selector,
+    ToStringSettings,
+};
+use derive_more::From;
+use source_map::{Span, ToString};
+use tokenizer_lib::{Token, TokenReader};
+
+/// A SCSS statement. These are removed by [`compile`](crate::compile)
+#[derive(Debug, Clone, From)]
+pub enum ScssStatement {
+    Variable(VariableDeclaration),
+    Mixin(MixinDefinition),
+    Include(Include),
+    Function(FunctionDefinition),
+    Return(ReturnStatement),
+    Use(UseStatement),
+    Forward(ForwardStatement),
+    Import(ImportStatement),
+    Extend(Extend),
+    If(IfStatement),
+    Each(EachStatement),
+    For(ForStatement),
+    While(WhileStatement),
+    /// `@content;` in a mixin body. Replaced by the block passed to the `@include`
+    #[from(ignore)]
+    Content(Span),
+}
+
+/// A SCSS variable declaration e.g. `$primary: #0000ff !default;`
+#[derive(Debug, Clone)]
+pub struct VariableDeclaration {
+    /// Name without the `$`
+    pub name: String,
+    pub value: CSSValue,
+    /// `!default`. Only assigns if the variable is undefined or `null`
+    pub default: bool,
+    /// `!global`. Assigns in the global scope
+    pub global: bool,
+    pub position: Option<Span>,
+}
+
+/// `@mixin name($a, $b: default) { ... }`
+#[derive(Debug, Clone)]
+pub struct MixinDefinition {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub body: Vec<BlockItem>,
+    pub position: Option<Span>,
+}
+
+/// `@function name($a, $b: default) { ... @return $a; }`. Calls in values are evaluated by
+/// running the body up to a `@return`
+#[derive(Debug, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub body: Vec<BlockItem>,
+    pub position: Option<Span>,
+}
+
+/// `@return value;` in a function body
+#[derive(Debug, Clone)]
+pub struct ReturnStatement {
+    pub value: CSSValue,
+    pub position: Option<Span>,
+}
+
+/// `@use "config" as cfg;`. Loads the module, making its members available under the namespace
+/// e.g. `cfg.$primary`
+#[derive(Debug, Clone)]
+pub struct UseStatement {
+    pub url: String,
+    /// Name after `as`. `*` makes the members available without a namespace
+    pub alias: Option<String>,
+    pub position: Option<Span>,
+}
+
+/// `@forward "theme" show $primary, button;`. Makes members of the module available to
+/// stylesheets which `@use` the one containing it
+#[derive(Debug, Clone)]
+pub struct ForwardStatement {
+    pub url: String,
+    pub filter: Option<ForwardFilter>,
+    pub position: Option<Span>,
+}
+
+/// Names of the members a `@forward` shows or hides. Variable names start with `$`
+#[derive(Debug, Clone)]
+pub enum ForwardFilter {
+    Show(Vec<String>),
+    Hide(Vec<String>),
+}
+
+/// `@import "a", "b";` of SCSS files, whose contents are compiled in place. Imports of plain CSS
+/// (urls ending with `.css` or starting with `http://`, `url()`s, or with media queries) are left
+/// as [`AtRule`](crate::AtRule)s
+#[derive(Debug, Clone)]
+pub struct ImportStatement {
+    pub urls: Vec<String>,
+    pub position: Option<Span>,
+}
+
+/// A parameter of a mixin or function e.g. `$b: default`
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    /// Name without the `$`
+    pub name: String,
+    pub default: Option<CSSValue>,
+}
+
+/// `@include name(args)` with an optional block which replaces `@content` in the mixin body
+#[derive(Debug, Clone)]
+pub struct Include {
+    pub name: String,
+    pub arguments: Vec<Argument>,
+    pub content: Option<Vec<BlockItem>>,
+    pub position: Option<Span>,
+}
+
+/// A positional or keyword (`$name: value`) argument
+#[derive(Debug, Clone)]
+pub struct Argument {
+    /// Name without the `$` for keyword arguments
+    pub name: Option<String>,
+    pub value: CSSValue,
+}
+
+/// `@extend .a, %b !optional;`. Adds the selectors of the rule it is in to the rules matching
+/// the selectors
+#[derive(Debug, Clone)]
+pub struct Extend {
+    /// Compound selectors to extend
+    pub selectors: Vec<Selector>,
+    /// `!optional`. Does not error if no rules match
+    pub optional: bool,
+    pub position: Option<Span>,
+}
+
+/// `@if $a { ... } @else if $b { ... } @else { ... }`
+#[derive(Debug, Clone)]
+pub struct IfStatement {
+    /// Conditions and bodies of the `@if` and `@else if`s
+    pub branches: Vec<(CSSValue, Vec<BlockItem>)>,
+    pub else_branch: Option<Vec<BlockItem>>,
+    pub position: Option<Span>,
+}
+
+/// `@each $item in a, b, c { ... }`. With multiple variables each item is destructured
+#[derive(Debug, Clone)]
+pub struct EachStatement {
+    /// Names without the `$`
+    pub variables: Vec<String>,
+    pub list: CSSValue,
+    pub body: Vec<BlockItem>,
+    pub position: Option<Span>,
+}
+
+/// `@for $i from 1 through 3 { ... }`
+#[derive(Debug, Clone)]
+pub struct ForStatement {
+    /// Name without the `$`
+    pub variable: String,
+    pub from: CSSValue,
+    pub to: CSSValue,
+    /// `through` includes `to`, `to` does not
+    pub inclusive: bool,
+    pub body: Vec<BlockItem>,
+    pub position: Option<Span>,
+}
+
+/// `@while $i > 0 { ... }`
+#[derive(Debug, Clone)]
+pub struct WhileStatement {
+    pub condition: CSSValue,
+    pub body: Vec<BlockItem>,
+    pub position: Option<Span>,
+}
+
+/// A item in a SCSS block. Keeps declarations and nested rules in order with the statements
+#[derive(Debug, Clone, From)]
+pub enum BlockItem {
+    Declaration(Declaration),
+    Rule(Rule),
+    Statement(ScssStatement),
+}
+
+impl ASTNode for ScssStatement {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        match reader.peek().unwrap() {
+            Token(CSSToken::Variable(_), _) => Ok(VariableDeclaration::from_reader(reader)?.into()),
+            Token(CSSToken::AtKeyword(name), _) if name == "mixin" => {
+                Ok(MixinDefinition::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "include" => {
+                Ok(Include::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "function" => {
+                Ok(FunctionDefinition::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "return" => {
+                Ok(ReturnStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "use" => {
+                Ok(UseStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "forward" => {
+                Ok(ForwardStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "import" => {
+                Ok(ImportStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "extend" => {
+                Ok(Extend::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "if" => {
+                Ok(IfStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "each" => {
+                Ok(EachStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "for" => {
+                Ok(ForStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "while" => {
+                Ok(WhileStatement::from_reader(reader)?.into())
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "else" => {
+                let position = reader.next().unwrap().1;
+                Err(ParseError {
+                    reason: "@else must come after @if".to_owned(),
+                    position,
+                })
+            }
+            Token(CSSToken::AtKeyword(name), _) if name == "content" => {
+                let mut position = reader.next().unwrap().1;
+                if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+                    position = position.union(&reader.next().unwrap().1);
+                }
+                Ok(ScssStatement::Content(position))
+            }
+            _ => {
+                let Token(token, position) = reader.next().unwrap();
+                Err(ParseError {
+                    reason: format!("Expected SCSS statement found '{:?}'", token),
+                    position,
+                })
+            }
+        }
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        match self {
+            ScssStatement::Variable(variable) => {
+                variable.to_string_from_buffer(buf, settings, depth)
+            }
+            ScssStatement::Mixin(mixin) => mixin.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::Include(include) => include.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::Function(function) => {
+                function.to_string_from_buffer(buf, settings, depth)
+            }
+            ScssStatement::Return(statement) => {
+                statement.to_string_from_buffer(buf, settings, depth)
+            }
+            ScssStatement::Use(statement) => statement.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::Forward(statement) => {
+                statement.to_string_from_buffer(buf, settings, depth)
+            }
+            ScssStatement::Import(statement) => {
+                statement.to_string_from_buffer(buf, settings, depth)
+            }
+            ScssStatement::Extend(extend) => extend.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::If(statement) => statement.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::Each(statement) => statement.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::For(statement) => statement.to_string_from_buffer(buf, settings, depth),
+            ScssStatement::While(statement) => {
+                statement.to_string_from_buffer(buf, settings, depth)
+            }
+            ScssStatement::Content(position) => {
+                buf.add_mapping(position);
+                buf.push_str("@content;");
+            }
+        }
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        match self {
+            ScssStatement::Variable(variable) => variable.get_position(),
+            ScssStatement::Mixin(mixin) => mixin.get_position(),
+            ScssStatement::Include(include) => include.get_position(),
+            ScssStatement::Function(function) => function.get_position(),
+            ScssStatement::Return(statement) => statement.get_position(),
+            ScssStatement::Use(statement) => statement.get_position(),
+            ScssStatement::Forward(statement) => statement.get_position(),
+            ScssStatement::Import(statement) => statement.get_position(),
+            ScssStatement::Extend(extend) => extend.get_position(),
+            ScssStatement::If(statement) => statement.get_position(),
+            ScssStatement::Each(statement) => statement.get_position(),
+            ScssStatement::For(statement) => statement.get_position(),
+            ScssStatement::While(statement) => statement.get_position(),
+            ScssStatement::Content(position) => Some(position),
+        }
+    }
+}
+
+impl ScssStatement {
+    /// Whether the at-rule is a SCSS statement rather than a CSS at-rule
+    pub(crate) fn is_scss_at_rule(name: &str) -> bool {
+        matches!(
+            name,
+            "mixin"
+                | "include"
+                | "content"
+                | "function"
+                | "return"
+                | "use"
+                | "forward"
+                | "extend"
+                | "if"
+                | "else"
+                | "each"
+                | "for"
+                | "while"
+        )
+    }
+}
+
+impl ASTNode for UseStatement {
+    /// Parses up to and including the `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let (url, mut end_span) = url_from_reader(reader)?;
+        let alias = if matches!(reader.peek(), Some(Token(CSSToken::Ident(ident), _)) if ident == "as")
+        {
+            reader.next();
+            match reader.next().unwrap() {
+                Token(CSSToken::Ident(alias), position) => {
+                    end_span = position;
+                    Some(alias)
+                }
+                Token(CSSToken::Asterisk, position) => {
+                    end_span = position;
+                    Some("*".to_owned())
+                }
+                Token(token, position) => {
+                    return Err(ParseError {
+                        reason: format!("Expected namespace found '{}'", token),
+                        position,
+                    })
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+            end_span = reader.next().unwrap().1;
+        }
+        Ok(Self {
+            url,
+            alias,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        _settings: &ToStringSettings,
+        _depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@use \"");
+        buf.push_str(&self.url);
+        buf.push('"');
+        if let Some(ref alias) = self.alias {
+            buf.push_str(" as ");
+            buf.push_str(alias);
+        }
+        buf.push(';');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl UseStatement {
+    /// The alias, else the name of the file without a leading `_` or extension. `None` for
+    /// `as *`
+    pub fn namespace(&self) -> Option<String> {
+        match self.alias.as_deref() {
+            Some("*") => None,
+            Some(alias) => Some(alias.to_owned()),
+            None => {
+                let name = self.url.rsplit('/').next().unwrap();
+                let name = name.strip_prefix("sass:").unwrap_or(name);
+                let name = name.strip_prefix('_').unwrap_or(name);
+                Some(name.split('.').next().unwrap().to_owned())
+            }
+        }
+    }
+}
+
+impl ASTNode for ForwardStatement {
+    /// Parses up to and including the `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let (url, mut end_span) = url_from_reader(reader)?;
+        let filter = match reader.peek() {
+            Some(Token(CSSToken::Ident(ident), _)) if ident == "show" || ident == "hide" => {
+                let show = ident == "show";
+                reader.next();
+                let mut members = Vec::new();
+                loop {
+                    match reader.next().unwrap() {
+                        Token(CSSToken::Ident(name), position) => {
+                            members.push(name);
+                            end_span = position;
+                        }
+                        Token(CSSToken::Variable(name), position) => {
+                            members.push(format!("${}", name));
+                            end_span = position;
+                        }
+                        Token(token, position) => {
+                            return Err(ParseError {
+                                reason: format!("Expected member name found '{}'", token),
+                                position,
+                            })
+                        }
+                    }
+                    if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+                        reader.next();
+                    } else {
+                        break;
+                    }
+                }
+                Some(if show {
+                    ForwardFilter::Show(members)
+                } else {
+                    ForwardFilter::Hide(members)
+                })
+            }
+            _ => None,
+        };
+        if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+            end_span = reader.next().unwrap().1;
+        }
+        Ok(Self {
+            url,
+            filter,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        _depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@forward \"");
+        buf.push_str(&self.url);
+        buf.push('"');
+        if let Some(ref filter) = self.filter {
+            let (keyword, members) = match filter {
+                ForwardFilter::Show(members) => (" show ", members),
+                ForwardFilter::Hide(members) => (" hide ", members),
+            };
+            buf.push_str(keyword);
+            for (idx, member) in members.iter().enumerate() {
+                buf.push_str(member);
+                if idx + 1 < members.len() {
+                    buf.push(',');
+                    if !settings.minify {
+                        buf.push(' ');
+                    }
+                }
+            }
+        }
+        buf.push(';');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ForwardFilter {
+    /// Whether the member, with a `$` if it is a variable, is forwarded
+    pub(crate) fn allows(&self, member: &str) -> bool {
+        match self {
+            ForwardFilter::Show(members) => members.iter().any(|shown| shown == member),
+            ForwardFilter::Hide(members) => members.iter().all(|hidden| hidden != member),
+        }
+    }
+}
+
+impl ASTNode for ImportStatement {
+    /// Parses up to and including the `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let mut urls = Vec::new();
+        let mut end_span = loop {
+            let (url, end_span) = url_from_reader(reader)?;
+            urls.push(url);
+            if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+                reader.next();
+            } else {
+                break end_span;
+            }
+        };
+        if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+            end_span = reader.next().unwrap().1;
+        }
+        Ok(Self {
+            urls,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        _depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@import ");
+        for (idx, url) in self.urls.iter().enumerate() {
+            buf.push('"');
+            buf.push_str(url);
+            buf.push('"');
+            if idx + 1 < self.urls.len() {
+                buf.push(',');
+                if !settings.minify {
+                    buf.push(' ');
+                }
+            }
+        }
+        buf.push(';');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ImportStatement {
+    /// Whether the `@import` ahead imports SCSS rather than being a plain CSS import. Does not
+    /// advance the reader
+    pub(crate) fn is_scss_import_ahead(reader: &mut impl TokenReader<CSSToken, Span>) -> bool {
+        let mut is_scss = true;
+        let mut count = 0;
+        reader.scan(|token, _| {
+            count += 1;
+            match token {
+                // The `@import`
+                _ if count == 1 => false,
+                CSSToken::String(url) => {
+                    let is_css = url.ends_with(".css")
+                        || url.starts_with("http://")
+                        || url.starts_with("https://")
+                        || url.starts_with("//");
+                    if is_css {
+                        is_scss = false;
+                    }
+                    is_css
+                }
+                CSSToken::Comma | CSSToken::EOS => false,
+                CSSToken::SemiColon => true,
+                // `url()` or media queries
+                _ => {
+                    is_scss = false;
+                    true
+                }
+            }
+        });
+        is_scss
+    }
+}
+
+impl ASTNode for VariableDeclaration {
+    /// Parses up to and including the `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let (name, start_span) = match reader.next().unwrap() {
+            Token(CSSToken::Variable(name), position) => (name, position),
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected variable found '{:?}'", token),
+                    position,
+                })
+            }
+        };
+        reader.expect_next(CSSToken::Colon)?;
+        let mut end_span =
+            CSSValue::end_position_ahead(reader).unwrap_or_else(|| start_span.clone());
+        let value = CSSValue::from_reader(reader)?;
+        let (mut default, mut global) = (false, false);
+        while let Some(Token(CSSToken::Exclamation, _)) = reader.peek() {
+            reader.next();
+            match reader.next().unwrap() {
+                Token(CSSToken::Ident(flag), position) if flag == "default" => {
+                    default = true;
+                    end_span = position;
+                }
+                Token(CSSToken::Ident(flag), position) if flag == "global" => {
+                    global = true;
+                    end_span = position;
+                }
+                Token(token, position) => {
+                    return Err(ParseError {
+                        reason: format!(
+                            "Expected 'default' or 'global' after '!' found '{}'",
+                            token
+                        ),
+                        position,
+                    })
+                }
+            }
+        }
+        if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+            end_span = reader.next().unwrap().1;
+        }
+        Ok(Self {
+            name,
+            value,
+            default,
+            global,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push('$');
+        buf.push_str(&self.name);
+        buf.push(':');
+        if !settings.minify {
+            buf.push(' ');
+        }
+        self.value.to_string_from_buffer(buf, settings, depth);
+        for (flag, set) in [("!default", self.default), ("!global", self.global)] {
+            if set {
+                if !settings.minify {
+                    buf.push(' ');
+                }
+                buf.push_str(flag);
+            }
+        }
+        buf.push(';');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for MixinDefinition {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let (name, _) = token_as_ident(reader.next().unwrap())?;
+        let parameters = if let Some(Token(CSSToken::OpenBracket, _)) = reader.peek() {
+            parameters_from_reader(reader)?
+        } else {
+            Vec::new()
+        };
+        let (body, end_span) = block_items_from_reader(reader, None)?;
+        Ok(Self {
+            name,
+            parameters,
+            body,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@mixin ");
+        buf.push_str(&self.name);
+        if !self.parameters.is_empty() {
+            parameters_to_string_from_buffer(&self.parameters, buf, settings, depth);
+        }
+        block_to_string_from_buffer(&self.body, buf, settings, depth);
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for FunctionDefinition {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let (name, _) = token_as_ident(reader.next().unwrap())?;
+        let parameters = parameters_from_reader(reader)?;
+        let (body, end_span) = block_items_from_reader(reader, None)?;
+        Ok(Self {
+            name,
+            parameters,
+            body,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@function ");
+        buf.push_str(&self.name);
+        parameters_to_string_from_buffer(&self.parameters, buf, settings, depth);
+        block_to_string_from_buffer(&self.body, buf, settings, depth);
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for ReturnStatement {
+    /// Parses up to and including the `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let mut end_span =
+            CSSValue::end_position_ahead(reader).unwrap_or_else(|| start_span.clone());
+        let value = CSSValue::from_reader(reader)?;
+        if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+            end_span = reader.next().unwrap().1;
+        }
+        Ok(Self {
+            value,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@return ");
+        self.value.to_string_from_buffer(buf, settings, depth);
+        buf.push(';');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for Include {
+    /// Parses up to and including the `;` or the end of the content block
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let (mut name, mut end_span) = token_as_ident(reader.next().unwrap())?;
+        // Mixin from a module e.g. `@include theme.button`
+        if CSSValue::member_ahead(reader, &end_span).is_some() {
+            reader.next();
+            let (member, member_span) = token_as_ident(reader.next().unwrap())?;
+            name.push('.');
+            name.push_str(&member);
+            end_span = member_span;
+        }
+        let arguments = if let Some(Token(CSSToken::OpenBracket, _)) = reader.peek() {
+            let (arguments, close_span) = arguments_from_reader(reader)?;
+            end_span = close_span;
+            arguments
+        } else {
+            Vec::new()
+        };
+        let content = if let Some(Token(CSSToken::OpenCurly, _)) = reader.peek() {
+            let (content, close_span) = block_items_from_reader(reader, None)?;
+            end_span = close_span;
+            Some(content)
+        } else {
+            if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+                end_span = reader.next().unwrap().1;
+            }
+            None
+        };
+        Ok(Self {
+            name,
+            arguments,
+            content,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@include ");
+        buf.push_str(&self.name);
+        if !self.arguments.is_empty() {
+            buf.push('(');
+            for (idx, argument) in self.arguments.iter().enumerate() {
+                if let Some(ref name) = argument.name {
+                    buf.push('$');
+                    buf.push_str(name);
+                    buf.push(':');
+                    if !settings.minify {
+                        buf.push(' ');
+                    }
+                }
+                argument.value.to_string_from_buffer(buf, settings, depth);
+                if idx + 1 < self.arguments.len() {
+                    buf.push(',');
+                    if !settings.minify {
+                        buf.push(' ');
+                    }
+                }
+            }
+            buf.push(')');
+        }
+        match self.content {
+            Some(ref content) => block_to_string_from_buffer(content, buf, settings, depth),
+            None => buf.push(';'),
+        }
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for Extend {
+    /// Parses up to and including the `;`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let selectors = selector_list_from_reader(reader)?;
+        let mut end_span = selectors.last().unwrap().get_position().unwrap().clone();
+        let optional = if let Some(Token(CSSToken::Exclamation, _)) = reader.peek() {
+            reader.next();
+            match reader.next().unwrap() {
+                Token(CSSToken::Ident(flag), position) if flag == "optional" => {
+                    end_span = position;
+                    true
+                }
+                Token(token, position) => {
+                    return Err(ParseError {
+                        reason: format!("Expected 'optional' after '!' found '{}'", token),
+                        position,
+                    })
+                }
+            }
+        } else {
+            false
+        };
+        if let Some(Token(CSSToken::SemiColon, _)) = reader.peek() {
+            end_span = reader.next().unwrap().1;
+        }
+        Ok(Self {
+            selectors,
+            optional,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@extend ");
+        selector_list_to_string_from_buffer(&self.selectors, buf, settings, depth);
+        if self.optional {
+            if !settings.minify {
+                buf.push(' ');
+            }
+            buf.push_str("!optional");
+        }
+        buf.push(';');
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for IfStatement {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let condition = CSSValue::expression_from_reader(reader)?;
+        let (body, mut end_span) = block_items_from_reader(reader, None)?;
+        let mut branches = vec![(condition, body)];
+        let mut else_branch = None;
+        while matches!(reader.peek(), Some(Token(CSSToken::AtKeyword(name), _)) if name == "else") {
+            reader.next();
+            if let Some(Token(CSSToken::Ident(ident), _)) = reader.peek() {
+                if ident == "if" {
+                    reader.next();
+                    let condition = CSSValue::expression_from_reader(reader)?;
+                    let (body, close_span) = block_items_from_reader(reader, None)?;
+                    branches.push((condition, body));
+                    end_span = close_span;
+                    continue;
+                }
+            }
+            let (body, close_span) = block_items_from_reader(reader, None)?;
+            else_branch = Some(body);
+            end_span = close_span;
+            break;
+        }
+        Ok(Self {
+            branches,
+            else_branch,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        for (idx, (condition, body)) in self.branches.iter().enumerate() {
+            if idx == 0 {
+                buf.push_str("@if ");
+            } else {
+                if !settings.minify {
+                    buf.push(' ');
+                }
+                buf.push_str("@else if ");
+            }
+            condition.to_string_from_buffer(buf, settings, depth);
+            block_to_string_from_buffer(body, buf, settings, depth);
+        }
+        if let Some(ref body) = self.else_branch {
+            if !settings.minify {
+                buf.push(' ');
+            }
+            buf.push_str("@else");
+            block_to_string_from_buffer(body, buf, settings, depth);
+        }
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for EachStatement {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let mut variables = vec![variable_name_from_reader(reader)?];
+        while let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+            reader.next();
+            variables.push(variable_name_from_reader(reader)?);
+        }
+        expect_keyword(reader, "in")?;
+        let list = CSSValue::from_reader(reader)?;
+        let (body, end_span) = block_items_from_reader(reader, None)?;
+        Ok(Self {
+            variables,
+            list,
+            body,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@each ");
+        for (idx, variable) in self.variables.iter().enumerate() {
+            buf.push('$');
+            buf.push_str(variable);
+            if idx + 1 < self.variables.len() {
+                buf.push(',');
+                if !settings.minify {
+                    buf.push(' ');
+                }
+            }
+        }
+        buf.push_str(" in ");
+        self.list.to_string_from_buffer(buf, settings, depth);
+        block_to_string_from_buffer(&self.body, buf, settings, depth);
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for ForStatement {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let variable = variable_name_from_reader(reader)?;
+        expect_keyword(reader, "from")?;
+        let from = CSSValue::expression_from_reader(reader)?;
+        let inclusive = match reader.next().unwrap() {
+            Token(CSSToken::Ident(keyword), _) if keyword == "through" => true,
+            Token(CSSToken::Ident(keyword), _) if keyword == "to" => false,
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected 'through' or 'to' found '{}'", token),
+                    position,
+                })
+            }
+        };
+        let to = CSSValue::expression_from_reader(reader)?;
+        let (body, end_span) = block_items_from_reader(reader, None)?;
+        Ok(Self {
+            variable,
+            from,
+            to,
+            inclusive,
+            body,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@for $");
+        buf.push_str(&self.variable);
+        buf.push_str(" from ");
+        self.from.to_string_from_buffer(buf, settings, depth);
+        buf.push_str(if self.inclusive { " through " } else { " to " });
+        self.to.to_string_from_buffer(buf, settings, depth);
+        block_to_string_from_buffer(&self.body, buf, settings, depth);
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+impl ASTNode for WhileStatement {
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let start_span = reader.next().unwrap().1;
+        let condition = CSSValue::expression_from_reader(reader)?;
+        let (body, end_span) = block_items_from_reader(reader, None)?;
+        Ok(Self {
+            condition,
+            body,
+            position: Some(start_span.union(&end_span)),
+        })
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if let Some(ref position) = self.position {
+            buf.add_mapping(position);
+        }
+        buf.push_str("@while ");
+        self.condition.to_string_from_buffer(buf, settings, depth);
+        block_to_string_from_buffer(&self.body, buf, settings, depth);
+    }
+
+    fn get_position(&self) -> Option<&Span> {
+        self.position.as_ref()
+    }
+}
+
+/// Prints ` { items }`
+fn block_to_string_from_buffer(
+    items: &[BlockItem],
+    buf: &mut impl ToString,
+    settings: &ToStringSettings,
+    depth: u8,
+) {
+    if !settings.minify {
+        buf.push(' ');
+    }
+    buf.push('{');
+    BlockItem::items_to_string_from_buffer(items, buf, settings, depth);
+    buf.push('}');
+}
+
+/// A quoted url of `@use`, `@forward` or `@import`
+fn url_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<(String, Span), ParseError> {
+    match reader.next().unwrap() {
+        Token(CSSToken::String(url), position) => Ok((url, position)),
+        Token(token, position) => Err(ParseError {
+            reason: format!("Expected quoted url found '{}'", token),
+            position,
+        }),
+    }
+}
+
+fn variable_name_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<String, ParseError> {
+    match reader.next().unwrap() {
+        Token(CSSToken::Variable(name), _) => Ok(name),
+        Token(token, position) => Err(ParseError {
+            reason: format!("Expected variable found '{}'", token),
+            position,
+        }),
+    }
+}
+
+fn expect_keyword(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+    keyword: &str,
+) -> Result<(), ParseError> {
+    match reader.next().unwrap() {
+        Token(CSSToken::Ident(ident), _) if ident == keyword => Ok(()),
+        Token(token, position) => Err(ParseError {
+            reason: format!("Expected '{}' found '{}'", keyword, token),
+            position,
+        }),
+    }
+}
+
+/// Parses `($a, $b: default)`
+fn parameters_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<Vec<Parameter>, ParseError> {
+    reader.expect_next(CSSToken::OpenBracket)?;
+    let mut parameters = Vec::new();
+    while !matches!(reader.peek(), Some(Token(CSSToken::CloseBracket, _))) {
+        let name = match reader.next().unwrap() {
+            Token(CSSToken::Variable(name), _) => name,
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected parameter found '{}'", token),
+                    position,
+                })
+            }
+        };
+        let default = if let Some(Token(CSSToken::Colon, _)) = reader.peek() {
+            reader.next();
+            Some(CSSValue::argument_from_reader(reader)?)
+        } else {
+            None
+        };
+        parameters.push(Parameter { name, default });
+        if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+            reader.next();
+        } else {
+            break;
+        }
+    }
+    reader.expect_next(CSSToken::CloseBracket)?;
+    Ok(parameters)
+}
+
+/// Prints `($a, $b: default)`
+fn parameters_to_string_from_buffer(
+    parameters: &[Parameter],
+    buf: &mut impl ToString,
+    settings: &ToStringSettings,
+    depth: u8,
+) {
+    buf.push('(');
+    for (idx, parameter) in parameters.iter().enumerate() {
+        buf.push('$');
+        buf.push_str(&parameter.name);
+        if let Some(ref default) = parameter.default {
+            buf.push(':');
+            if !settings.minify {
+                buf.push(' ');
+            }
+            default.to_string_from_buffer(buf, settings, depth);
+        }
+        if idx + 1 < parameters.len() {
+            buf.push(',');
+            if !settings.minify {
+                buf.push(' ');
+            }
+        }
+    }
+    buf.push(')');
+}
+
+/// Parses `(value, $name: value)`. Returns the arguments and the position of the `)`
+fn arguments_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<(Vec<Argument>, Span), ParseError> {
+    reader.expect_next(CSSToken::OpenBracket)?;
+    let mut arguments = Vec::new();
+    while !matches!(reader.peek(), Some(Token(CSSToken::CloseBracket, _))) {
+        // Keyword arguments are a variable followed by a `:`
+        let mut is_keyword = false;
+        if let Some(Token(CSSToken::Variable(_), _)) = reader.peek() {
+            let mut count = 0;
+            reader.scan(|token, _| {
+                count += 1;
+                is_keyword = count == 2 && *token == CSSToken::Colon;
+                count == 2
+            });
+        }
+        let name = if is_keyword {
+            let name = match reader.next().unwrap() {
+                Token(CSSToken::Variable(name), _) => name,
+                _ => unreachable!(),
+            };
+            reader.next();
+            Some(name)
+        } else {
+            None
+        };
+        let value = CSSValue::argument_from_reader(reader)?;
+        arguments.push(Argument { name, value });
+        if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+            reader.next();
+        } else {
+            break;
+        }
+    }
+    let close_span = reader.expect_next(CSSToken::CloseBracket)?;
+    Ok((arguments, close_span))
+}
+
+impl BlockItem {
+    /// Prints items in a block, each on a new line at `depth + 1`
+    pub(crate) fn items_to_string_from_buffer(
+        items: &[BlockItem],
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        for item in items.iter() {
+            if !settings.minify {
+                buf.push_new_line();
+                buf.push_str(&settings.indent_with.repeat(depth as usize + 1));
+            }
+            match item {
+                BlockItem::Declaration(declaration) => {
+                    declaration.to_string_from_buffer(buf, settings, depth + 1);
+                    buf.push(';');
+                }
+                BlockItem::Rule(rule) => rule.to_string_from_buffer(buf, settings, depth + 1),
+                BlockItem::Statement(statement) => {
+                    statement.to_string_from_buffer(buf, settings, depth + 1)
+                }
+            }
+        }
+        if !settings.minify && !items.is_empty() {
+            buf.push_new_line();
+            buf.push_str(&settings.indent_with.repeat(depth as usize));
+        }
+    }
+}
+
+#[cfg(test)]
+mod scss_tests {
+    use super::*;
+    use source_map::SourceId;
+
+    #[test]
+    fn variable_declaration() {
+        let variable = VariableDeclaration::from_string(
+            "$primary: #0000ff !default !global;".to_owned(),
+            SourceId::null(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(variable.name, "primary");
+        assert_eq!(variable.value, CSSValue::Color("0000ff".to_owned()));
+        assert!(variable.default && variable.global);
+        assert_eq!(variable.position.as_ref().unwrap().end, 35);
+        assert_eq!(
+            variable.to_string(&ToStringSettings::minified()),
+            "$primary:#0000ff!default!global;"
+        );
+        assert!(VariableDeclaration::from_string(
+            "$a: 1 !important".to_owned(),
+            SourceId::null(),
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn mixin_and_include() {
+        let source = "@mixin m($a, $b: 1px 2px) {\n    width: $a;\n    @content;\n}";
+        let statement =
+            ScssStatement::from_string(source.to_owned(), SourceId::null(), None).unwrap();
+        assert_eq!(statement.to_string(&ToStringSettings::default()), source);
+        let include = Include::from_string(
+            "@include m(1px, $b: 2px) { color: red; }".to_owned(),
+            SourceId::null(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(include.arguments[1].name.as_deref(), Some("b"));
+        assert_eq!(include.position.as_ref().unwrap().end, 40);
+        assert_eq!(
+            include.to_string(&ToStringSettings::minified()),
+            "@include m(1px,$b:2px){color:red;}"
+        );
+    }
+
+    #[test]
+    fn function_definition() {
+        let source = "@function rem($px, $base: 16px) {\n    $result: math.div($px, $base);\n    @return $result * 1rem;\n}";
+        let statement =
+            ScssStatement::from_string(source.to_owned(), SourceId::null(), None).unwrap();
+        assert!(matches!(
+            &statement,
+            ScssStatement::Function(function) if function.parameters.len() == 2
+        ));
+        assert_eq!(statement.to_string(&ToStringSettings::default()), source);
+        assert_eq!(
+            statement.to_string(&ToStringSettings::minified()),
+            "@function rem($px,$base:16px){$result:math.div($px,$base);@return $result*1rem;}"
+        );
+    }
+
+    #[test]
+    fn modules() {
+        let source = "@use \"src/_config.scss\";\n\n@use \"theme\" as *;\n\n@forward \"a\" hide $b, c;\n\n@import \"d\", \"e\";\n\n@import \"f.css\";\n\na {\n    @include config.m;\n}";
+        let style_sheet =
+            crate::StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        assert_eq!(style_sheet.to_string(None), source);
+        match &style_sheet.entries[..] {
+            [crate::Entry::ScssStatement(ScssStatement::Use(config)), crate::Entry::ScssStatement(ScssStatement::Use(theme)), crate::Entry::ScssStatement(ScssStatement::Forward(forward)), crate::Entry::ScssStatement(ScssStatement::Import(import)), crate::Entry::AtRule(_), _] =>
+            {
+                assert_eq!(config.namespace().as_deref(), Some("config"));
+                assert_eq!(theme.namespace(), None);
+                let filter = forward.filter.as_ref().unwrap();
+                assert!(filter.allows("$a") && !filter.allows("$b") && !filter.allows("c"));
+                assert_eq!(import.urls, ["d", "e"]);
+            }
+            entries => panic!("Unexpected entries {:?}", entries),
+        }
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "a {\n    @if $a <= 1 {\n        b: c;\n    } @else if $a != 2 {\n        b: d;\n    } @else {\n        @for $i from 1 to $n {\n            @each $x, $y in $list {\n                @while $z {}\n            }\n        }\n    }\n}";
+        let style_sheet =
+            crate::StyleSheet::from_string(source.to_owned(), SourceId::null()).unwrap();
+        assert_eq!(style_sheet.to_string(None), source);
+        // `@if` and `@else` are recovered as one item
+        let (style_sheet, errors) = crate::StyleSheet::from_string_recovering(
+            "a { @if x { b: c; } @else { d: e; } f: ; }".to_owned(),
+            SourceId::null(),
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            style_sheet.to_string(Some(ToStringSettings::minified())),
+            "a{@if x{b:c;}@else{d:e;}}"
+        );
+    }
+}
@@ -1,43 +1,64 @@
 use super::{token_as_ident, ASTNode, CSSToken, ParseError, Span, ToStringSettings};
-use source_map::ToString;
+use source_map::{SourceId, ToString};
 use tokenizer_lib::{Token, TokenReader};
 
 /// [A css selector](https://developer.mozilla.org/en-US/docs/Web/CSS/CSS_Selectors)
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Selector {
+    /// SCSS `&`, replaced by the parent selector when nested. Holds the suffix of `&__title`,
+    /// empty for a plain `&`
+    parent: Option<String>,
+    /// A combinator before the selector, relating it to the parent rule e.g. `> b` nested in a
+    /// rule, or to the subject of `:has(> img)`
+    leading_combinator: Option<Combinator>,
     /// Can be '*' for universal
     tag_name: Option<String>,
     /// #...
     identifier: Option<String>,
     /// .x.y.z
     class_names: Option<Vec<String>>,
-    /// div h1
-    descendant: Option<Box<Selector>>,
-    /// div > h1
-    child: Option<Box<Selector>>,
+    /// SCSS %x. Only matched by `@extend`, removed from output by [`compile`](crate::compile)
+    placeholders: Option<Vec<String>>,
+    /// [x][y="z"]
+    attributes: Option<Vec<AttributeSelector>>,
+    /// :hover:not(.x)::before, in source order
+    pseudos: Option<Vec<Pseudo>>,
+    /// The next compound selector and how it relates to this one e.g. `div > h1`
+    next: Option<(Combinator, Box<Selector>)>,
     position: Option<Span>,
 }
 
 impl ASTNode for Selector {
     fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
         let mut selector: Self = Self {
+            parent: None,
+            leading_combinator: None,
             tag_name: None,
             identifier: None,
             class_names: None,
-            descendant: None,
-            child: None,
+            placeholders: None,
+            attributes: None,
+            pseudos: None,
+            next: None,
             position: None,
         };
         for i in 0.. {
             // Handling "descendant" parsing by checking gap/space in tokens
             let Token(peek_token, peek_span) = reader.peek().unwrap();
 
-            if matches!(peek_token, CSSToken::Comma) {
+            // End of selector in selector list, in a functional pseudo class or in `@extend`
+            if matches!(
+                peek_token,
+                CSSToken::Comma
+                    | CSSToken::CloseBracket
+                    | CSSToken::SemiColon
+                    | CSSToken::Exclamation
+            ) {
                 return Ok(selector);
             }
 
             if i != 0
-                && !matches!(peek_token, CSSToken::CloseAngle)
+                && Combinator::from_token(peek_token).is_none()
                 && !selector
                     .position
                     .as_ref()
@@ -45,12 +66,29 @@ impl ASTNode for Selector {
                     .is_adjacent_to(peek_span)
             {
                 let descendant = Self::from_reader(reader)?;
-                selector.descendant = Some(Box::new(descendant));
+                selector.next = Some((Combinator::Descendant, Box::new(descendant)));
                 break;
             }
             match reader.next().unwrap() {
+                Token(CSSToken::Ampersand, mut position) => {
+                    if selector.position.is_some() {
+                        return Err(ParseError {
+                            reason: "'&' must be at the start of a compound selector".to_owned(),
+                            position,
+                        });
+                    }
+                    let mut suffix = String::new();
+                    if matches!(reader.peek(), Some(Token(CSSToken::Ident(_), ident_position)) if position.is_adjacent_to(ident_position))
+                    {
+                        let (ident, end_span) = token_as_ident(reader.next().unwrap())?;
+                        suffix = ident;
+                        position = position.union(&end_span);
+                    }
+                    selector.parent = Some(suffix);
+                    selector.position = Some(position);
+                }
                 Token(CSSToken::Ident(name), pos) => {
-                    if let Some(_) = selector.tag_name.replace(name) {
+                    if selector.tag_name.replace(name).is_some() {
                         return Err(ParseError {
                             reason: "Tag name specified twice".to_owned(),
                             position: pos,
@@ -59,7 +97,7 @@ impl ASTNode for Selector {
                     selector.position = Some(pos);
                 }
                 Token(CSSToken::Asterisk, pos) => {
-                    if let Some(_) = selector.tag_name.replace("*".to_owned()) {
+                    if selector.tag_name.replace("*".to_owned()).is_some() {
                         return Err(ParseError {
                             reason: "Tag name specified twice".to_owned(),
                             position: pos,
@@ -71,7 +109,7 @@ impl ASTNode for Selector {
                     let (class_name, end_span) = token_as_ident(reader.next().unwrap())?;
                     selector
                         .class_names
-                        .get_or_insert_with(|| Vec::new())
+                        .get_or_insert_with(Vec::new)
                         .push(class_name);
                     if let Some(ref mut selector_position) = selector.position {
                         *selector_position = selector_position.union(&end_span);
@@ -79,6 +117,18 @@ impl ASTNode for Selector {
                         selector.position = Some(start_span.union(&end_span));
                     }
                 }
+                Token(CSSToken::Percentage, start_span) => {
+                    let (placeholder, end_span) = token_as_ident(reader.next().unwrap())?;
+                    selector
+                        .placeholders
+                        .get_or_insert_with(Vec::new)
+                        .push(placeholder);
+                    if let Some(ref mut selector_position) = selector.position {
+                        *selector_position = selector_position.union(&end_span);
+                    } else {
+                        selector.position = Some(start_span.union(&end_span));
+                    }
+                }
                 Token(CSSToken::HashPrefixedValue(identifier), position) => {
                     if selector.identifier.replace(identifier).is_some() {
                         return Err(ParseError {
@@ -92,17 +142,47 @@ impl ASTNode for Selector {
                         selector.position = Some(position);
                     }
                 }
-                Token(CSSToken::CloseAngle, position) => {
-                    let child = Self::from_reader(reader)?;
+                Token(CSSToken::OpenSquareBracket, start_span) => {
+                    let (attribute, end_span) = AttributeSelector::from_reader(reader)?;
+                    selector
+                        .attributes
+                        .get_or_insert_with(Vec::new)
+                        .push(attribute);
+                    if let Some(ref mut selector_position) = selector.position {
+                        *selector_position = selector_position.union(&end_span);
+                    } else {
+                        selector.position = Some(start_span.union(&end_span));
+                    }
+                }
+                Token(CSSToken::Colon, start_span) => {
+                    let (pseudo, end_span) = if let Some(Token(CSSToken::Colon, _)) = reader.peek()
+                    {
+                        reader.next();
+                        let (pseudo_element, end_span) = PseudoElement::from_reader(reader)?;
+                        (Pseudo::Element(pseudo_element), end_span)
+                    } else {
+                        let (pseudo_class, end_span) = PseudoClass::from_reader(reader)?;
+                        (Pseudo::Class(pseudo_class), end_span)
+                    };
+                    selector.pseudos.get_or_insert_with(Vec::new).push(pseudo);
+                    if let Some(ref mut selector_position) = selector.position {
+                        *selector_position = selector_position.union(&end_span);
+                    } else {
+                        selector.position = Some(start_span.union(&end_span));
+                    }
+                }
+                Token(token, position) if Combinator::from_token(&token).is_some() => {
+                    let combinator = Combinator::from_token(&token).unwrap();
                     if let Some(ref mut selector_position) = selector.position {
                         *selector_position = selector_position.union(&position);
                     } else {
                         return Err(ParseError {
-                            reason: "Expected selector start, found '>'".to_owned(),
+                            reason: format!("Expected selector start, found '{}'", token),
                             position,
                         });
                     }
-                    selector.child = Some(Box::new(child));
+                    let next = Self::from_reader(reader)?;
+                    selector.next = Some((combinator, Box::new(next)));
                     break;
                 }
                 Token(token, position) => {
@@ -132,6 +212,16 @@ impl ASTNode for Selector {
             buf.add_mapping(pos);
         }
 
+        if let Some(combinator) = self.leading_combinator.and_then(|c| c.as_str()) {
+            buf.push_str(combinator);
+            if !settings.minify {
+                buf.push(' ');
+            }
+        }
+        if let Some(suffix) = &self.parent {
+            buf.push('&');
+            buf.push_str(suffix);
+        }
         if let Some(name) = &self.tag_name {
             buf.push_str(name);
         }
@@ -145,18 +235,35 @@ impl ASTNode for Selector {
                 buf.push_str(class_name);
             }
         }
-        if let Some(descendant) = &self.descendant {
-            buf.push(' ');
-            descendant.to_string_from_buffer(buf, settings, depth);
-        } else if let Some(child) = &self.child {
-            if !settings.minify {
-                buf.push(' ');
+        if let Some(placeholders) = &self.placeholders {
+            for placeholder in placeholders.iter() {
+                buf.push('%');
+                buf.push_str(placeholder);
             }
-            buf.push('>');
-            if !settings.minify {
+        }
+        if let Some(attributes) = &self.attributes {
+            for attribute in attributes.iter() {
+                attribute.to_string_from_buffer(buf, settings);
+            }
+        }
+        if let Some(pseudos) = &self.pseudos {
+            for pseudo in pseudos.iter() {
+                pseudo.to_string_from_buffer(buf, settings, depth);
+            }
+        }
+        if let Some((combinator, next)) = &self.next {
+            if let Some(combinator) = combinator.as_str() {
+                if !settings.minify {
+                    buf.push(' ');
+                }
+                buf.push_str(combinator);
+                if !settings.minify {
+                    buf.push(' ');
+                }
+            } else {
                 buf.push(' ');
             }
-            child.to_string_from_buffer(buf, settings, depth);
+            next.to_string_from_buffer(buf, settings, depth);
         }
     }
 
@@ -165,24 +272,975 @@ impl ASTNode for Selector {
     }
 }
 
+/// How two compound selectors relate
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Combinator {
+    /// `div h1`
+    Descendant,
+    /// `div > h1`
+    Child,
+    /// `h1 + p`
+    NextSibling,
+    /// `h1 ~ p`
+    SubsequentSibling,
+    /// `col || td`
+    Column,
+}
+
+impl Combinator {
+    fn from_token(token: &CSSToken) -> Option<Self> {
+        match token {
+            CSSToken::CloseAngle => Some(Combinator::Child),
+            CSSToken::Plus => Some(Combinator::NextSibling),
+            CSSToken::Tilde => Some(Combinator::SubsequentSibling),
+            CSSToken::Column => Some(Combinator::Column),
+            _ => None,
+        }
+    }
+
+    /// `None` for descendant which is represented by whitespace
+    fn as_str(&self) -> Option<&'static str> {
+        match self {
+            Combinator::Descendant => None,
+            Combinator::Child => Some(">"),
+            Combinator::NextSibling => Some("+"),
+            Combinator::SubsequentSibling => Some("~"),
+            Combinator::Column => Some("||"),
+        }
+    }
+}
+
+/// [A attribute selector](https://developer.mozilla.org/en-US/docs/Web/CSS/Attribute_selectors)
+/// e.g. `[disabled]`, `[href^="https" i]`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AttributeSelector {
+    pub name: String,
+    /// `None` if only testing for presence of the attribute
+    pub matcher: Option<AttributeMatcher>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AttributeMatcher {
+    pub operator: AttributeOperator,
+    pub value: String,
+    /// The `i` or `s` flag
+    pub case_sensitivity: Option<CaseSensitivity>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AttributeOperator {
+    /// `=`
+    Equal,
+    /// `~=`, value is in whitespace separated list
+    Includes,
+    /// `|=`, value or value followed by `-`
+    DashMatch,
+    /// `^=`
+    Prefix,
+    /// `$=`
+    Suffix,
+    /// `*=`
+    Substring,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CaseSensitivity {
+    /// `i`
+    Insensitive,
+    /// `s`
+    Sensitive,
+}
+
+impl AttributeSelector {
+    /// Parses after the `[` up to and including the `]`. Returns position of `]`
+    fn from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<(Self, Span), ParseError> {
+        let (name, _) = token_as_ident(reader.next().unwrap())?;
+        let operator = match reader.next().unwrap() {
+            Token(CSSToken::CloseSquareBracket, end_span) => {
+                return Ok((
+                    Self {
+                        name,
+                        matcher: None,
+                    },
+                    end_span,
+                ));
+            }
+            Token(CSSToken::Equal, _) => AttributeOperator::Equal,
+            Token(CSSToken::IncludesMatch, _) => AttributeOperator::Includes,
+            Token(CSSToken::DashMatch, _) => AttributeOperator::DashMatch,
+            Token(CSSToken::PrefixMatch, _) => AttributeOperator::Prefix,
+            Token(CSSToken::SuffixMatch, _) => AttributeOperator::Suffix,
+            Token(CSSToken::SubstringMatch, _) => AttributeOperator::Substring,
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected attribute operator or ']' found '{:?}'", token),
+                    position,
+                });
+            }
+        };
+        let value = match reader.next().unwrap() {
+            Token(CSSToken::Ident(value), _) | Token(CSSToken::String(value), _) => value,
+            Token(token, position) => {
+                return Err(ParseError {
+                    reason: format!("Expected attribute value found '{:?}'", token),
+                    position,
+                });
+            }
+        };
+        let case_sensitivity = match reader.peek() {
+            Some(Token(CSSToken::Ident(flag), position)) => {
+                let case_sensitivity = match flag.as_str() {
+                    "i" | "I" => CaseSensitivity::Insensitive,
+                    "s" | "S" => CaseSensitivity::Sensitive,
+                    flag => {
+                        return Err(ParseError {
+                            reason: format!("Unknown attribute selector flag '{}'", flag),
+                            position: position.clone(),
+                        });
+                    }
+                };
+                reader.next();
+                Some(case_sensitivity)
+            }
+            _ => None,
+        };
+        let end_span = reader.expect_next(CSSToken::CloseSquareBracket)?;
+        Ok((
+            Self {
+                name,
+                matcher: Some(AttributeMatcher {
+                    operator,
+                    value,
+                    case_sensitivity,
+                }),
+            },
+            end_span,
+        ))
+    }
+
+    /// Values are always quoted, unless minifying and the value is a valid identifier
+    fn to_string_from_buffer(&self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        buf.push('[');
+        buf.push_str(&self.name);
+        if let Some(AttributeMatcher {
+            operator,
+            value,
+            case_sensitivity,
+        }) = &self.matcher
+        {
+            buf.push_str(match operator {
+                AttributeOperator::Equal => "=",
+                AttributeOperator::Includes => "~=",
+                AttributeOperator::DashMatch => "|=",
+                AttributeOperator::Prefix => "^=",
+                AttributeOperator::Suffix => "$=",
+                AttributeOperator::Substring => "*=",
+            });
+            if settings.minify && is_valid_identifier(value) {
+                buf.push_str(value);
+            } else {
+                buf.push('"');
+                buf.push_str(value);
+                buf.push('"');
+            }
+            match case_sensitivity {
+                Some(CaseSensitivity::Insensitive) => buf.push_str(" i"),
+                Some(CaseSensitivity::Sensitive) => buf.push_str(" s"),
+                None => {}
+            }
+        }
+        buf.push(']');
+    }
+}
+
+/// A pseudo class or pseudo element. These are kept in one list as the order matters e.g.
+/// `::-webkit-scrollbar-thumb:hover`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Pseudo {
+    Class(PseudoClass),
+    Element(PseudoElement),
+}
+
+/// A [pseudo class](https://developer.mozilla.org/en-US/docs/Web/CSS/Pseudo-classes) e.g. `:hover`, `:not(.x)`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PseudoClass {
+    /// Non functional pseudo class e.g. `:hover`, `:first-child`
+    Named(String),
+    Not(Vec<Selector>),
+    Is(Vec<Selector>),
+    Where(Vec<Selector>),
+    /// `:has(> img, .a)`. Selectors are relative to the subject so may start with a combinator
+    Has(Vec<Selector>),
+    /// `:nth-child(An+B of S)`
+    NthChild(AnPlusB, Option<Vec<Selector>>),
+    /// `:nth-last-child(An+B of S)`
+    NthLastChild(AnPlusB, Option<Vec<Selector>>),
+    NthOfType(AnPlusB),
+    NthLastOfType(AnPlusB),
+    /// Other functional pseudo classes e.g. `:lang(en)`. Arguments are kept as source
+    Function {
+        name: String,
+        arguments: String,
+    },
+}
+
+/// A [pseudo element](https://developer.mozilla.org/en-US/docs/Web/CSS/Pseudo-elements) e.g. `::before`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PseudoElement {
+    Named(String),
+    /// e.g. `::part(label)`. Arguments are kept as source
+    Function {
+        name: String,
+        arguments: String,
+    },
+}
+
+/// A `An+B` expression, matches every `a`th element offset by `b` e.g. `2n+1`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AnPlusB {
+    pub a: i32,
+    pub b: i32,
+}
+
+impl Pseudo {
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        match self {
+            Pseudo::Class(pseudo_class) => pseudo_class.to_string_from_buffer(buf, settings, depth),
+            Pseudo::Element(pseudo_element) => pseudo_element.to_string_from_buffer(buf),
+        }
+    }
+}
+
+impl PseudoClass {
+    /// Parses after the `:`. Returns position of the last token
+    fn from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<(Self, Span), ParseError> {
+        let (name, name_span) = token_as_ident(reader.next().unwrap())?;
+        if !is_function_call(reader, &name_span) {
+            return Ok((PseudoClass::Named(name), name_span));
+        }
+        reader.next();
+        let pseudo_class = match name.to_ascii_lowercase().as_str() {
+            "not" => PseudoClass::Not(selector_list_from_reader(reader)?),
+            "is" => PseudoClass::Is(selector_list_from_reader(reader)?),
+            "where" => PseudoClass::Where(selector_list_from_reader(reader)?),
+            "has" => PseudoClass::Has(relative_selector_list_from_reader(reader)?),
+            "nth-child" | "nth-last-child" => {
+                let an_plus_b = AnPlusB::from_reader(reader)?;
+                let of = if let Some(Token(CSSToken::Ident(of), _)) = reader.peek() {
+                    if !of.eq_ignore_ascii_case("of") {
+                        let Token(token, position) = reader.next().unwrap();
+                        return Err(ParseError {
+                            reason: format!("Expected 'of' or ')' found '{:?}'", token),
+                            position,
+                        });
+                    }
+                    reader.next();
+                    Some(selector_list_from_reader(reader)?)
+                } else {
+                    None
+                };
+                if name.eq_ignore_ascii_case("nth-child") {
+                    PseudoClass::NthChild(an_plus_b, of)
+                } else {
+                    PseudoClass::NthLastChild(an_plus_b, of)
+                }
+            }
+            "nth-of-type" => PseudoClass::NthOfType(AnPlusB::from_reader(reader)?),
+            "nth-last-of-type" => PseudoClass::NthLastOfType(AnPlusB::from_reader(reader)?),
+            _ => {
+                let (arguments, end_span) = arguments_from_reader(reader)?;
+                return Ok((PseudoClass::Function { name, arguments }, end_span));
+            }
+        };
+        let end_span = reader.expect_next(CSSToken::CloseBracket)?;
+        Ok((pseudo_class, end_span))
+    }
+
+    /// Selectors in the arguments e.g. `:not(.a, .b)`
+    fn selectors_mut(&mut self) -> Option<&mut Vec<Selector>> {
+        match self {
+            PseudoClass::Not(selectors)
+            | PseudoClass::Is(selectors)
+            | PseudoClass::Where(selectors)
+            | PseudoClass::Has(selectors)
+            | PseudoClass::NthChild(_, Some(selectors))
+            | PseudoClass::NthLastChild(_, Some(selectors)) => Some(selectors),
+            _ => None,
+        }
+    }
+
+    fn contains_parent(&self) -> bool {
+        match self {
+            PseudoClass::Not(selectors)
+            | PseudoClass::Is(selectors)
+            | PseudoClass::Where(selectors)
+            | PseudoClass::Has(selectors)
+            | PseudoClass::NthChild(_, Some(selectors))
+            | PseudoClass::NthLastChild(_, Some(selectors)) => {
+                selectors.iter().any(Selector::contains_parent)
+            }
+            _ => false,
+        }
+    }
+
+    fn replace_parent(&mut self, parent: &Selector) -> Result<(), ParseError> {
+        for selector in self.selectors_mut().into_iter().flatten() {
+            if selector.contains_parent() {
+                *selector = selector.clone().replace_parent(parent)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn to_string_from_buffer(
+        &self,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        buf.push(':');
+        let (name, selectors) = match self {
+            PseudoClass::Named(name) => {
+                buf.push_str(name);
+                return;
+            }
+            PseudoClass::Function { name, arguments } => {
+                buf.push_str(name);
+                buf.push('(');
+                buf.push_str(arguments);
+                buf.push(')');
+                return;
+            }
+            PseudoClass::Not(selectors) => ("not", selectors),
+            PseudoClass::Is(selectors) => ("is", selectors),
+            PseudoClass::Where(selectors) => ("where", selectors),
+            PseudoClass::Has(selectors) => ("has", selectors),
+            PseudoClass::NthChild(an_plus_b, of) | PseudoClass::NthLastChild(an_plus_b, of) => {
+                buf.push_str(if matches!(self, PseudoClass::NthChild(..)) {
+                    "nth-child("
+                } else {
+                    "nth-last-child("
+                });
+                an_plus_b.to_string_from_buffer(buf, settings);
+                if let Some(selectors) = of {
+                    buf.push_str(" of ");
+                    selector_list_to_string_from_buffer(selectors, buf, settings, depth);
+                }
+                buf.push(')');
+                return;
+            }
+            PseudoClass::NthOfType(an_plus_b) | PseudoClass::NthLastOfType(an_plus_b) => {
+                buf.push_str(if matches!(self, PseudoClass::NthOfType(..)) {
+                    "nth-of-type("
+                } else {
+                    "nth-last-of-type("
+                });
+                an_plus_b.to_string_from_buffer(buf, settings);
+                buf.push(')');
+                return;
+            }
+        };
+        buf.push_str(name);
+        buf.push('(');
+        selector_list_to_string_from_buffer(selectors, buf, settings, depth);
+        buf.push(')');
+    }
+}
+
+impl PseudoElement {
+    /// Parses after the `::`. Returns position of the last token
+    fn from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<(Self, Span), ParseError> {
+        let (name, name_span) = token_as_ident(reader.next().unwrap())?;
+        if !is_function_call(reader, &name_span) {
+            return Ok((PseudoElement::Named(name), name_span));
+        }
+        reader.next();
+        let (arguments, end_span) = arguments_from_reader(reader)?;
+        Ok((PseudoElement::Function { name, arguments }, end_span))
+    }
+
+    fn to_string_from_buffer(&self, buf: &mut impl ToString) {
+        buf.push_str("::");
+        match self {
+            PseudoElement::Named(name) => buf.push_str(name),
+            PseudoElement::Function { name, arguments } => {
+                buf.push_str(name);
+                buf.push('(');
+                buf.push_str(arguments);
+                buf.push(')');
+            }
+        }
+    }
+}
+
+impl AnPlusB {
+    /// Parses `An+B` from the string representation without whitespace. Also accepts `odd` and `even`
+    pub fn from_expression(expression: &str) -> Option<Self> {
+        let expression = expression.to_ascii_lowercase();
+        match expression.as_str() {
+            "odd" => return Some(Self { a: 2, b: 1 }),
+            "even" => return Some(Self { a: 2, b: 0 }),
+            _ => {}
+        }
+        if let Some((a, b)) = expression.split_once('n') {
+            let a = match a {
+                "" | "+" => 1,
+                "-" => -1,
+                a => a.parse().ok()?,
+            };
+            let b = match b.chars().next() {
+                None => 0,
+                Some('+' | '-') => b.parse().ok()?,
+                Some(_) => return None,
+            };
+            Some(Self { a, b })
+        } else {
+            Some(Self {
+                a: 0,
+                b: expression.parse().ok()?,
+            })
+        }
+    }
+
+    /// Parses up to the closing `)` or `of`
+    fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let mut expression = String::new();
+        let mut position: Option<Span> = None;
+        loop {
+            match reader.peek().unwrap() {
+                Token(CSSToken::CloseBracket, _) => break,
+                Token(CSSToken::Ident(ident), _) if ident.eq_ignore_ascii_case("of") => break,
+                Token(CSSToken::OpenCurly | CSSToken::SemiColon | CSSToken::EOS, _) => {
+                    let Token(token, position) = reader.next().unwrap();
+                    return Err(ParseError {
+                        reason: format!("Expected ')' found '{:?}'", token),
+                        position,
+                    });
+                }
+                _ => {
+                    let Token(token, token_position) = reader.next().unwrap();
+                    expression.push_str(&token.to_string());
+                    position = Some(match position {
+                        Some(position) => position.union(&token_position),
+                        None => token_position,
+                    });
+                }
+            }
+        }
+        match (Self::from_expression(&expression), position) {
+            (Some(an_plus_b), _) => Ok(an_plus_b),
+            (None, Some(position)) => Err(ParseError {
+                reason: format!("Invalid An+B expression '{}'", expression),
+                position,
+            }),
+            (None, None) => {
+                let Token(_, position) = reader.next().unwrap();
+                Err(ParseError {
+                    reason: "Expected An+B expression".to_owned(),
+                    position,
+                })
+            }
+        }
+    }
+
+    /// Prints the shortest form. `odd` when minifying
+    fn to_string_from_buffer(self, buf: &mut impl ToString, settings: &ToStringSettings) {
+        match (self.a, self.b) {
+            (0, b) => buf.push_str(&b.to_string()),
+            (2, 1) if settings.minify => buf.push_str("odd"),
+            (a, b) => {
+                match a {
+                    1 => {}
+                    -1 => buf.push('-'),
+                    a => buf.push_str(&a.to_string()),
+                }
+                buf.push('n');
+                if b > 0 {
+                    buf.push('+');
+                }
+                if b != 0 {
+                    buf.push_str(&b.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// Whether the next token is a `(` directly after the name
+fn is_function_call(reader: &mut impl TokenReader<CSSToken, Span>, name_span: &Span) -> bool {
+    matches!(reader.peek(), Some(Token(CSSToken::OpenBracket, position)) if name_span.is_adjacent_to(position))
+}
+
+/// Parses comma separated selectors
+pub(crate) fn selector_list_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<Vec<Selector>, ParseError> {
+    let mut selectors = Vec::new();
+    loop {
+        let selector = Selector::from_reader(reader)?;
+        if selector.position.is_none() {
+            let Token(token, position) = reader.next().unwrap();
+            return Err(ParseError {
+                reason: format!("Expected selector found '{:?}'", token),
+                position,
+            });
+        }
+        selectors.push(selector);
+        if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+            reader.next();
+        } else {
+            return Ok(selectors);
+        }
+    }
+}
+
+/// Parses comma separated selectors which may start with a combinator e.g. `> img, + p`
+pub(crate) fn relative_selector_list_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<Vec<Selector>, ParseError> {
+    let mut selectors = Vec::new();
+    loop {
+        let leading_combinator = Combinator::from_token(&reader.peek().unwrap().0);
+        let start_span = leading_combinator.map(|_| reader.next().unwrap().1);
+        let mut selector = Selector::from_reader(reader)?;
+        if selector.position.is_none() {
+            let Token(token, position) = reader.next().unwrap();
+            return Err(ParseError {
+                reason: format!("Expected selector found '{:?}'", token),
+                position,
+            });
+        }
+        selector.leading_combinator = leading_combinator;
+        if let (Some(start_span), Some(position)) = (start_span, &mut selector.position) {
+            *position = start_span.union(position);
+        }
+        selectors.push(selector);
+        if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+            reader.next();
+        } else {
+            return Ok(selectors);
+        }
+    }
+}
+
+pub(crate) fn selector_list_to_string_from_buffer(
+    selectors: &[Selector],
+    buf: &mut impl ToString,
+    settings: &ToStringSettings,
+    depth: u8,
+) {
+    for (idx, selector) in selectors.iter().enumerate() {
+        selector.to_string_from_buffer(buf, settings, depth);
+        if idx + 1 < selectors.len() {
+            buf.push(',');
+            if !settings.minify {
+                buf.push(' ');
+            }
+        }
+    }
+}
+
+/// Collects tokens up to and including the matching `)`. Returns the arguments and the position of the `)`
+fn arguments_from_reader(
+    reader: &mut impl TokenReader<CSSToken, Span>,
+) -> Result<(String, Span), ParseError> {
+    let mut arguments = String::new();
+    let mut last_span: Option<Span> = None;
+    let mut depth = 0u32;
+    loop {
+        let Token(token, position) = reader.next().unwrap();
+        match token {
+            CSSToken::CloseBracket if depth == 0 => return Ok((arguments, position)),
+            CSSToken::CloseBracket => depth -= 1,
+            CSSToken::OpenBracket => depth += 1,
+            CSSToken::OpenCurly | CSSToken::CloseCurly | CSSToken::SemiColon | CSSToken::EOS => {
+                return Err(ParseError {
+                    reason: format!("Expected ')' found '{:?}'", token),
+                    position,
+                });
+            }
+            _ => {}
+        }
+        if matches!(last_span, Some(ref last_span) if !last_span.is_adjacent_to(&position)) {
+            arguments.push(' ');
+        }
+        arguments.push_str(&token.to_string());
+        last_span = Some(position);
+    }
+}
+
+fn is_valid_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+    let first_is_valid = match chars.next() {
+        Some('-') => matches!(chars.clone().next(), Some(chr) if !chr.is_ascii_digit()),
+        Some(chr) => chr.is_ascii_alphabetic() || chr == '_',
+        None => false,
+    };
+    first_is_valid && chars.all(|chr| chr.is_ascii_alphanumeric() || chr == '-' || chr == '_')
+}
+
 impl Selector {
-    /// Returns other nested under self
-    pub fn nest_selector(&self, other: Self) -> Self {
+    /// Returns other nested under self. Any `&` in other is replaced with self, otherwise other
+    /// is a descendant of self or related by its leading combinator e.g. `> b`. Errors if a `&`
+    /// has a suffix which cannot be added to self e.g. `&__title` under `a:hover`
+    pub fn nest_selector(&self, mut other: Self) -> Result<Self, ParseError> {
+        if other.contains_parent() {
+            return other.replace_parent(self);
+        }
+        let combinator = other
+            .leading_combinator
+            .take()
+            .unwrap_or(Combinator::Descendant);
         let mut new_selector = self.clone();
-        // Walk down the new selector descendant and child branches until at end. Then set descendant value
+        // Walk down the new selector compound selectors until at end. Then set descendant value
         // on the tail. Uses raw pointers & unsafe due to issues with Rust borrow checker
         let mut tail: *mut Selector = &mut new_selector;
         loop {
             let cur = unsafe { &mut *tail };
-            if let Some(child) = cur.descendant.as_mut().or(cur.child.as_mut()) {
-                tail = &mut **child;
+            if let Some((_, next)) = cur.next.as_mut() {
+                tail = &mut **next;
             } else {
-                cur.descendant = Some(Box::new(other));
+                cur.next = Some((combinator, Box::new(other)));
                 break;
             }
         }
-        new_selector
+        Ok(new_selector)
+    }
+
+    /// Whether any of the compound selectors or selectors in pseudo classes has a `&`
+    fn contains_parent(&self) -> bool {
+        self.parent.is_some()
+            || self.pseudos.iter().flatten().any(
+                |pseudo| matches!(pseudo, Pseudo::Class(pseudo_class) if pseudo_class.contains_parent()),
+            )
+            || self
+                .next
+                .as_ref()
+                .is_some_and(|(_, next)| next.contains_parent())
+    }
+
+    /// Whether a `&` has a suffix e.g. `&__title`, which CSS Nesting cannot express
+    pub(crate) fn has_parent_suffix(&self) -> bool {
+        self.parent
+            .as_ref()
+            .is_some_and(|suffix| !suffix.is_empty())
+            || self
+                .next
+                .as_ref()
+                .is_some_and(|(_, next)| next.has_parent_suffix())
+    }
+
+    /// For CSS Nesting, adds a `&` before a nested selector starting with a tag name e.g. `a` to
+    /// `& a`. Nested selectors must not start with an identifier. Other selectors without a `&`
+    /// are already relative to the parent
+    pub(crate) fn with_nesting_selector(self) -> Self {
+        if self.tag_name.is_none() || self.leading_combinator.is_some() || self.contains_parent() {
+            return self;
+        }
+        Self {
+            parent: Some(String::new()),
+            leading_combinator: None,
+            tag_name: None,
+            identifier: None,
+            class_names: None,
+            placeholders: None,
+            attributes: None,
+            pseudos: None,
+            position: self.position.clone(),
+            next: Some((Combinator::Descendant, Box::new(self))),
+        }
+    }
+
+    /// Replaces each `&` with `parent`. The parts following a `&` join the last compound selector
+    /// of `parent` (`&:hover`) and a suffix is added to its last class name, placeholder, id or
+    /// tag name (`&__title`)
+    fn replace_parent(self, parent: &Self) -> Result<Self, ParseError> {
+        let position = self.position.clone();
+        let mut compounds = Vec::new();
+        for (mut compound, combinator) in self.into_compounds() {
+            for pseudo in compound.pseudos.iter_mut().flatten() {
+                if let Pseudo::Class(pseudo_class) = pseudo {
+                    pseudo_class.replace_parent(parent)?;
+                }
+            }
+            match compound.parent.take() {
+                Some(suffix) => {
+                    let mut parent_compounds = parent.clone().into_compounds();
+                    let (mut last, _) = parent_compounds.pop().unwrap();
+                    if !last.add_suffix(&suffix) {
+                        return Err(ParseError {
+                            reason: format!(
+                                "Selector \"{}\" can't have a suffix",
+                                parent.to_string(&ToStringSettings::minified())
+                            ),
+                            position: position.or_else(|| parent.position.clone()).unwrap_or(
+                                Span {
+                                    start: 0,
+                                    end: 0,
+                                    source_id: SourceId::null(),
+                                },
+                            ),
+                        });
+                    }
+                    compounds.extend(parent_compounds);
+                    compounds.push((last.join(compound), combinator));
+                }
+                None => compounds.push((compound, combinator)),
+            }
+        }
+        Ok(Self::from_compounds(compounds))
     }
+
+    /// Adds the suffix to the end of the compound selector, which must be a class name,
+    /// placeholder, id or tag name. Returns whether it was added
+    fn add_suffix(&mut self, suffix: &str) -> bool {
+        if suffix.is_empty() {
+            return true;
+        }
+        // Attributes and pseudos follow the names of a compound selector
+        if self.attributes.is_some() || self.pseudos.is_some() {
+            return false;
+        }
+        let last = self
+            .placeholders
+            .as_mut()
+            .and_then(|placeholders| placeholders.last_mut())
+            .or(self
+                .class_names
+                .as_mut()
+                .and_then(|class_names| class_names.last_mut()))
+            .or(self.identifier.as_mut())
+            .or(self.tag_name.as_mut().filter(|tag_name| *tag_name != "*"));
+        match last {
+            Some(last) => {
+                last.push_str(suffix);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds the parts of the compound `other` to this compound selector. Unlike
+    /// [`Self::unify`] parts are not deduplicated and `other` takes precedence on conflicts
+    fn join(mut self, other: Self) -> Self {
+        fn append<T>(parts: &mut Option<Vec<T>>, other: Option<Vec<T>>) {
+            if let Some(other) = other {
+                parts.get_or_insert_with(Vec::new).extend(other);
+            }
+        }
+        self.tag_name = other.tag_name.or(self.tag_name);
+        self.identifier = other.identifier.or(self.identifier);
+        append(&mut self.class_names, other.class_names);
+        append(&mut self.placeholders, other.placeholders);
+        append(&mut self.attributes, other.attributes);
+        append(&mut self.pseudos, other.pseudos);
+        self.position = other.position.or(self.position);
+        self
+    }
+
+    /// Whether any of the compound selectors has a `%placeholder`
+    pub(crate) fn contains_placeholder(&self) -> bool {
+        self.placeholders.is_some()
+            || self
+                .next
+                .as_ref()
+                .is_some_and(|(_, next)| next.contains_placeholder())
+    }
+
+    /// The class name if the selector is only a class e.g. `.bordered`
+    pub(crate) fn as_class_name(&self) -> Option<&str> {
+        match self {
+            Selector {
+                parent: None,
+                leading_combinator: None,
+                tag_name: None,
+                identifier: None,
+                class_names: Some(class_names),
+                placeholders: None,
+                attributes: None,
+                pseudos: None,
+                next: None,
+                position: _,
+            } if class_names.len() == 1 => Some(&class_names[0]),
+            _ => None,
+        }
+    }
+
+    /// Whether the selector has no combinators
+    pub(crate) fn is_compound(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Whether any compound selector matches the compound `target`
+    pub(crate) fn has_compound(&self, target: &Selector) -> bool {
+        self.contains_compound(target)
+            || self
+                .next
+                .as_ref()
+                .is_some_and(|(_, next)| next.has_compound(target))
+    }
+
+    /// For `@extend`. Replaces the first compound selector which matches the compound `target`
+    /// with `extender`, keeping the parts of the compound not in `target` (`.b:hover` extended by
+    /// `.a` gives `.a:hover`). `None` if not matched or the parts conflict (e.g. two tag names)
+    pub(crate) fn extend(&self, target: &Selector, extender: &Selector) -> Option<Self> {
+        let mut compounds = self.clone().into_compounds();
+        let index = compounds
+            .iter()
+            .position(|(compound, _)| compound.contains_compound(target))?;
+        let (compound, combinator) = compounds.remove(index);
+        let mut extender_compounds = extender.clone().into_compounds();
+        let (last, _) = extender_compounds.pop().unwrap();
+        extender_compounds.push((last.unify(compound.without_compound(target))?, combinator));
+        compounds.splice(index..index, extender_compounds);
+        Some(Self::from_compounds(compounds))
+    }
+
+    /// Splits into compound selectors with the combinator to the next
+    fn into_compounds(mut self) -> Vec<(Self, Option<Combinator>)> {
+        let mut compounds = Vec::new();
+        while let Some((combinator, next)) = self.next.take() {
+            compounds.push((self, Some(combinator)));
+            self = *next;
+        }
+        compounds.push((self, None));
+        compounds
+    }
+
+    fn from_compounds(compounds: Vec<(Self, Option<Combinator>)>) -> Self {
+        let mut compounds = compounds.into_iter().rev();
+        let (mut selector, _) = compounds.next().unwrap();
+        for (mut compound, combinator) in compounds {
+            compound.next = Some((combinator.unwrap(), Box::new(selector)));
+            selector = compound;
+        }
+        selector
+    }
+
+    /// Whether this compound selector has all the parts of the compound `other`
+    fn contains_compound(&self, other: &Self) -> bool {
+        fn contains<T>(
+            parts: &Option<Vec<T>>,
+            other: &Option<Vec<T>>,
+            eq: fn(&T, &T) -> bool,
+        ) -> bool {
+            other
+                .iter()
+                .flatten()
+                .all(|other| parts.iter().flatten().any(|part| eq(part, other)))
+        }
+        (other.tag_name.is_none() || self.tag_name == other.tag_name)
+            && (other.identifier.is_none() || self.identifier == other.identifier)
+            && contains(&self.class_names, &other.class_names, PartialEq::eq)
+            && contains(&self.placeholders, &other.placeholders, PartialEq::eq)
+            && contains(&self.attributes, &other.attributes, PartialEq::eq)
+            && contains(&self.pseudos, &other.pseudos, pseudo_eq)
+    }
+
+    /// Removes the parts of the compound `other` from this compound selector
+    fn without_compound(mut self, other: &Self) -> Self {
+        fn remove<T>(parts: &mut Option<Vec<T>>, other: &Option<Vec<T>>, eq: fn(&T, &T) -> bool) {
+            if let Some(items) = parts {
+                items.retain(|part| !other.iter().flatten().any(|other| eq(part, other)));
+                if items.is_empty() {
+                    *parts = None;
+                }
+            }
+        }
+        if other.tag_name.is_some() {
+            self.tag_name = None;
+        }
+        if other.identifier.is_some() {
+            self.identifier = None;
+        }
+        remove(&mut self.class_names, &other.class_names, PartialEq::eq);
+        remove(&mut self.placeholders, &other.placeholders, PartialEq::eq);
+        remove(&mut self.attributes, &other.attributes, PartialEq::eq);
+        remove(&mut self.pseudos, &other.pseudos, pseudo_eq);
+        self
+    }
+
+    /// Combines two compound selectors. `None` if they have conflicting tag names, ids or pseudo
+    /// elements
+    fn unify(mut self, other: Self) -> Option<Self> {
+        fn merge<T>(parts: &mut Option<Vec<T>>, other: Option<Vec<T>>, eq: fn(&T, &T) -> bool) {
+            for other in other.into_iter().flatten() {
+                let items = parts.get_or_insert_with(Vec::new);
+                if !items.iter().any(|part| eq(part, &other)) {
+                    items.push(other);
+                }
+            }
+        }
+        let (elements, other_elements) = (self.pseudo_elements(), other.pseudo_elements());
+        if !elements.is_empty() && !other_elements.is_empty() && elements != other_elements {
+            return None;
+        }
+        self.tag_name = match (self.tag_name, other.tag_name) {
+            (Some(tag_name), Some(other)) if tag_name == "*" => Some(other),
+            (Some(tag_name), Some(other)) if other == "*" || tag_name == other => Some(tag_name),
+            (Some(_), Some(_)) => return None,
+            (tag_name, other) => tag_name.or(other),
+        };
+        self.identifier = match (self.identifier, other.identifier) {
+            (Some(identifier), Some(other)) if identifier != other => return None,
+            (identifier, other) => identifier.or(other),
+        };
+        merge(&mut self.class_names, other.class_names, PartialEq::eq);
+        merge(&mut self.placeholders, other.placeholders, PartialEq::eq);
+        merge(&mut self.attributes, other.attributes, PartialEq::eq);
+        // Pseudo classes from `other` go before pseudo elements e.g. `.a:hover::before`
+        let pseudos = self.pseudos.get_or_insert_with(Vec::new);
+        for pseudo in other.pseudos.into_iter().flatten() {
+            if pseudos.iter().any(|part| pseudo_eq(part, &pseudo)) {
+                continue;
+            }
+            match pseudo {
+                Pseudo::Class(_) => {
+                    let index = pseudos
+                        .iter()
+                        .position(|part| matches!(part, Pseudo::Element(_)))
+                        .unwrap_or(pseudos.len());
+                    pseudos.insert(index, pseudo);
+                }
+                Pseudo::Element(_) => pseudos.push(pseudo),
+            }
+        }
+        if pseudos.is_empty() {
+            self.pseudos = None;
+        }
+        Some(self)
+    }
+
+    fn pseudo_elements(&self) -> Vec<&PseudoElement> {
+        self.pseudos
+            .iter()
+            .flatten()
+            .filter_map(|pseudo| match pseudo {
+                Pseudo::Element(pseudo_element) => Some(pseudo_element),
+                Pseudo::Class(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Compares ignoring positions of selectors in arguments
+fn pseudo_eq(a: &Pseudo, b: &Pseudo) -> bool {
+    let settings = ToStringSettings::minified();
+    let (mut a_string, mut b_string) = (String::new(), String::new());
+    a.to_string_from_buffer(&mut a_string, &settings, 0);
+    b.to_string_from_buffer(&mut b_string, &settings, 0);
+    a_string == b_string
 }
 
 #[cfg(test)]
@@ -245,7 +1303,8 @@ mod selector_tests {
             "Bad selector {:?}",
             selector
         );
-        let descendant_selector = *selector.descendant.unwrap();
+        let (combinator, descendant_selector) = selector.next.unwrap();
+        assert_eq!(combinator, Combinator::Descendant);
         assert_eq!(
             descendant_selector.class_names.as_ref().unwrap()[0],
             "button".to_owned(),
@@ -263,7 +1322,8 @@ mod selector_tests {
             "Bad selector {:?}",
             selector
         );
-        let child_selector = *selector.child.unwrap();
+        let (combinator, child_selector) = selector.next.unwrap();
+        assert_eq!(combinator, Combinator::Child);
         assert_eq!(
             child_selector.tag_name,
             Some("h1".to_owned()),
@@ -271,4 +1331,291 @@ mod selector_tests {
             child_selector
         );
     }
+
+    #[test]
+    fn attributes() {
+        let selector = Selector::from_string(
+            "input[disabled][type=\"text\"][data-state~=open i]".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert_eq!(selector.tag_name, Some("input".to_owned()));
+        let attributes = selector.attributes.unwrap();
+        assert_eq!(
+            attributes,
+            vec![
+                AttributeSelector {
+                    name: "disabled".to_owned(),
+                    matcher: None
+                },
+                AttributeSelector {
+                    name: "type".to_owned(),
+                    matcher: Some(AttributeMatcher {
+                        operator: AttributeOperator::Equal,
+                        value: "text".to_owned(),
+                        case_sensitivity: None
+                    })
+                },
+                AttributeSelector {
+                    name: "data-state".to_owned(),
+                    matcher: Some(AttributeMatcher {
+                        operator: AttributeOperator::Includes,
+                        value: "open".to_owned(),
+                        case_sensitivity: Some(CaseSensitivity::Insensitive)
+                    })
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_to_string() {
+        let selector = Selector::from_string(
+            "a[href^=\"https\"] [lang|=en]".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            selector.to_string(&ToStringSettings::default()),
+            "a[href^=\"https\"] [lang|=\"en\"]"
+        );
+        assert_eq!(
+            selector.to_string(&ToStringSettings::minified()),
+            "a[href^=https] [lang|=en]"
+        );
+        let nested = Selector::from_string(".form".to_owned(), NULL_SOURCE_ID, None)
+            .unwrap()
+            .nest_selector(selector)
+            .unwrap();
+        assert_eq!(
+            nested.to_string(&ToStringSettings::default()),
+            ".form a[href^=\"https\"] [lang|=\"en\"]"
+        );
+    }
+
+    #[test]
+    fn pseudo_classes_and_elements() {
+        let selector =
+            Selector::from_string("a:hover::before".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(
+            selector.pseudos,
+            Some(vec![
+                Pseudo::Class(PseudoClass::Named("hover".to_owned())),
+                Pseudo::Element(PseudoElement::Named("before".to_owned()))
+            ])
+        );
+
+        let selector = Selector::from_string(
+            "li:not(.active, #first):nth-child(-n+3 of .item)".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        let pseudos = selector.pseudos.unwrap();
+        if let Pseudo::Class(PseudoClass::Not(selectors)) = &pseudos[0] {
+            assert_eq!(selectors.len(), 2);
+            assert_eq!(selectors[1].identifier, Some("first".to_owned()));
+        } else {
+            panic!("Expected :not, found {:?}", pseudos[0]);
+        }
+        if let Pseudo::Class(PseudoClass::NthChild(an_plus_b, Some(of))) = &pseudos[1] {
+            assert_eq!(an_plus_b, &AnPlusB { a: -1, b: 3 });
+            assert_eq!(of[0].class_names, Some(vec!["item".to_owned()]));
+        } else {
+            panic!("Expected :nth-child, found {:?}", pseudos[1]);
+        }
+    }
+
+    #[test]
+    fn an_plus_b() {
+        let cases = [
+            ("odd", 2, 1),
+            ("even", 2, 0),
+            ("2n+1", 2, 1),
+            ("n", 1, 0),
+            ("-n+6", -1, 6),
+            ("3n-2", 3, -2),
+            ("+5", 0, 5),
+            ("-2", 0, -2),
+        ];
+        for (expression, a, b) in cases {
+            assert_eq!(
+                AnPlusB::from_expression(expression),
+                Some(AnPlusB { a, b }),
+                "{}",
+                expression
+            );
+        }
+        assert_eq!(AnPlusB::from_expression("2n1"), None);
+        assert_eq!(AnPlusB::from_expression("x"), None);
+        assert!(
+            Selector::from_string("li:nth-child(3x)".to_owned(), NULL_SOURCE_ID, None).is_err()
+        );
+    }
+
+    #[test]
+    fn pseudo_to_string() {
+        let source = "ul > li:nth-child(2n+1):is(.a, .b) p::first-line";
+        let selector = Selector::from_string(source.to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(
+            selector.to_string(&ToStringSettings::default()),
+            "ul > li:nth-child(2n+1):is(.a, .b) p::first-line"
+        );
+        assert_eq!(
+            selector.to_string(&ToStringSettings::minified()),
+            "ul>li:nth-child(odd):is(.a,.b) p::first-line"
+        );
+        let selector = Selector::from_string(
+            ":lang(en)::part(label) :where(a:not(:hover))".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            selector.to_string(&ToStringSettings::default()),
+            ":lang(en)::part(label) :where(a:not(:hover))"
+        );
+        let selector = Selector::from_string(
+            "a:has(> img):has(+ b, ~ c) section:has(.x, || td)".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            selector.to_string(&ToStringSettings::default()),
+            "a:has(> img):has(+ b, ~ c) section:has(.x, || td)"
+        );
+        assert_eq!(
+            selector.to_string(&ToStringSettings::minified()),
+            "a:has(>img):has(+b,~c) section:has(.x,||td)"
+        );
+        if let Some(Pseudo::Class(PseudoClass::Has(selectors))) =
+            selector.pseudos.as_ref().map(|pseudos| &pseudos[0])
+        {
+            assert_eq!(selectors[0].leading_combinator, Some(Combinator::Child));
+            assert_eq!(selectors[0].tag_name, Some("img".to_owned()));
+        } else {
+            panic!("Expected :has, found {:?}", selector.pseudos);
+        }
+        assert!(Selector::from_string("a:has(>)".to_owned(), NULL_SOURCE_ID, None).is_err());
+        // Pseudo classes after a pseudo element keep their position
+        for source in [
+            "::-webkit-scrollbar-thumb:hover",
+            "input::placeholder:focus",
+            "a:hover::before:focus-visible",
+        ] {
+            let selector = Selector::from_string(source.to_owned(), NULL_SOURCE_ID, None).unwrap();
+            assert_eq!(selector.to_string(&ToStringSettings::default()), source);
+        }
+    }
+
+    #[test]
+    fn sibling_combinators() {
+        let selector =
+            Selector::from_string("h1 + p ~ span".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        let (combinator, next) = selector.next.as_ref().unwrap();
+        assert_eq!(combinator, &Combinator::NextSibling);
+        assert_eq!(next.tag_name, Some("p".to_owned()));
+        let (combinator, next) = next.next.as_ref().unwrap();
+        assert_eq!(combinator, &Combinator::SubsequentSibling);
+        assert_eq!(next.tag_name, Some("span".to_owned()));
+
+        let selector =
+            Selector::from_string("col.selected||td".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(selector.next.as_ref().unwrap().0, Combinator::Column);
+
+        // Not a unicode range
+        let selector = Selector::from_string("u+a".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(selector.tag_name, Some("u".to_owned()));
+        let (combinator, next) = selector.next.as_ref().unwrap();
+        assert_eq!(combinator, &Combinator::NextSibling);
+        assert_eq!(next.tag_name, Some("a".to_owned()));
+    }
+
+    #[test]
+    fn combinators_to_string() {
+        let selector =
+            Selector::from_string("main h1+p > a ~ span||td".to_owned(), NULL_SOURCE_ID, None)
+                .unwrap();
+        assert_eq!(
+            selector.to_string(&ToStringSettings::default()),
+            "main h1 + p > a ~ span || td"
+        );
+        assert_eq!(
+            selector.to_string(&ToStringSettings::minified()),
+            "main h1+p>a~span||td"
+        );
+        let nested = Selector::from_string("article ~ aside".to_owned(), NULL_SOURCE_ID, None)
+            .unwrap()
+            .nest_selector(selector)
+            .unwrap();
+        assert_eq!(
+            nested.to_string(&ToStringSettings::minified()),
+            "article~aside main h1+p>a~span||td"
+        );
+        assert!(Selector::from_string("+ p".to_owned(), NULL_SOURCE_ID, None).is_err());
+    }
+
+    #[test]
+    fn placeholders() {
+        let selector =
+            Selector::from_string("a%button.x".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(selector.placeholders, Some(vec!["button".to_owned()]));
+        assert!(selector.contains_placeholder());
+        assert_eq!(
+            selector.to_string(&ToStringSettings::minified()),
+            "a.x%button"
+        );
+    }
+
+    #[test]
+    fn extend() {
+        let parse =
+            |source: &str| Selector::from_string(source.to_owned(), NULL_SOURCE_ID, None).unwrap();
+        let extended = parse("nav .b:hover > p")
+            .extend(&parse(".b"), &parse("main .a"))
+            .unwrap();
+        assert_eq!(
+            extended.to_string(&ToStringSettings::minified()),
+            "nav main .a:hover>p"
+        );
+        assert!(parse("a.b").extend(&parse(".b"), &parse("span")).is_none());
+        assert!(parse(".c").extend(&parse(".b"), &parse(".a")).is_none());
+    }
+
+    #[test]
+    fn parent_selector() {
+        let parse =
+            |source: &str| Selector::from_string(source.to_owned(), NULL_SOURCE_ID, None).unwrap();
+        let nest = |parent: &str, child: &str| {
+            parse(parent)
+                .nest_selector(parse(child))
+                .unwrap()
+                .to_string(&ToStringSettings::minified())
+        };
+        assert_eq!(parse("&__title:hover").parent, Some("__title".to_owned()));
+        assert_eq!(
+            parse(".card &").to_string(&ToStringSettings::minified()),
+            ".card &"
+        );
+        assert_eq!(nest("nav a", "&:hover"), "nav a:hover");
+        assert_eq!(nest(".button", "&.active > span"), ".button.active>span");
+        assert_eq!(nest(".title", ".card &"), ".card .title");
+        assert_eq!(nest("main .card", "&__title"), "main .card__title");
+        assert_eq!(nest("#menu", "&-item"), "#menu-item");
+        assert_eq!(nest("li", "& + &"), "li+li");
+        assert_eq!(nest("a", "p:not(&)"), "p:not(a)");
+        assert_eq!(nest("a", "span"), "a span");
+        assert!(Selector::from_string("a&".to_owned(), NULL_SOURCE_ID, None).is_err());
+
+        for (parent, child) in [("a:hover", "&__x"), ("[x]", "&-y"), ("*", "&-z")] {
+            let error = parse(parent).nest_selector(parse(child)).unwrap_err();
+            assert_eq!(
+                error.reason,
+                format!("Selector \"{}\" can't have a suffix", parent)
+            );
+        }
+    }
 }
@@ -0,0 +1,627 @@
+use super::Syntax;
+use std::ops::Range;
+
+/// A token of the [CSS Syntax Level 3](https://www.w3.org/TR/css-syntax-3/#tokenization)
+/// tokenizer. Values have their escapes replaced e.g. the ident `\31 0` has the value `10`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxToken {
+    /// e.g. `color`, `--main-color`, `café`
+    Ident(String),
+    /// Identifier followed by a `(` e.g. `rgb(`. Holds the name
+    Function(String),
+    /// e.g. `@media`. Holds the name
+    AtKeyword(String),
+    /// e.g. `#header`, `#0f5421`. `is_id` when the value is a valid identifier, which id
+    /// selectors require
+    Hash {
+        value: String,
+        is_id: bool,
+    },
+    /// Quoted with `"` or `'`. Holds the content
+    String(String),
+    /// A string containing an unescaped newline
+    BadString,
+    /// Unquoted url e.g. `url(image.png)`. Holds the url
+    Url(String),
+    /// Unquoted url containing a quote, `(`, whitespace or a non printable character
+    BadUrl,
+    /// A character which is not part of another token e.g. `.`, `>`, `!`
+    Delim(char),
+    /// e.g. `42`, `-1.5`, `+1e3`. `is_integer` when written without a `.` or exponent
+    Number {
+        value: f64,
+        is_integer: bool,
+    },
+    /// e.g. `50%`
+    Percentage(f64),
+    /// Number with a unit e.g. `10px`
+    Dimension {
+        value: f64,
+        is_integer: bool,
+        unit: String,
+    },
+    /// e.g. `U+0025-00FF`, `U+4??`. The inclusive range of code points
+    UnicodeRange {
+        start: u32,
+        end: u32,
+    },
+    Whitespace,
+    /// `<!--`
+    CDO,
+    /// `-->`
+    CDC,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquareBracket,
+    CloseSquareBracket,
+    OpenBracket,
+    CloseBracket,
+    OpenCurly,
+    CloseCurly,
+    /// `/* ... */`. Not a token in the specification, kept so comments can be printed
+    Comment(String),
+}
+
+/// Splits source into [`SyntaxToken`]s and their byte ranges in the source, following the
+/// specification. Never fails, invalid source gives [`SyntaxToken::BadString`],
+/// [`SyntaxToken::BadUrl`] and [`SyntaxToken::Delim`] tokens. In SCSS and Less `//` comments
+/// are skipped
+pub struct Tokenizer<'a> {
+    source: &'a str,
+    position: usize,
+    syntax: Syntax,
+    unicode_ranges_allowed: bool,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(source: &'a str, syntax: Syntax) -> Self {
+        Self {
+            source,
+            position: 0,
+            syntax,
+            unicode_ranges_allowed: false,
+        }
+    }
+
+    /// Whether `U+...` is a [`SyntaxToken::UnicodeRange`]. Only set in declaration values,
+    /// elsewhere `u+a` is an identifier, `+` and identifier
+    pub fn set_unicode_ranges_allowed(&mut self, allowed: bool) {
+        self.unicode_ranges_allowed = allowed;
+    }
+
+    /// Continues tokenizing from a byte offset in the source
+    pub(crate) fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.source[self.position..].chars().nth(offset)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let chr = self.peek(0)?;
+        self.position += chr.len_utf8();
+        Some(chr)
+    }
+
+    fn consume_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek(0).is_some_and(&predicate) {
+            self.advance();
+        }
+    }
+
+    fn consume_token(&mut self, first: char) -> SyntaxToken {
+        let (second, third) = (self.peek(1), self.peek(2));
+        if starts_number(Some(first), second, third) {
+            return self.consume_numeric();
+        }
+        if first == '-' && second == Some('-') && third == Some('>') {
+            self.position += 3;
+            return SyntaxToken::CDC;
+        }
+        if self.unicode_ranges_allowed
+            && matches!(first, 'u' | 'U')
+            && second == Some('+')
+            && third.is_some_and(|chr| chr.is_ascii_hexdigit() || chr == '?')
+        {
+            self.position += 2;
+            return self.consume_unicode_range();
+        }
+        if would_start_ident(Some(first), second, third) {
+            return self.consume_ident_like();
+        }
+        if first == '@' && would_start_ident(second, third, self.peek(3)) {
+            self.advance();
+            return SyntaxToken::AtKeyword(self.consume_name());
+        }
+        if self.source[self.position..].starts_with("<!--") {
+            self.position += 4;
+            return SyntaxToken::CDO;
+        }
+        self.advance();
+        match first {
+            '/' if second == Some('*') => {
+                self.advance();
+                let rest = &self.source[self.position..];
+                let (content, length) = match rest.find("*/") {
+                    Some(end) => (&rest[..end], end + 2),
+                    None => (rest, rest.len()),
+                };
+                self.position += length;
+                SyntaxToken::Comment(content.to_owned())
+            }
+            chr if is_whitespace(chr) => {
+                self.consume_while(is_whitespace);
+                SyntaxToken::Whitespace
+            }
+            '"' | '\'' => self.consume_string(first),
+            '#' if second.is_some_and(is_name) || is_valid_escape(second, third) => {
+                let is_id = would_start_ident(second, third, self.peek(2));
+                let value = self.consume_name();
+                SyntaxToken::Hash { value, is_id }
+            }
+            '(' => SyntaxToken::OpenBracket,
+            ')' => SyntaxToken::CloseBracket,
+            '[' => SyntaxToken::OpenSquareBracket,
+            ']' => SyntaxToken::CloseSquareBracket,
+            '{' => SyntaxToken::OpenCurly,
+            '}' => SyntaxToken::CloseCurly,
+            ':' => SyntaxToken::Colon,
+            ';' => SyntaxToken::Semicolon,
+            ',' => SyntaxToken::Comma,
+            chr => SyntaxToken::Delim(chr),
+        }
+    }
+
+    /// A number, percentage or dimension
+    fn consume_numeric(&mut self) -> SyntaxToken {
+        let (value, is_integer) = self.consume_number();
+        if would_start_ident(self.peek(0), self.peek(1), self.peek(2)) {
+            let unit = self.consume_name();
+            SyntaxToken::Dimension {
+                value,
+                is_integer,
+                unit,
+            }
+        } else if self.peek(0) == Some('%') {
+            self.advance();
+            SyntaxToken::Percentage(value)
+        } else {
+            SyntaxToken::Number { value, is_integer }
+        }
+    }
+
+    fn consume_number(&mut self) -> (f64, bool) {
+        let start = self.position;
+        let mut is_integer = true;
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.advance();
+        }
+        self.consume_while(|chr| chr.is_ascii_digit());
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|chr| chr.is_ascii_digit()) {
+            is_integer = false;
+            self.advance();
+            self.consume_while(|chr| chr.is_ascii_digit());
+        }
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let exponent_length = match (self.peek(1), self.peek(2)) {
+                (Some('+' | '-'), Some(chr)) if chr.is_ascii_digit() => Some(2),
+                (Some(chr), _) if chr.is_ascii_digit() => Some(1),
+                _ => None,
+            };
+            if let Some(length) = exponent_length {
+                is_integer = false;
+                self.position += length;
+                self.consume_while(|chr| chr.is_ascii_digit());
+            }
+        }
+        let value = self.source[start..self.position]
+            .parse()
+            .unwrap_or_default();
+        (value, is_integer)
+    }
+
+    /// After the `U+`
+    fn consume_unicode_range(&mut self) -> SyntaxToken {
+        let start = self.position;
+        self.consume_while(|chr| chr.is_ascii_hexdigit());
+        self.position = self.position.min(start + 6);
+        while self.position < start + 6 && self.peek(0) == Some('?') {
+            self.advance();
+        }
+        let first = &self.source[start..self.position];
+        if first.contains('?') {
+            let start = u32::from_str_radix(&first.replace('?', "0"), 16).unwrap_or_default();
+            let end = u32::from_str_radix(&first.replace('?', "F"), 16).unwrap_or_default();
+            return SyntaxToken::UnicodeRange { start, end };
+        }
+        let start = u32::from_str_radix(first, 16).unwrap_or_default();
+        let mut end = start;
+        if self.peek(0) == Some('-') && self.peek(1).is_some_and(|chr| chr.is_ascii_hexdigit()) {
+            self.advance();
+            let end_start = self.position;
+            self.consume_while(|chr| chr.is_ascii_hexdigit());
+            self.position = self.position.min(end_start + 6);
+            end =
+                u32::from_str_radix(&self.source[end_start..self.position], 16).unwrap_or_default();
+        }
+        SyntaxToken::UnicodeRange { start, end }
+    }
+
+    /// An ident, function or url
+    fn consume_ident_like(&mut self) -> SyntaxToken {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return SyntaxToken::Ident(name);
+        }
+        self.advance();
+        if !name.eq_ignore_ascii_case("url") {
+            return SyntaxToken::Function(name);
+        }
+        while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace) {
+            self.advance();
+        }
+        let is_quote = |chr: Option<char>| matches!(chr, Some('"' | '\''));
+        if is_quote(self.peek(0))
+            || (self.peek(0).is_some_and(is_whitespace) && is_quote(self.peek(1)))
+        {
+            SyntaxToken::Function(name)
+        } else {
+            self.consume_url()
+        }
+    }
+
+    /// After the `url(`
+    fn consume_url(&mut self) -> SyntaxToken {
+        self.consume_while(is_whitespace);
+        let mut url = String::new();
+        loop {
+            match self.advance() {
+                None | Some(')') => return SyntaxToken::Url(url),
+                Some(chr) if is_whitespace(chr) => {
+                    self.consume_while(is_whitespace);
+                    return match self.peek(0) {
+                        None => SyntaxToken::Url(url),
+                        Some(')') => {
+                            self.advance();
+                            SyntaxToken::Url(url)
+                        }
+                        Some(_) => self.consume_bad_url_remnants(),
+                    };
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    url.push(self.consume_escape());
+                }
+                Some(chr) if matches!(chr, '"' | '\'' | '(' | '\\') || is_non_printable(chr) => {
+                    return self.consume_bad_url_remnants();
+                }
+                Some(chr) => url.push(chr),
+            }
+        }
+    }
+
+    /// Skips up to the end of the bad url, so the rest of the source is tokenized as usual
+    fn consume_bad_url_remnants(&mut self) -> SyntaxToken {
+        loop {
+            match self.advance() {
+                None | Some(')') => return SyntaxToken::BadUrl,
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// After the opening quote
+    fn consume_string(&mut self, quote: char) -> SyntaxToken {
+        let mut content = String::new();
+        loop {
+            match self.peek(0) {
+                None => return SyntaxToken::String(content),
+                Some(chr) if chr == quote => {
+                    self.advance();
+                    return SyntaxToken::String(content);
+                }
+                // The newline is not part of the string
+                Some(chr) if is_newline(chr) => return SyntaxToken::BadString,
+                Some('\\') => {
+                    self.advance();
+                    match self.peek(0) {
+                        None => {}
+                        // Escaped newlines continue the string on the next line
+                        Some(chr) if is_newline(chr) => self.consume_newline(),
+                        Some(_) => content.push(self.consume_escape()),
+                    }
+                }
+                Some(chr) => {
+                    self.advance();
+                    content.push(chr);
+                }
+            }
+        }
+    }
+
+    /// After the `\`. Hex escapes e.g. `\31 ` can be followed by a whitespace character, which
+    /// is part of the escape
+    fn consume_escape(&mut self) -> char {
+        match self.advance() {
+            Some(chr) if chr.is_ascii_hexdigit() => {
+                let start = self.position - 1;
+                while self.position - start < 6
+                    && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit())
+                {
+                    self.advance();
+                }
+                let code_point =
+                    u32::from_str_radix(&self.source[start..self.position], 16).unwrap_or_default();
+                if self.peek(0).is_some_and(is_whitespace) {
+                    self.consume_newline();
+                }
+                match char::from_u32(code_point) {
+                    Some(chr) if code_point != 0 => chr,
+                    _ => char::REPLACEMENT_CHARACTER,
+                }
+            }
+            Some(chr) => chr,
+            None => char::REPLACEMENT_CHARACTER,
+        }
+    }
+
+    /// Consumes one whitespace character, treating `\r\n` as one
+    fn consume_newline(&mut self) {
+        if self.advance() == Some('\r') && self.peek(0) == Some('\n') {
+            self.advance();
+        }
+    }
+
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(chr) if is_name(chr) => {
+                    self.advance();
+                    name.push(chr);
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(1)) => {
+                    self.advance();
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = (SyntaxToken, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let first = self.peek(0)?;
+            if first == '/' && self.peek(1) == Some('/') && self.syntax != Syntax::Css {
+                self.consume_while(|chr| !is_newline(chr));
+                continue;
+            }
+            let start = self.position;
+            let token = self.consume_token(first);
+            return Some((token, start..self.position));
+        }
+    }
+}
+
+/// Length of the number at the start of `text` e.g. 4 for `+1.5px`
+pub(crate) fn number_length(text: &str) -> usize {
+    let mut tokenizer = Tokenizer::new(text, Syntax::Css);
+    tokenizer.consume_number();
+    tokenizer.position
+}
+
+fn is_newline(chr: char) -> bool {
+    matches!(chr, '\n' | '\r' | '\x0C')
+}
+
+fn is_whitespace(chr: char) -> bool {
+    is_newline(chr) || matches!(chr, ' ' | '\t')
+}
+
+fn is_name_start(chr: char) -> bool {
+    chr.is_ascii_alphabetic() || chr == '_' || !chr.is_ascii()
+}
+
+fn is_name(chr: char) -> bool {
+    is_name_start(chr) || chr.is_ascii_digit() || chr == '-'
+}
+
+fn is_non_printable(chr: char) -> bool {
+    matches!(chr, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && !second.is_some_and(is_newline)
+}
+
+fn would_start_ident(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => {
+            second.is_some_and(|chr| is_name_start(chr) || chr == '-')
+                || is_valid_escape(second, third)
+        }
+        Some('\\') => is_valid_escape(first, second),
+        Some(chr) => is_name_start(chr),
+        None => false,
+    }
+}
+
+fn starts_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    let is_digit = |chr: Option<char>| chr.is_some_and(|chr| chr.is_ascii_digit());
+    match first {
+        Some('+' | '-') => is_digit(second) || (second == Some('.') && is_digit(third)),
+        Some('.') => is_digit(second),
+        chr => is_digit(chr),
+    }
+}
+
+#[cfg(test)]
+mod tokenizer_tests {
+    use super::*;
+
+    fn tokenize(source: &str) -> Vec<SyntaxToken> {
+        Tokenizer::new(source, Syntax::Css)
+            .map(|(token, _)| token)
+            .filter(|token| *token != SyntaxToken::Whitespace)
+            .collect()
+    }
+
+    #[test]
+    fn identifiers_and_escapes() {
+        assert_eq!(
+            tokenize(".\\31 0 --main-color café \\@media"),
+            vec![
+                SyntaxToken::Delim('.'),
+                SyntaxToken::Ident("10".to_owned()),
+                SyntaxToken::Ident("--main-color".to_owned()),
+                SyntaxToken::Ident("café".to_owned()),
+                SyntaxToken::Ident("@media".to_owned()),
+            ]
+        );
+        assert_eq!(
+            tokenize("#header #0f5421 @font-face rgb("),
+            vec![
+                SyntaxToken::Hash {
+                    value: "header".to_owned(),
+                    is_id: true
+                },
+                SyntaxToken::Hash {
+                    value: "0f5421".to_owned(),
+                    is_id: false
+                },
+                SyntaxToken::AtKeyword("font-face".to_owned()),
+                SyntaxToken::Function("rgb".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            tokenize("+1.5 -2 1e3 10px 50% .5em -.5"),
+            vec![
+                SyntaxToken::Number {
+                    value: 1.5,
+                    is_integer: false
+                },
+                SyntaxToken::Number {
+                    value: -2.0,
+                    is_integer: true
+                },
+                SyntaxToken::Number {
+                    value: 1000.0,
+                    is_integer: false
+                },
+                SyntaxToken::Dimension {
+                    value: 10.0,
+                    is_integer: true,
+                    unit: "px".to_owned()
+                },
+                SyntaxToken::Percentage(50.0),
+                SyntaxToken::Dimension {
+                    value: 0.5,
+                    is_integer: false,
+                    unit: "em".to_owned()
+                },
+                SyntaxToken::Number {
+                    value: -0.5,
+                    is_integer: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_and_urls() {
+        assert_eq!(
+            tokenize("'it\\'s' \"a\nb \"c"),
+            vec![
+                SyntaxToken::String("it's".to_owned()),
+                SyntaxToken::BadString,
+                SyntaxToken::Ident("b".to_owned()),
+                SyntaxToken::String("c".to_owned()),
+            ]
+        );
+        assert_eq!(
+            tokenize("url( a.png ) url(\"b.png\") url(a b) url(c)"),
+            vec![
+                SyntaxToken::Url("a.png".to_owned()),
+                SyntaxToken::Function("url".to_owned()),
+                SyntaxToken::String("b.png".to_owned()),
+                SyntaxToken::CloseBracket,
+                SyntaxToken::BadUrl,
+                SyntaxToken::Url("c".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_ranges_cdo_and_cdc() {
+        let mut tokenizer = Tokenizer::new("<!-- U+0025-00FF u+4?? U+A5 -->", Syntax::Css);
+        tokenizer.set_unicode_ranges_allowed(true);
+        let tokens: Vec<_> = tokenizer
+            .map(|(token, _)| token)
+            .filter(|token| *token != SyntaxToken::Whitespace)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                SyntaxToken::CDO,
+                SyntaxToken::UnicodeRange {
+                    start: 0x25,
+                    end: 0xFF
+                },
+                SyntaxToken::UnicodeRange {
+                    start: 0x400,
+                    end: 0x4FF
+                },
+                SyntaxToken::UnicodeRange {
+                    start: 0xA5,
+                    end: 0xA5
+                },
+                SyntaxToken::CDC,
+            ]
+        );
+        // Outside of `unicode-range` values
+        assert_eq!(
+            tokenize("u+a"),
+            vec![
+                SyntaxToken::Ident("u".to_owned()),
+                SyntaxToken::Delim('+'),
+                SyntaxToken::Ident("a".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let source = "a /* x */ { color: red }";
+        let ranges: Vec<_> = Tokenizer::new(source, Syntax::Css)
+            .map(|(_, range)| &source[range])
+            .collect();
+        assert_eq!(
+            ranges,
+            ["a", " ", "/* x */", " ", "{", " ", "color", ":", " ", "red", " ", "}"]
+        );
+        let tokens: Vec<_> = Tokenizer::new("a // b\nc", Syntax::Scss)
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                SyntaxToken::Ident("a".to_owned()),
+                SyntaxToken::Whitespace,
+                SyntaxToken::Whitespace,
+                SyntaxToken::Ident("c".to_owned()),
+            ]
+        );
+    }
+}
@@ -1,11 +1,18 @@
-use super::{ASTNode, CSSToken, ParseError, Span, ToStringSettings, Token};
+use super::{ASTNode, CSSToken, Interpolation, ParseError, Span, ToStringSettings, Token};
 use source_map::ToString;
+use std::mem;
 use tokenizer_lib::TokenReader;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Number(pub String);
 
-#[derive(Debug, PartialEq, Eq)]
+impl Number {
+    pub fn as_f64(&self) -> Option<f64> {
+        self.0.parse().ok()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum CSSValue {
     Keyword(String),
     Function(String, Vec<CSSValue>),
@@ -16,27 +23,176 @@ pub enum CSSValue {
     Color(String),
     List(Vec<CSSValue>),
     CommaSeparatedList(Vec<CSSValue>),
+    /// List in square brackets e.g. `[a b]` or grid line names `[full-start]`. Holds a `List`,
+    /// `CommaSeparatedList` or a single value
+    Bracketed(Box<CSSValue>),
+    /// SCSS map e.g. `(primary: red, secondary: blue)`. Not valid in CSS output
+    Map(Vec<(CSSValue, CSSValue)>),
+    /// SCSS keyword argument of a function call e.g. `$lightness: -10%` in
+    /// `color.adjust($color, $lightness: -10%)`. Name is without the `$`
+    NamedArgument(String, Box<CSSValue>),
+    /// Unquoted SCSS interpolated word e.g. `icon-#{$name}`. Evaluated to a keyword during
+    /// compilation
+    Interpolation(Interpolation),
+    /// Quoted string containing SCSS interpolation e.g. `"#{$dir}/logo.svg"`. Evaluated to a
+    /// string literal during compilation
+    InterpolatedString(Interpolation),
+    /// SCSS variable reference e.g. `$primary`. Replaced by its value during compilation
+    Variable {
+        /// Module namespace e.g. `cfg` in `cfg.$primary`
+        namespace: Option<String>,
+        name: String,
+        position: Span,
+    },
+    /// SCSS operation e.g. `$a == 1` or `$gap * 2`. Evaluated during compilation
+    BinaryOperation {
+        lhs: Box<CSSValue>,
+        operator: BinaryOperator,
+        rhs: Box<CSSValue>,
+        /// Position of the operator
+        position: Span,
+    },
+    /// SCSS operation e.g. `not $a` or `-$gap`. Evaluated during compilation
+    UnaryOperation {
+        operator: UnaryOperator,
+        operand: Box<CSSValue>,
+        /// Position of the operator
+        position: Span,
+    },
+    /// Operation in brackets e.g. `(10px / 2)`, in which `/` always divides
+    Parenthesized(Box<CSSValue>),
+    /// SCSS `null`, the result of evaluating the `null` keyword. Left out of lists and
+    /// declarations during compilation
+    Null,
+}
+
+/// Operators in SCSS expressions
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOperator {
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    LessThan,
+    /// `<=`
+    LessThanEqual,
+    /// `>`
+    GreaterThan,
+    /// `>=`
+    GreaterThanEqual,
+    /// `and`
+    And,
+    /// `or`
+    Or,
+    /// `+`
+    Add,
+    /// `-`
+    Subtract,
+    /// `*`
+    Multiply,
+    /// `/`. Outside of SCSS, and between literal values, it separates values e.g. `12px/1.5`
+    Divide,
+    /// `%`
+    Modulo,
+}
+
+impl BinaryOperator {
+    fn from_token(token: &CSSToken) -> Option<Self> {
+        match token {
+            CSSToken::DoubleEqual => Some(BinaryOperator::Equal),
+            CSSToken::NotEqual => Some(BinaryOperator::NotEqual),
+            CSSToken::OpenAngle => Some(BinaryOperator::LessThan),
+            CSSToken::LessThanEqual => Some(BinaryOperator::LessThanEqual),
+            CSSToken::CloseAngle => Some(BinaryOperator::GreaterThan),
+            CSSToken::GreaterThanEqual => Some(BinaryOperator::GreaterThanEqual),
+            CSSToken::Ident(ident) if ident == "and" => Some(BinaryOperator::And),
+            CSSToken::Ident(ident) if ident == "or" => Some(BinaryOperator::Or),
+            CSSToken::Plus => Some(BinaryOperator::Add),
+            CSSToken::Minus => Some(BinaryOperator::Subtract),
+            CSSToken::Asterisk => Some(BinaryOperator::Multiply),
+            CSSToken::Slash => Some(BinaryOperator::Divide),
+            CSSToken::Percentage => Some(BinaryOperator::Modulo),
+            _ => None,
+        }
+    }
+
+    /// `+`, `-`, `*`, `/` and `%`, which are also in CSS math functions e.g. `calc(100% - 1px)`
+    fn is_arithmetic(self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Add
+                | BinaryOperator::Subtract
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+                | BinaryOperator::Modulo
+        )
+    }
+
+    /// Higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Or => 0,
+            BinaryOperator::And => 1,
+            BinaryOperator::Equal | BinaryOperator::NotEqual => 2,
+            BinaryOperator::LessThan
+            | BinaryOperator::LessThanEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanEqual => 3,
+            BinaryOperator::Add | BinaryOperator::Subtract => 4,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 5,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::LessThanEqual => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::GreaterThanEqual => ">=",
+            BinaryOperator::And => "and",
+            BinaryOperator::Or => "or",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOperator {
+    /// `not`
+    Not,
+    /// `-`
+    Negate,
+}
+
+impl UnaryOperator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnaryOperator::Not => "not",
+            UnaryOperator::Negate => "-",
+        }
+    }
 }
 
 impl ASTNode for CSSValue {
     fn from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
-        let value = Self::single_value_from_reader(reader)?;
-        macro_rules! css_value_has_ended {
-            () => {
-                matches!(
-                    reader.peek().unwrap().0,
-                    CSSToken::EOS | CSSToken::SemiColon | CSSToken::CloseCurly
-                )
-            };
-        }
-        if !css_value_has_ended!() {
-            let mut values: Vec<CSSValue> = vec![value];
-            while !css_value_has_ended!() {
-                values.push(Self::single_value_from_reader(reader)?);
-            }
-            Ok(CSSValue::List(values))
+        let mut groups = Self::groups_from_reader(reader, None)?;
+        if groups.is_empty() {
+            let Token(token, position) = reader.next().unwrap();
+            Err(ParseError {
+                reason: format!("Expected value, found {:?}", token),
+                position,
+            })
+        } else if groups.len() == 1 {
+            Ok(groups.pop().unwrap())
         } else {
-            Ok(value)
+            Ok(CSSValue::CommaSeparatedList(groups))
         }
     }
 
@@ -47,14 +203,15 @@ impl ASTNode for CSSValue {
         depth: u8,
     ) {
         match self {
-            Self::Keyword(keyword) => buf.push_str(&keyword),
+            Self::Keyword(keyword) => buf.push_str(keyword),
+            Self::Null => buf.push_str("null"),
             Self::Color(color) => {
                 buf.push('#');
-                buf.push_str(&color);
+                buf.push_str(color);
             }
             Self::StringLiteral(content) => {
                 buf.push('"');
-                buf.push_str(&content);
+                buf.push_str(content);
                 buf.push('"');
             }
             Self::Percentage(percent) => {
@@ -66,12 +223,12 @@ impl ASTNode for CSSValue {
             }
             Self::NumberWithUnit(value, unit) => {
                 buf.push_str(&value.0);
-                buf.push_str(&unit);
+                buf.push_str(unit);
             }
             Self::List(values) => {
                 for (idx, value) in values.iter().enumerate() {
                     value.to_string_from_buffer(buf, settings, depth);
-                    if idx + 1 < values.len() && !settings.minify {
+                    if idx + 1 < values.len() {
                         buf.push(' ');
                     }
                 }
@@ -87,8 +244,101 @@ impl ASTNode for CSSValue {
                     }
                 }
             }
+            Self::Bracketed(value) => {
+                buf.push('[');
+                value.to_string_from_buffer(buf, settings, depth);
+                buf.push(']');
+            }
+            Self::Parenthesized(value) => {
+                buf.push('(');
+                value.to_string_from_buffer(buf, settings, depth);
+                buf.push(')');
+            }
+            Self::Map(entries) => {
+                buf.push('(');
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    key.to_string_from_buffer(buf, settings, depth);
+                    buf.push(':');
+                    if !settings.minify {
+                        buf.push(' ');
+                    }
+                    value.to_string_from_buffer(buf, settings, depth);
+                    if idx + 1 < entries.len() {
+                        buf.push(',');
+                        if !settings.minify {
+                            buf.push(' ');
+                        }
+                    }
+                }
+                buf.push(')');
+            }
+            Self::NamedArgument(name, value) => {
+                buf.push('$');
+                buf.push_str(name);
+                buf.push(':');
+                if !settings.minify {
+                    buf.push(' ');
+                }
+                value.to_string_from_buffer(buf, settings, depth);
+            }
+            Self::Interpolation(interpolation) => {
+                interpolation.to_string_from_buffer(buf, settings, depth);
+            }
+            Self::InterpolatedString(interpolation) => {
+                buf.push('"');
+                interpolation.to_string_from_buffer(buf, settings, depth);
+                buf.push('"');
+            }
+            Self::Variable {
+                namespace, name, ..
+            } => {
+                if let Some(namespace) = namespace {
+                    buf.push_str(namespace);
+                    buf.push('.');
+                }
+                buf.push('$');
+                buf.push_str(name);
+            }
+            Self::BinaryOperation {
+                lhs, operator, rhs, ..
+            } => {
+                // Bracket operands which bind less tightly than the operator
+                let binds_less = |operand: &CSSValue, is_rhs: bool| {
+                    matches!(operand, CSSValue::BinaryOperation { operator: inner, .. }
+                        if inner.precedence() < operator.precedence()
+                            || (is_rhs && inner.precedence() == operator.precedence()))
+                };
+                // `calc` requires spaces around `+` and `-`. `/` separated values are not spaced
+                let spaced = match operator {
+                    BinaryOperator::And
+                    | BinaryOperator::Or
+                    | BinaryOperator::Add
+                    | BinaryOperator::Subtract => true,
+                    BinaryOperator::Divide => false,
+                    _ => !settings.minify,
+                };
+                lhs.operand_to_string_from_buffer(binds_less(lhs, false), buf, settings, depth);
+                if spaced {
+                    buf.push(' ');
+                }
+                buf.push_str(operator.as_str());
+                if spaced {
+                    buf.push(' ');
+                }
+                rhs.operand_to_string_from_buffer(binds_less(rhs, true), buf, settings, depth);
+            }
+            Self::UnaryOperation {
+                operator, operand, ..
+            } => {
+                buf.push_str(operator.as_str());
+                if let UnaryOperator::Not = operator {
+                    buf.push(' ');
+                }
+                let is_operation = matches!(**operand, CSSValue::BinaryOperation { .. });
+                operand.operand_to_string_from_buffer(is_operation, buf, settings, depth);
+            }
             Self::Function(func, arguments) => {
-                buf.push_str(&func);
+                buf.push_str(func);
                 buf.push('(');
                 for (idx, value) in arguments.iter().enumerate() {
                     value.to_string_from_buffer(buf, settings, depth);
@@ -110,19 +360,547 @@ impl ASTNode for CSSValue {
 }
 
 impl CSSValue {
-    fn single_value_from_reader(
+    /// Values do not hold positions, so this finds the position of the last token of the value
+    /// ahead of parsing it. Does not advance the reader
+    pub(crate) fn end_position_ahead(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Option<Span> {
+        let mut end_span = None;
+        let mut depth = 0u32;
+        let mut in_interpolation = false;
+        reader.scan(|token, position| {
+            match token {
+                CSSToken::InterpolationStart => in_interpolation = true,
+                CSSToken::CloseCurly if in_interpolation => in_interpolation = false,
+                CSSToken::SemiColon | CSSToken::CloseCurly => return true,
+                CSSToken::Exclamation if depth == 0 => return true,
+                CSSToken::EOS => return false,
+                CSSToken::OpenBracket | CSSToken::OpenSquareBracket => depth += 1,
+                CSSToken::CloseBracket | CSSToken::CloseSquareBracket => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+            end_span = Some(position.clone());
+            false
+        });
+        end_span
+    }
+
+    /// Parses comma separated groups of space separated values. If `enclosing` is `Some` parses
+    /// up to (but not including) the closing bracket of its opening e.g. `calc(`, `(` or `[`,
+    /// else up to the end of the declaration
+    fn groups_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        enclosing: Option<(&str, &Span)>,
+    ) -> Result<Vec<CSSValue>, ParseError> {
+        let mut groups: Vec<CSSValue> = Vec::new();
+        let mut group: Vec<CSSValue> = Vec::new();
+        let mut last_comma: Option<Span> = None;
+        // Only function calls have named arguments
+        let is_call = matches!(enclosing, Some((opening, _)) if opening.len() > 1);
+        loop {
+            if is_call && group.is_empty() && Self::is_named_argument_ahead(reader) {
+                let name = match reader.next().unwrap().0 {
+                    CSSToken::Variable(name) => name,
+                    _ => unreachable!(),
+                };
+                reader.next();
+                let value = Self::argument_from_reader(reader)?;
+                group.push(CSSValue::NamedArgument(name, Box::new(value)));
+                continue;
+            }
+            let Token(peek_type, peek_span) = reader.peek().unwrap();
+            match peek_type {
+                CSSToken::CloseBracket | CSSToken::CloseSquareBracket if enclosing.is_some() => {
+                    break
+                }
+                // Start of `!important`
+                CSSToken::Exclamation if enclosing.is_none() => break,
+                // Start of a SCSS block e.g. `@each $x in a, b {`
+                CSSToken::OpenCurly if enclosing.is_none() => break,
+                CSSToken::EOS | CSSToken::SemiColon | CSSToken::CloseCurly => {
+                    if let Some((opening, start_span)) = enclosing {
+                        let closing = if opening.ends_with('[') { ']' } else { ')' };
+                        return Err(ParseError {
+                            reason: format!(
+                                "Could not find closing '{}' for '{}'",
+                                closing, opening
+                            ),
+                            position: start_span.union(peek_span),
+                        });
+                    }
+                    break;
+                }
+                CSSToken::Comma => {
+                    let position = reader.next().unwrap().1;
+                    if group.is_empty() {
+                        return Err(ParseError {
+                            reason: "Expected value before ','".to_owned(),
+                            position,
+                        });
+                    }
+                    groups.push(Self::from_group(mem::take(&mut group)));
+                    last_comma = Some(position);
+                }
+                _ => group.push(Self::expression_from_reader(reader)?),
+            }
+        }
+        if !group.is_empty() {
+            groups.push(Self::from_group(group));
+        } else if let Some(position) = last_comma {
+            return Err(ParseError {
+                reason: "Expected value after ','".to_owned(),
+                position,
+            });
+        }
+        Ok(groups)
+    }
+
+    /// Parses space separated values up to a `,` or `)`. Used for SCSS arguments
+    pub(crate) fn argument_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<Self, ParseError> {
+        let mut group: Vec<CSSValue> = Vec::new();
+        loop {
+            match reader.peek().unwrap() {
+                Token(
+                    CSSToken::Comma
+                    | CSSToken::CloseBracket
+                    | CSSToken::SemiColon
+                    | CSSToken::OpenCurly
+                    | CSSToken::CloseCurly
+                    | CSSToken::Exclamation
+                    | CSSToken::EOS,
+                    position,
+                ) => {
+                    if group.is_empty() {
+                        return Err(ParseError {
+                            reason: "Expected value".to_owned(),
+                            position: position.clone(),
+                        });
+                    }
+                    return Ok(Self::from_group(group));
+                }
+                _ => group.push(Self::expression_from_reader(reader)?),
+            }
+        }
+    }
+
+    /// Parses a value with SCSS operators. Operands are single values, `not` operations or
+    /// values in brackets
+    pub(crate) fn expression_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<Self, ParseError> {
+        Self::binary_expression_from_reader(reader, 0)
+    }
+
+    /// Parses operations whose operators have at least `min_precedence`
+    fn binary_expression_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        min_precedence: u8,
+    ) -> Result<Self, ParseError> {
+        let mut lhs = Self::unary_expression_from_reader(reader)?;
+        loop {
+            let operator = match BinaryOperator::from_token(&reader.peek().unwrap().0) {
+                // `$a -$b` is a list of `$a` and `-$b`
+                Some(BinaryOperator::Subtract) if Self::is_negation_ahead(reader) => break,
+                Some(operator) if operator.precedence() >= min_precedence => operator,
+                _ => break,
+            };
+            let position = reader.next().unwrap().1;
+            let rhs = Self::binary_expression_from_reader(reader, operator.precedence() + 1)?;
+            lhs = CSSValue::BinaryOperation {
+                lhs: Box::new(lhs),
+                operator,
+                rhs: Box::new(rhs),
+                position,
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Whether the next token is a `-` directly followed by its operand e.g. `-$gap`
+    fn is_negation_ahead(reader: &mut impl TokenReader<CSSToken, Span>) -> bool {
+        let mut minus_position: Option<Span> = None;
+        let mut is_negation = false;
+        reader.scan(|_, position| match minus_position.take() {
+            Some(minus_position) => {
+                is_negation = minus_position.is_adjacent_to(position);
+                true
+            }
+            None => {
+                minus_position = Some(position.clone());
+                false
+            }
+        });
+        is_negation
+    }
+
+    fn unary_expression_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+    ) -> Result<Self, ParseError> {
+        if Interpolation::is_word_ahead(reader) {
+            return Ok(CSSValue::Interpolation(Interpolation::word_from_reader(
+                reader,
+            )?));
+        }
+        let operator = match reader.peek().unwrap() {
+            Token(CSSToken::Ident(ident), _) if ident == "not" => Some(UnaryOperator::Not),
+            Token(CSSToken::Minus, _) => Some(UnaryOperator::Negate),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            let position = reader.next().unwrap().1;
+            let operand = Self::unary_expression_from_reader(reader)?;
+            return Ok(CSSValue::UnaryOperation {
+                operator,
+                operand: Box::new(operand),
+                position,
+            });
+        }
+        match reader.peek().unwrap() {
+            Token(CSSToken::OpenBracket, _) => {
+                let start_span = reader.next().unwrap().1;
+                if Self::is_map_ahead(reader) {
+                    return Self::map_from_reader(reader);
+                }
+                // Brackets can contain a list e.g. `(a b, c)`
+                let mut groups = Self::groups_from_reader(reader, Some(("(", &start_span)))?;
+                reader.expect_next(CSSToken::CloseBracket)?;
+                if groups.len() == 1 {
+                    match groups.pop().unwrap() {
+                        value @ CSSValue::BinaryOperation { .. } => {
+                            Ok(CSSValue::Parenthesized(Box::new(value)))
+                        }
+                        value => Ok(value),
+                    }
+                } else {
+                    Ok(CSSValue::CommaSeparatedList(groups))
+                }
+            }
+            Token(CSSToken::OpenSquareBracket, _) => {
+                let start_span = reader.next().unwrap().1;
+                let mut groups = Self::groups_from_reader(reader, Some(("[", &start_span)))?;
+                reader.expect_next(CSSToken::CloseSquareBracket)?;
+                let list = if groups.len() == 1 {
+                    groups.pop().unwrap()
+                } else {
+                    CSSValue::CommaSeparatedList(groups)
+                };
+                Ok(CSSValue::Bracketed(Box::new(list)))
+            }
+            _ => Self::single_value_from_reader(reader),
+        }
+    }
+
+    /// Whether the next tokens are a variable and `:` e.g. `$alpha: 0.5` in a function call
+    fn is_named_argument_ahead(reader: &mut impl TokenReader<CSSToken, Span>) -> bool {
+        let mut count = 0;
+        let mut is_named = false;
+        reader.scan(|token, _| {
+            count += 1;
+            match (count, token) {
+                (1, CSSToken::Variable(_)) => false,
+                (2, CSSToken::Colon) => {
+                    is_named = true;
+                    true
+                }
+                _ => true,
+            }
+        });
+        is_named
+    }
+
+    /// After a `(`, whether a `:` comes before the first `,` or `)`
+    fn is_map_ahead(reader: &mut impl TokenReader<CSSToken, Span>) -> bool {
+        let mut depth = 0u32;
+        let mut is_map = false;
+        reader.scan(|token, _| match token {
+            CSSToken::OpenBracket | CSSToken::OpenSquareBracket => {
+                depth += 1;
+                false
+            }
+            CSSToken::CloseBracket | CSSToken::CloseSquareBracket if depth > 0 => {
+                depth -= 1;
+                false
+            }
+            CSSToken::Colon if depth == 0 => {
+                is_map = true;
+                true
+            }
+            CSSToken::Comma if depth == 0 => true,
+            CSSToken::CloseBracket
+            | CSSToken::CloseSquareBracket
+            | CSSToken::SemiColon
+            | CSSToken::OpenCurly
+            | CSSToken::CloseCurly => true,
+            _ => false,
+        });
+        is_map
+    }
+
+    /// Parses the `key: value` pairs of a map after the `(`, up to and including the `)`
+    fn map_from_reader(reader: &mut impl TokenReader<CSSToken, Span>) -> Result<Self, ParseError> {
+        let mut entries = Vec::new();
+        loop {
+            let key = Self::expression_from_reader(reader)?;
+            reader.expect_next(CSSToken::Colon)?;
+            let value = Self::argument_from_reader(reader)?;
+            entries.push((key, value));
+            if let Some(Token(CSSToken::Comma, _)) = reader.peek() {
+                reader.next();
+                // Trailing comma
+                if let Some(Token(CSSToken::CloseBracket, _)) = reader.peek() {
+                    reader.next();
+                    break;
+                }
+            } else {
+                reader.expect_next(CSSToken::CloseBracket)?;
+                break;
+            }
+        }
+        Ok(CSSValue::Map(entries))
+    }
+
+    fn operand_to_string_from_buffer(
+        &self,
+        in_brackets: bool,
+        buf: &mut impl ToString,
+        settings: &ToStringSettings,
+        depth: u8,
+    ) {
+        if in_brackets {
+            buf.push('(');
+        }
+        self.to_string_from_buffer(buf, settings, depth);
+        if in_brackets {
+            buf.push(')');
+        }
+    }
+
+    /// `false` and `null` are falsy, all other values are truthy
+    pub(crate) fn is_truthy(&self) -> bool {
+        !matches!(self, CSSValue::Null)
+            && !matches!(self, CSSValue::Keyword(keyword) if keyword == "false")
+    }
+
+    pub(crate) fn from_bool(value: bool) -> Self {
+        CSSValue::Keyword(if value { "true" } else { "false" }.to_owned())
+    }
+
+    /// The number and unit (empty if unitless) of numeric values
+    pub(crate) fn as_number(&self) -> Option<(f64, &str)> {
+        match self {
+            CSSValue::Number(number) => Some((number.as_f64()?, "")),
+            CSSValue::NumberWithUnit(number, unit) => Some((number.as_f64()?, unit)),
+            CSSValue::Percentage(number) => Some((number.as_f64()?, "%")),
+            _ => None,
+        }
+    }
+
+    /// Numeric value from a number and unit (empty if unitless). Rounded to 10 decimal places
+    pub(crate) fn from_number(value: f64, unit: &str) -> Self {
+        let rounded = (value * 1e10).round() / 1e10;
+        // Avoids printing `-0`
+        let number = Number(format!("{}", if rounded == 0.0 { 0.0 } else { rounded }));
+        match unit {
+            "" => CSSValue::Number(number),
+            "%" => CSSValue::Percentage(number),
+            unit => CSSValue::NumberWithUnit(number, unit.to_owned()),
+        }
+    }
+
+    /// SCSS equality. Quoted and unquoted strings with the same content are equal and numbers
+    /// are compared by value
+    pub(crate) fn scss_equals(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                CSSValue::Keyword(a) | CSSValue::StringLiteral(a),
+                CSSValue::Keyword(b) | CSSValue::StringLiteral(b),
+            ) => a == b,
+            (CSSValue::Color(a), CSSValue::Color(b)) => a.eq_ignore_ascii_case(b),
+            (CSSValue::List(a), CSSValue::List(b))
+            | (CSSValue::CommaSeparatedList(a), CSSValue::CommaSeparatedList(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.scss_equals(b))
+            }
+            (CSSValue::Bracketed(a), CSSValue::Bracketed(b)) => a.scss_equals(b),
+            // Maps are equal regardless of order
+            (CSSValue::Map(a), CSSValue::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(key, value)| {
+                        b.iter().any(|(other_key, other_value)| {
+                            key.scss_equals(other_key) && value.scss_equals(other_value)
+                        })
+                    })
+            }
+            (a, b) => match (a.as_number(), b.as_number()) {
+                (Some(a), Some(b)) => a == b,
+                _ => a == b,
+            },
+        }
+    }
+
+    /// Comparisons, `and`, `or` and `not` are only operators in SCSS. In CSS this replaces them
+    /// with the space separated values they were parsed from e.g. `a or b` is three keywords
+    pub(crate) fn into_plain_css(self) -> Self {
+        match self {
+            CSSValue::BinaryOperation { operator, .. } if !operator.is_arithmetic() => {
+                let mut items = Vec::new();
+                self.push_plain_css_items(&mut items);
+                CSSValue::List(items)
+            }
+            CSSValue::UnaryOperation {
+                operator: UnaryOperator::Not,
+                ..
+            } => {
+                let mut items = Vec::new();
+                self.push_plain_css_items(&mut items);
+                CSSValue::List(items)
+            }
+            CSSValue::BinaryOperation {
+                lhs,
+                operator,
+                rhs,
+                position,
+            } => CSSValue::BinaryOperation {
+                lhs: Box::new(lhs.into_plain_css()),
+                operator,
+                rhs: Box::new(rhs.into_plain_css()),
+                position,
+            },
+            CSSValue::UnaryOperation {
+                operator,
+                operand,
+                position,
+            } => CSSValue::UnaryOperation {
+                operator,
+                operand: Box::new(operand.into_plain_css()),
+                position,
+            },
+            CSSValue::List(values) => {
+                let mut items = Vec::new();
+                for value in values {
+                    value.push_plain_css_items(&mut items);
+                }
+                CSSValue::List(items)
+            }
+            CSSValue::CommaSeparatedList(values) => {
+                CSSValue::CommaSeparatedList(values.into_iter().map(Self::into_plain_css).collect())
+            }
+            CSSValue::Function(name, arguments) => CSSValue::Function(
+                name,
+                arguments.into_iter().map(Self::into_plain_css).collect(),
+            ),
+            CSSValue::Bracketed(value) => CSSValue::Bracketed(Box::new(value.into_plain_css())),
+            CSSValue::Parenthesized(value) => {
+                CSSValue::Parenthesized(Box::new(value.into_plain_css()))
+            }
+            value => value,
+        }
+    }
+
+    /// Adds the items of [`CSSValue::into_plain_css`] to a space separated list
+    fn push_plain_css_items(self, items: &mut Vec<CSSValue>) {
+        match self {
+            CSSValue::BinaryOperation {
+                lhs, operator, rhs, ..
+            } if !operator.is_arithmetic() => {
+                lhs.push_plain_css_items(items);
+                items.push(CSSValue::Keyword(operator.as_str().to_owned()));
+                rhs.push_plain_css_items(items);
+            }
+            CSSValue::UnaryOperation {
+                operator: UnaryOperator::Not,
+                operand,
+                ..
+            } => {
+                items.push(CSSValue::Keyword(UnaryOperator::Not.as_str().to_owned()));
+                operand.push_plain_css_items(items);
+            }
+            value => items.push(value.into_plain_css()),
+        }
+    }
+
+    /// Single value if only one else space separated list
+    fn from_group(mut group: Vec<CSSValue>) -> Self {
+        if group.len() == 1 {
+            group.pop().unwrap()
+        } else {
+            CSSValue::List(group)
+        }
+    }
+
+    /// If the next tokens are `.` and an identifier or variable directly after `start_span`,
+    /// returns the position of the identifier or variable
+    pub(crate) fn member_ahead(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        start_span: &Span,
+    ) -> Option<Span> {
+        let mut last_span = start_span.clone();
+        let mut member_span = None;
+        let mut count = 0;
+        reader.scan(|token, position| {
+            count += 1;
+            if !last_span.is_adjacent_to(position) {
+                return true;
+            }
+            last_span = position.clone();
+            match (count, token) {
+                (1, CSSToken::Dot) => false,
+                (2, CSSToken::Ident(_) | CSSToken::Variable(_)) => {
+                    member_span = Some(position.clone());
+                    true
+                }
+                _ => true,
+            }
+        });
+        member_span
+    }
+
+    /// Parses a function call if the identifier is followed by `(`, else a keyword
+    fn ident_value_from_reader(
+        reader: &mut impl TokenReader<CSSToken, Span>,
+        ident: String,
+        start_span: Span,
+    ) -> Result<Self, ParseError> {
+        let Token(peek_type, peek_span) = reader.peek().unwrap();
+        if *peek_type == CSSToken::OpenBracket && start_span.is_adjacent_to(peek_span) {
+            reader.next();
+            let opening = format!("{}(", ident);
+            let arguments = Self::groups_from_reader(reader, Some((&opening, &start_span)))?;
+            reader.expect_next(CSSToken::CloseBracket)?;
+            Ok(CSSValue::Function(ident, arguments))
+        } else {
+            Ok(CSSValue::Keyword(ident))
+        }
+    }
+
+    pub(crate) fn single_value_from_reader(
         reader: &mut impl TokenReader<CSSToken, Span>,
     ) -> Result<Self, ParseError> {
         match reader.next().unwrap() {
-            Token(CSSToken::Ident(ident), start_span) => {
-                let Token(peek_type, peek_span) = reader.peek().unwrap();
-                if *peek_type == CSSToken::OpenBracket && start_span.is_adjacent_to(peek_span) {
+            Token(CSSToken::Ident(mut ident), mut start_span) => {
+                // Module member e.g. `math.div` or `cfg.$primary`
+                if let Some(member_span) = Self::member_ahead(reader, &start_span) {
                     reader.next();
-                    reader.expect_next(CSSToken::CloseBracket)?;
-                    todo!("Functions")
-                } else {
-                    Ok(CSSValue::Keyword(ident))
+                    match reader.next().unwrap().0 {
+                        CSSToken::Variable(name) => {
+                            return Ok(CSSValue::Variable {
+                                namespace: Some(ident),
+                                name,
+                                position: start_span.union(&member_span),
+                            });
+                        }
+                        CSSToken::Ident(member) => {
+                            ident.push('.');
+                            ident.push_str(&member);
+                        }
+                        _ => unreachable!(),
+                    }
+                    start_span = start_span.union(&member_span);
                 }
+                Self::ident_value_from_reader(reader, ident, start_span)
             }
             Token(CSSToken::HashPrefixedValue(color), _) => Ok(CSSValue::Color(color)),
             Token(CSSToken::Number(number), start_position) => {
@@ -150,7 +928,57 @@ impl CSSValue {
                     Ok(CSSValue::Number(number))
                 }
             }
-            Token(CSSToken::String(string), _) => Ok(CSSValue::StringLiteral(string)),
+            Token(CSSToken::String(string), position) => {
+                if string.contains("#{") {
+                    Ok(CSSValue::InterpolatedString(
+                        Interpolation::from_string_content(&string, &position)?,
+                    ))
+                } else {
+                    Ok(CSSValue::StringLiteral(string))
+                }
+            }
+            // Less escape e.g. `~"calc(100% - 10px)"`, which is printed without the quotes
+            Token(CSSToken::Tilde, position) => match reader.next().unwrap() {
+                Token(CSSToken::String(content), string_position)
+                    if position.is_adjacent_to(&string_position) =>
+                {
+                    if content.contains("#{") {
+                        Ok(CSSValue::Interpolation(Interpolation::from_string_content(
+                            &content,
+                            &string_position,
+                        )?))
+                    } else {
+                        Ok(CSSValue::Keyword(content))
+                    }
+                }
+                Token(token, position) => Err(ParseError {
+                    reason: format!("Expected string after '~' found '{}'", token),
+                    position,
+                }),
+            },
+            Token(CSSToken::Variable(name), position) => Ok(CSSValue::Variable {
+                namespace: None,
+                name,
+                position,
+            }),
+            // SCSS interpolation e.g. `url(#{$base}/a.png)`, the token is at the position of the
+            // content
+            Token(CSSToken::Url(url), position) if url.contains("#{") => Ok(CSSValue::Function(
+                "url".to_owned(),
+                vec![CSSValue::Interpolation(Interpolation::from_text(
+                    &url,
+                    position.start,
+                    &position,
+                )?)],
+            )),
+            Token(CSSToken::Url(url), _) => Ok(CSSValue::Function(
+                "url".to_owned(),
+                vec![CSSValue::Keyword(url)],
+            )),
+            Token(CSSToken::CloseBracket, position) => Err(ParseError {
+                reason: "Found ')' without matching '('".to_owned(),
+                position,
+            }),
             Token(token, position) => Err(ParseError {
                 reason: format!("Expected value, found {:?}", token),
                 position,
@@ -161,7 +989,7 @@ impl CSSValue {
 
 #[cfg(test)]
 mod css_values_test {
-    use super::{ASTNode, CSSValue, Number};
+    use super::{ASTNode, CSSValue, Number, ToStringSettings};
     use source_map::SourceId;
 
     const NULL_SOURCE_ID: SourceId = SourceId::null();
@@ -205,4 +1033,168 @@ mod css_values_test {
             CSSValue::Color("00ff00".to_owned())
         ])
     );
+    test_value!(
+        comma_separated_list,
+        "\"SF Pro Display\", sans-serif",
+        CSSValue::CommaSeparatedList(vec![
+            CSSValue::StringLiteral("SF Pro Display".to_owned()),
+            CSSValue::Keyword("sans-serif".to_owned())
+        ])
+    );
+    test_value!(
+        function,
+        "translate(10px, 20px)",
+        CSSValue::Function(
+            "translate".to_owned(),
+            vec![
+                CSSValue::NumberWithUnit(Number("10".to_owned()), "px".to_owned()),
+                CSSValue::NumberWithUnit(Number("20".to_owned()), "px".to_owned())
+            ]
+        )
+    );
+    test_value!(
+        function_space_separated_arguments,
+        "rgb(0 128 255)",
+        CSSValue::Function(
+            "rgb".to_owned(),
+            vec![CSSValue::List(vec![
+                CSSValue::Number(Number("0".to_owned())),
+                CSSValue::Number(Number("128".to_owned())),
+                CSSValue::Number(Number("255".to_owned()))
+            ])]
+        )
+    );
+    test_value!(
+        nested_function,
+        "var(--main-color, rgb(1, 2, 3))",
+        CSSValue::Function(
+            "var".to_owned(),
+            vec![
+                CSSValue::Keyword("--main-color".to_owned()),
+                CSSValue::Function(
+                    "rgb".to_owned(),
+                    vec![
+                        CSSValue::Number(Number("1".to_owned())),
+                        CSSValue::Number(Number("2".to_owned())),
+                        CSSValue::Number(Number("3".to_owned()))
+                    ]
+                )
+            ]
+        )
+    );
+    test_value!(
+        unquoted_url,
+        "url(https://example.com/image.png)",
+        CSSValue::Function(
+            "url".to_owned(),
+            vec![CSSValue::Keyword(
+                "https://example.com/image.png".to_owned()
+            )]
+        )
+    );
+    test_value!(
+        quoted_url,
+        "url(\"image.png\")",
+        CSSValue::Function(
+            "url".to_owned(),
+            vec![CSSValue::StringLiteral("image.png".to_owned())]
+        )
+    );
+
+    #[test]
+    fn unbalanced_brackets() {
+        let unclosed = CSSValue::from_string("rgb(1, 2".to_owned(), NULL_SOURCE_ID, None);
+        assert_eq!(unclosed.unwrap_err().position.start, 0);
+        let unopened = CSSValue::from_string("rgb(1, 2))".to_owned(), NULL_SOURCE_ID, None);
+        assert!(unopened.is_err());
+    }
+
+    #[test]
+    fn function_to_string() {
+        let value =
+            CSSValue::from_string("rgb(0 0 0), f(a, b)".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert_eq!(
+            value.to_string(&ToStringSettings::default()),
+            "rgb(0 0 0), f(a, b)"
+        );
+        assert_eq!(
+            value.to_string(&ToStringSettings::minified()),
+            "rgb(0 0 0),f(a,b)"
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let value = CSSValue::from_string(
+            "$a == 1 and not ($b or $c) or (a or b) and c".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            value,
+            CSSValue::BinaryOperation {
+                operator: super::BinaryOperator::Or,
+                ..
+            }
+        ));
+        assert_eq!(
+            value.to_string(&ToStringSettings::default()),
+            "$a == 1 and not ($b or $c) or (a or b) and c"
+        );
+        assert_eq!(
+            value.to_string(&ToStringSettings::minified()),
+            "$a==1 and not ($b or $c) or (a or b) and c"
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let value = CSSValue::from_string(
+            "$a * (2 + $b) - -$c 12px/1.5 calc(100% - 2px) math.div(1, 2)".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(&value, CSSValue::List(items) if items.len() == 4));
+        assert_eq!(
+            value.to_string(&ToStringSettings::default()),
+            "$a * (2 + $b) - -$c 12px/1.5 calc(100% - 2px) math.div(1, 2)"
+        );
+        assert_eq!(
+            value.to_string(&ToStringSettings::minified()),
+            "$a*(2 + $b) - -$c 12px/1.5 calc(100% - 2px) math.div(1,2)"
+        );
+        // `-` directly before a value starts a new item
+        let value = CSSValue::from_string("$a -$b".to_owned(), NULL_SOURCE_ID, None).unwrap();
+        assert!(matches!(value, CSSValue::List(items) if items.len() == 2));
+    }
+
+    #[test]
+    fn maps_and_bracketed_lists() {
+        let value = CSSValue::from_string(
+            "(primary: red, sizes: (small: 1px 2px),)".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(&value, CSSValue::Map(entries) if entries.len() == 2));
+        assert_eq!(
+            value.to_string(&ToStringSettings::default()),
+            "(primary: red, sizes: (small: 1px 2px))"
+        );
+        let value = CSSValue::from_string(
+            "[full-start] minmax(1em, 1fr) [a, b]".to_owned(),
+            NULL_SOURCE_ID,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(&value, CSSValue::List(items) if items.len() == 3));
+        assert_eq!(
+            value.to_string(&ToStringSettings::minified()),
+            "[full-start] minmax(1em,1fr) [a,b]"
+        );
+        let error = CSSValue::from_string("[a b".to_owned(), NULL_SOURCE_ID, None).unwrap_err();
+        assert_eq!(error.reason, "Could not find closing ']' for '['");
+    }
 }